@@ -1,13 +1,16 @@
-use anyhow::{Context, Result};
+use crate::logging::{log_error, log_info, log_warn, redact};
+use anyhow::Result;
 use serde::{Deserialize, Serialize};
 use std::fs;
 use std::path::PathBuf;
 
-const GRAPH_API_BASE: &str = "https://graph.microsoft.com/v1.0";
-
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct ChatMember {
+    /// Id of the membership, not of the user
     pub id: Option<String>,
+    /// Azure AD id of the member
+    #[serde(rename = "userId", default)]
+    pub user_id: Option<String>,
     #[serde(rename = "displayName")]
     pub display_name: Option<String>,
     pub email: Option<String>,
@@ -21,21 +24,401 @@ pub struct Chat {
     pub chat_type: String,
     #[serde(rename = "lastUpdatedDateTime")]
     pub last_updated: Option<String>,
+    /// The signed-in user's read state for this chat
+    #[serde(default)]
+    pub viewpoint: Option<ChatViewpoint>,
+    /// Newest message, when the chat list was fetched with `$expand=lastMessagePreview`
+    #[serde(rename = "lastMessagePreview", default)]
+    pub last_message: Option<Message>,
     #[serde(skip)]
     pub members: Vec<ChatMember>,
     #[serde(skip)]
     pub cached_display_name: Option<String>,
+    /// One-line plain text of `last_message` for the chat list
+    #[serde(skip)]
+    pub last_message_preview: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct ChatViewpoint {
+    #[serde(rename = "lastMessageReadDateTime")]
+    pub last_message_read: Option<String>,
+}
+
+impl Chat {
+    /// Whether the chat changed after the user last read it
+    ///
+    /// Chats without read state (older tenants, chats never opened) count as read,
+    /// so the list isn't covered in markers when Graph has nothing to say.
+    pub fn is_unread(&self) -> bool {
+        let updated = self
+            .last_updated
+            .as_deref()
+            .and_then(crate::time::parse_teams_datetime);
+        match (updated, self.last_read_at()) {
+            (Some(updated), Some(read)) => updated > read,
+            _ => false,
+        }
+    }
+
+    /// Show `message` as the chat's latest activity in the list
+    pub fn set_last_message(&mut self, message: Message, current_user_name: Option<&str>) {
+        self.last_message_preview = message_preview(&message, current_user_name);
+        self.last_message = Some(message);
+    }
+
+    /// When the user last read the chat, if Graph reports it
+    pub fn last_read_at(&self) -> Option<chrono::DateTime<chrono::FixedOffset>> {
+        self.viewpoint
+            .as_ref()
+            .and_then(|v| v.last_message_read.as_deref())
+            .and_then(crate::time::parse_teams_datetime)
+    }
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct Message {
     pub id: String,
+    /// Graph's `messageType`: "message" for user posts, "systemEventMessage" for
+    /// membership changes, renames and other control events
+    #[serde(rename = "messageType", default)]
+    pub message_type: Option<String>,
     #[serde(rename = "createdDateTime")]
     pub created_date_time: String,
+    /// Last change of any kind, reactions included
+    #[serde(rename = "lastModifiedDateTime", default)]
+    pub last_modified_date_time: Option<String>,
+    /// Last time the sender edited the text
+    #[serde(rename = "lastEditedDateTime", default)]
+    pub last_edited_date_time: Option<String>,
+    /// Set once the message has been deleted; the body is then empty
+    #[serde(rename = "deletedDateTime", default)]
+    pub deleted_date_time: Option<String>,
+    /// Present when a data loss prevention policy blocked or hid the message
+    #[serde(rename = "policyViolation", default)]
+    pub policy_violation: Option<serde_json::Value>,
     pub from: Option<MessageFrom>,
     pub body: Option<MessageBody>,
     #[serde(default)]
     pub attachments: Vec<MessageAttachment>,
+    #[serde(default)]
+    pub reactions: Vec<MessageReaction>,
+    /// People and conversations `<at id="N">` tags in the body refer to
+    #[serde(rename = "mentions", default)]
+    pub mentioned: Vec<MessageMention>,
+    /// Graph's `importance`: "normal", "high" or "urgent"
+    #[serde(default)]
+    pub importance: Option<String>,
+    /// What a system event did (members added, chat renamed, ...); the
+    /// `@odata.type` names the kind of event
+    #[serde(rename = "eventDetail", default)]
+    pub event_detail: Option<serde_json::Value>,
+    /// Title of a channel post; chat messages don't have one
+    #[serde(default)]
+    pub subject: Option<String>,
+    /// Delivery state for messages sent from this client and not yet confirmed by the server
+    #[serde(skip)]
+    pub send_state: Option<SendState>,
+}
+
+impl Message {
+    /// Whether this is a system/control event rather than something a person wrote
+    pub fn is_system(&self) -> bool {
+        self.message_type.as_deref().is_some_and(|t| t != "message")
+    }
+
+    /// A readable line for a system event, e.g. "Alice added Bob", from its `eventDetail`
+    ///
+    /// `None` for ordinary messages and for events without a known detail type.
+    pub fn system_event_text(&self) -> Option<String> {
+        let detail = self.event_detail.as_ref()?;
+        let kind = detail.get("@odata.type")?.as_str()?;
+        let kind = kind.trim_start_matches("#microsoft.graph.");
+        let initiator = detail
+            .pointer("/initiator/user/displayName")
+            .or_else(|| detail.pointer("/initiator/application/displayName"))
+            .and_then(|name| name.as_str())
+            .unwrap_or("Someone");
+        // Graph often leaves member names out; count them instead
+        let members = || {
+            let members = detail.get("members").and_then(|m| m.as_array());
+            let names: Vec<&str> = members
+                .into_iter()
+                .flatten()
+                .filter_map(|m| m.get("displayName")?.as_str())
+                .filter(|name| !name.trim().is_empty())
+                .collect();
+            let count = members.map_or(0, Vec::len);
+            match count {
+                _ if !names.is_empty() && names.len() == count => names.join(", "),
+                1 => "1 person".to_string(),
+                count => format!("{} people", count),
+            }
+        };
+        let text = match kind {
+            "membersAddedEventMessageDetail" => format!("{} added {}", initiator, members()),
+            "membersDeletedEventMessageDetail" => format!("{} removed {}", initiator, members()),
+            "membersJoinedEventMessageDetail" => format!("{} joined", members()),
+            "membersLeftEventMessageDetail" => format!("{} left", members()),
+            "chatRenamedEventMessageDetail" => match detail
+                .get("chatDisplayName")
+                .and_then(|name| name.as_str())
+                .filter(|name| !name.trim().is_empty())
+            {
+                Some(name) => format!("{} renamed the chat to '{}'", initiator, name),
+                None => format!("{} removed the chat name", initiator),
+            },
+            "callStartedEventMessageDetail" => format!("{} started a call", initiator),
+            "callEndedEventMessageDetail" => "Call ended".to_string(),
+            "messagePinnedEventMessageDetail" => format!("{} pinned a message", initiator),
+            "messageUnpinnedEventMessageDetail" => format!("{} unpinned a message", initiator),
+            _ => return None,
+        };
+        Some(text)
+    }
+
+    /// Whether the message was deleted after it was sent
+    pub fn is_deleted(&self) -> bool {
+        self.deleted_date_time
+            .as_deref()
+            .is_some_and(|ts| !ts.trim().is_empty())
+    }
+
+    /// Whether a data loss prevention policy hid the message
+    pub fn is_policy_hidden(&self) -> bool {
+        self.policy_violation
+            .as_ref()
+            .is_some_and(|violation| !violation.is_null())
+    }
+
+    /// Whether another copy of this message shows the same thing: nothing was
+    /// edited, deleted or reacted to in between
+    pub fn same_revision(&self, other: &Message) -> bool {
+        let reactions = |m: &Message| -> Vec<(String, Option<String>)> {
+            m.reactions
+                .iter()
+                .map(|r| (r.reaction_type.clone(), r.user_id().map(str::to_string)))
+                .collect()
+        };
+        self.id == other.id
+            && self.last_modified_date_time == other.last_modified_date_time
+            && self.last_edited_date_time == other.last_edited_date_time
+            && self.deleted_date_time == other.deleted_date_time
+            && reactions(self) == reactions(other)
+    }
+
+    /// Whether the sender edited the message after sending it
+    ///
+    /// `lastModifiedDateTime` also moves when someone reacts, so it is only
+    /// consulted for messages without `lastEditedDateTime` and without reactions.
+    /// Timestamps that don't parse count as unedited.
+    pub fn is_edited(&self) -> bool {
+        if self.is_deleted() {
+            return false;
+        }
+        let edited = self.last_edited_date_time.as_deref().or_else(|| {
+            self.reactions
+                .is_empty()
+                .then_some(self.last_modified_date_time.as_deref())
+                .flatten()
+        });
+        let edited = edited.and_then(crate::time::parse_teams_datetime);
+        let created = crate::time::parse_teams_datetime(&self.created_date_time);
+        matches!((edited, created), (Some(edited), Some(created)) if edited > created)
+    }
+
+    /// Whether the message @-mentions me, by user id where Graph resolved the
+    /// mention, else by the name in an `<at>` tag
+    pub fn mentions_me(&self, user_id: Option<&str>, user_name: Option<&str>) -> bool {
+        let by_id = user_id.is_some_and(|id| {
+            self.mentioned
+                .iter()
+                .filter_map(|m| m.mentioned.as_ref()?.user.as_ref()?.id.as_deref())
+                .any(|mentioned| mentioned == id)
+        });
+        by_id || user_name.is_some_and(|name| self.mentions(name))
+    }
+
+    /// Whether an `<at>` mention in the body names `user_name`
+    ///
+    /// Teams often mentions people by first name only, so that matches too.
+    pub fn mentions(&self, user_name: &str) -> bool {
+        let Some(content) = self.body.as_ref().and_then(|b| b.content.as_deref()) else {
+            return false;
+        };
+        let first_name = user_name.split_whitespace().next().unwrap_or(user_name);
+        let mut rest = content;
+        while let Some(start) = rest.find("<at") {
+            rest = &rest[start..];
+            let Some(open_end) = rest.find('>') else {
+                break;
+            };
+            let Some(close) = rest.find("</at>") else {
+                break;
+            };
+            if open_end < close {
+                let mentioned = crate::html::html_to_plain(&rest[open_end + 1..close]);
+                if mentioned.eq_ignore_ascii_case(user_name)
+                    || mentioned.eq_ignore_ascii_case(first_name)
+                {
+                    return true;
+                }
+            }
+            rest = &rest[close + "</at>".len()..];
+        }
+        false
+    }
+
+    /// Whether this server message is the copy of a send of `text` by `sender` at `sent_at`
+    ///
+    /// Messages created shortly before the send time still match, to allow for clock skew.
+    pub fn is_echo_of(
+        &self,
+        sender: Option<&str>,
+        text: &str,
+        sent_at: Option<chrono::DateTime<chrono::FixedOffset>>,
+    ) -> bool {
+        let from = self
+            .from
+            .as_ref()
+            .and_then(|f| f.user.as_ref())
+            .and_then(|u| u.display_name.as_deref());
+        if from != sender {
+            return false;
+        }
+        let recent = match (
+            sent_at,
+            crate::time::parse_teams_datetime(&self.created_date_time),
+        ) {
+            (Some(sent), Some(created)) => created >= sent - chrono::Duration::seconds(60),
+            _ => true,
+        };
+        let content = self
+            .body
+            .as_ref()
+            .and_then(|b| b.content.as_deref())
+            .map(crate::html::html_to_plain)
+            .unwrap_or_default();
+        recent && content == text
+    }
+
+    /// Whether `user_id` has already reacted with `reaction_type`
+    pub fn has_reaction(&self, reaction_type: &str, user_id: Option<&str>) -> bool {
+        user_id.is_some_and(|id| {
+            self.reactions
+                .iter()
+                .any(|r| r.reaction_type == reaction_type && r.user_id() == Some(id))
+        })
+    }
+
+    /// How urgently the sender flagged the message
+    pub fn importance(&self) -> Importance {
+        Importance::from_graph(self.importance.as_deref())
+    }
+
+    /// Reaction emoji with their counts, the standard Teams reactions first
+    pub fn reaction_summary(&self) -> Vec<(&str, usize)> {
+        let mut summary: Vec<(&str, usize)> = Vec::new();
+        let standard = REACTIONS.iter().map(|(kind, _)| *kind);
+        let others = self
+            .reactions
+            .iter()
+            .map(|r| r.reaction_type.as_str())
+            .filter(|kind| !REACTIONS.iter().any(|(k, _)| k == kind));
+        for kind in standard.chain(others) {
+            if summary
+                .iter()
+                .any(|(emoji, _)| *emoji == reaction_emoji(kind))
+            {
+                continue;
+            }
+            let count = self
+                .reactions
+                .iter()
+                .filter(|r| r.reaction_type == kind)
+                .count();
+            if count > 0 {
+                summary.push((reaction_emoji(kind), count));
+            }
+        }
+        summary
+    }
+}
+
+/// The reactions Teams offers on every message: Graph `reactionType` and its emoji
+pub const REACTIONS: [(&str, &str); 6] = [
+    ("like", "👍"),
+    ("heart", "❤️"),
+    ("laugh", "😆"),
+    ("surprised", "😮"),
+    ("sad", "😢"),
+    ("angry", "😡"),
+];
+
+/// Emoji for a `reactionType`; newer clients send the emoji itself, which is kept as is
+pub fn reaction_emoji(reaction_type: &str) -> &str {
+    REACTIONS
+        .iter()
+        .find(|(kind, _)| *kind == reaction_type)
+        .map_or(reaction_type, |(_, emoji)| emoji)
+}
+
+/// One person's reaction to a message
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct MessageReaction {
+    #[serde(rename = "reactionType")]
+    pub reaction_type: String,
+    pub user: Option<MessageFrom>,
+}
+
+impl MessageReaction {
+    pub fn user_id(&self) -> Option<&str> {
+        self.user
+            .as_ref()
+            .and_then(|f| f.user.as_ref())
+            .and_then(|u| u.id.as_deref())
+    }
+}
+
+/// One line of plain text summarizing a message, e.g. "You: see you at 3"
+///
+/// System events have no useful text and give `None`.
+pub fn message_preview(message: &Message, current_user_name: Option<&str>) -> Option<String> {
+    if message.is_system() {
+        return None;
+    }
+    if message.is_deleted() {
+        return Some("(message deleted)".to_string());
+    }
+    let text = message
+        .body
+        .as_ref()
+        .and_then(|b| b.content.as_deref())
+        .map(crate::html::html_to_plain)
+        .unwrap_or_default();
+    let text = text.split_whitespace().collect::<Vec<_>>().join(" ");
+    let text = match (text.is_empty(), message.attachments.is_empty()) {
+        (false, _) => text,
+        (true, false) => "📎 Attachment".to_string(),
+        (true, true) => return None,
+    };
+    let sender = message
+        .from
+        .as_ref()
+        .and_then(|f| f.user.as_ref())
+        .and_then(|u| u.display_name.as_deref());
+    Some(match sender {
+        Some(name) if Some(name) == current_user_name => format!("You: {}", text),
+        _ => text,
+    })
+}
+
+/// Delivery state of a locally echoed outgoing message
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SendState {
+    Sending,
+    Sent,
+    Failed,
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
@@ -45,10 +428,22 @@ pub struct MessageFrom {
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct MessageUser {
+    #[serde(default)]
+    pub id: Option<String>,
     #[serde(rename = "displayName")]
     pub display_name: Option<String>,
 }
 
+/// One entry of a message's `mentions`
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct MessageMention {
+    /// Matches the `id` of the `<at>` tag in the body
+    pub id: Option<i64>,
+    #[serde(rename = "mentionText")]
+    pub mention_text: Option<String>,
+    pub mentioned: Option<MessageFrom>,
+}
+
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct MessageBody {
     pub content: Option<String>,
@@ -80,25 +475,26 @@ impl MessageAttachment {
     /// Microsoft Teams uses "reference" content type for file attachments (including images)
     /// that are stored in SharePoint/OneDrive. We check the file extension for these
     /// reference-type attachments to determine if they are images.
-    pub fn is_image(&self) -> bool {
-        if let Some(content_type) = &self.content_type {
-            let ct_lower = content_type.to_lowercase();
-            // Direct image MIME types
-            ct_lower.starts_with("image/")
-                // Teams "reference" type attachments are files stored in SharePoint/OneDrive
-                // We check file extension to identify images
-                || ct_lower == "reference" && self.name.as_ref().map_or(false, |n| {
-                    let n_lower = n.to_lowercase();
-                    n_lower.ends_with(".png")
-                        || n_lower.ends_with(".jpg")
-                        || n_lower.ends_with(".jpeg")
-                        || n_lower.ends_with(".gif")
-                        || n_lower.ends_with(".webp")
-                        || n_lower.ends_with(".bmp")
-                })
-        } else {
-            false
+    ///
+    /// `extensions` are lowercase file extensions without the dot (see
+    /// `Config::image_extensions`).
+    pub fn is_image(&self, extensions: &[String]) -> bool {
+        let Some(content_type) = &self.content_type else {
+            return false;
+        };
+        let ct_lower = content_type.to_lowercase();
+        // Direct image MIME types
+        if ct_lower.starts_with("image/") {
+            return true;
         }
+        // Teams "reference" type attachments are files stored in SharePoint/OneDrive
+        // We check file extension to identify images
+        ct_lower == "reference"
+            && self
+                .name
+                .as_deref()
+                .and_then(|n| n.rsplit_once('.'))
+                .is_some_and(|(_, ext)| extensions.iter().any(|e| e.eq_ignore_ascii_case(ext)))
     }
 
     /// Get the URL to use for downloading/displaying the image
@@ -115,6 +511,15 @@ struct ChatsResponse {
     value: Vec<Chat>,
 }
 
+#[derive(Debug, Deserialize)]
+struct ChatsDeltaResponse {
+    value: Vec<serde_json::Value>,
+    #[serde(rename = "@odata.nextLink")]
+    next_link: Option<String>,
+    #[serde(rename = "@odata.deltaLink")]
+    delta_link: Option<String>,
+}
+
 #[derive(Debug, Deserialize)]
 struct MembersResponse {
     value: Vec<ChatMember>,
@@ -122,7 +527,19 @@ struct MembersResponse {
 
 #[derive(Debug, Deserialize)]
 struct MessagesResponse {
-    value: Vec<Message>,
+    /// Left as JSON so one message of an unexpected shape can be skipped
+    /// instead of failing the whole page
+    value: Vec<serde_json::Value>,
+    #[serde(rename = "@odata.nextLink")]
+    next_link: Option<String>,
+}
+
+/// One page of a chat's messages, newest first
+#[derive(Debug, Clone)]
+pub struct MessagePage {
+    pub messages: Vec<Message>,
+    /// URL of the next (older) page; `None` at the beginning of the conversation
+    pub next_link: Option<String>,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -135,10 +552,7 @@ pub struct User {
 }
 
 fn get_profile_path() -> Result<PathBuf> {
-    let config_dir = dirs::config_dir().context("Could not find config directory")?;
-    let app_dir = config_dir.join(crate::config::APP_DIR_NAME);
-    fs::create_dir_all(&app_dir)?;
-    Ok(app_dir.join("profile.json"))
+    Ok(crate::config::get_app_dir()?.join("profile.json"))
 }
 
 fn save_profile(user: &User) -> Result<()> {
@@ -158,25 +572,46 @@ fn load_profile() -> Result<Option<User>> {
     Ok(Some(user))
 }
 
-pub async fn get_me(access_token: &str) -> Result<User> {
+/// Graph service the API calls below are sent to
+///
+/// The app uses the one for the session's cloud; tests point one at a mock server.
+#[derive(Debug, Clone)]
+pub struct Graph {
+    /// v1.0 API root without a trailing slash, e.g. `https://graph.microsoft.com/v1.0`
+    base: String,
+}
+
+impl Graph {
+    pub fn new(base: impl Into<String>) -> Self {
+        Self { base: base.into() }
+    }
+
+    /// Graph of the cloud chosen at startup (see `cloud::init`)
+    pub fn for_session() -> Self {
+        Self::new(crate::cloud::graph_api_base())
+    }
+}
+
+pub async fn get_me(graph: &Graph, access_token: &str) -> Result<User> {
     // Try to load from cache first
     if let Ok(Some(user)) = load_profile() {
         return Ok(user);
     }
 
-    let client = reqwest::Client::new();
-    let url = format!("{}/me", GRAPH_API_BASE);
+    let client = crate::http::client();
+    let url = format!("{}/me", graph.base);
 
     let response = client
         .get(&url)
         .header("Authorization", format!("Bearer {}", access_token))
         .send()
-        .await?;
+        .await
+        .map_err(crate::http::request_error)?;
 
     if !response.status().is_success() {
         let status = response.status();
         let text = response.text().await?;
-        anyhow::bail!("Failed to get user profile: {} - {}", status, text);
+        anyhow::bail!("Failed to get user profile: {} - {}", status, redact(&text));
     }
 
     let user = response.json::<User>().await?;
@@ -202,15 +637,20 @@ fn abbreviate_name(full_name: &str) -> String {
     }
 }
 
-async fn get_chat_members(access_token: &str, chat_id: &str) -> Result<Vec<ChatMember>> {
-    let client = reqwest::Client::new();
-    let url = format!("{}/chats/{}/members", GRAPH_API_BASE, chat_id);
+async fn get_chat_members(
+    graph: &Graph,
+    access_token: &str,
+    chat_id: &str,
+) -> Result<Vec<ChatMember>> {
+    let client = crate::http::client();
+    let url = format!("{}/chats/{}/members", graph.base, chat_id);
 
     let response = client
         .get(&url)
         .header("Authorization", format!("Bearer {}", access_token))
         .send()
-        .await?;
+        .await
+        .map_err(crate::http::request_error)?;
 
     if !response.status().is_success() {
         // If we can't get members, return empty vec instead of failing
@@ -221,91 +661,637 @@ async fn get_chat_members(access_token: &str, chat_id: &str) -> Result<Vec<ChatM
     Ok(members_response.value)
 }
 
-pub async fn get_messages(access_token: &str, chat_id: &str) -> Result<Vec<Message>> {
-    let client = reqwest::Client::new();
-    let url = format!("{}/chats/{}/messages", GRAPH_API_BASE, chat_id);
+/// Most messages Graph returns per page of a chat
+pub const MAX_PAGE_SIZE: usize = 50;
+
+/// Fetch the newest page of a chat's messages, `page_size` of them at most
+pub async fn get_messages(
+    graph: &Graph,
+    access_token: &str,
+    chat_id: &str,
+    page_size: usize,
+) -> Result<MessagePage> {
+    let url = format!(
+        "{}/chats/{}/messages?$top={}",
+        graph.base,
+        chat_id,
+        page_size.clamp(1, MAX_PAGE_SIZE)
+    );
+    get_message_page(access_token, &url).await
+}
+
+/// Fetch an older page using the `@odata.nextLink` of the previous one, which
+/// keeps the page size of the first request
+pub async fn get_older_messages(access_token: &str, next_link: &str) -> Result<MessagePage> {
+    get_message_page(access_token, next_link).await
+}
+
+/// Graph answered 404 for a chat's messages: it was deleted or we were removed from it
+#[derive(Debug)]
+pub struct ChatUnavailable;
+
+impl std::fmt::Display for ChatUnavailable {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Chat no longer exists")
+    }
+}
+
+impl std::error::Error for ChatUnavailable {}
+
+/// Why a chat's messages couldn't be loaded
+#[derive(Debug, Clone)]
+pub enum MessagesError {
+    /// The chat is gone; retrying won't help
+    Unavailable,
+    Failed(String),
+}
+
+async fn get_message_page(access_token: &str, url: &str) -> Result<MessagePage> {
+    let client = crate::http::client();
 
     let response = client
-        .get(&url)
+        .get(url)
         .header("Authorization", format!("Bearer {}", access_token))
         .send()
-        .await?;
+        .await
+        .map_err(crate::http::request_error)?;
 
+    if response.status() == reqwest::StatusCode::NOT_FOUND {
+        return Err(ChatUnavailable.into());
+    }
     if !response.status().is_success() {
         let status = response.status();
         let text = response.text().await?;
-        anyhow::bail!("Failed to get messages: {} - {}", status, text);
+        anyhow::bail!("Failed to get messages: {} - {}", status, redact(&text));
     }
 
     let messages_response = response.json::<MessagesResponse>().await?;
-    Ok(messages_response.value)
+    Ok(MessagePage {
+        messages: parse_messages(messages_response.value),
+        next_link: messages_response.next_link,
+    })
+}
+
+/// Most unreadable message ids remembered so each is logged once
+const MAX_REPORTED_UNREADABLE: usize = 1000;
+
+/// Deserialize a page's messages one by one, skipping any that don't parse
+///
+/// Polls fetch the same page over and over, so each unreadable message is
+/// logged only the first time it turns up.
+fn parse_messages(values: Vec<serde_json::Value>) -> Vec<Message> {
+    static REPORTED: std::sync::Mutex<Option<std::collections::HashSet<String>>> =
+        std::sync::Mutex::new(None);
+
+    let total = values.len();
+    let mut new_failures = Vec::new();
+    let messages: Vec<Message> = values
+        .into_iter()
+        .filter_map(|value| {
+            let id = value
+                .get("id")
+                .and_then(|id| id.as_str())
+                .unwrap_or("(no id)")
+                .to_string();
+            match serde_json::from_value::<Message>(value) {
+                Ok(message) => Some(message),
+                Err(e) => {
+                    new_failures.push((id, e));
+                    None
+                }
+            }
+        })
+        .collect();
+    if new_failures.is_empty() {
+        return messages;
+    }
+
+    let skipped = new_failures.len();
+    if let Ok(mut reported) = REPORTED.lock() {
+        let reported = reported.get_or_insert_with(Default::default);
+        if reported.len() > MAX_REPORTED_UNREADABLE {
+            reported.clear();
+        }
+        new_failures.retain(|(id, _)| reported.insert(id.clone()));
+    }
+    if let Some((id, e)) = new_failures.first() {
+        log_warn!(
+            "Skipped {} of {} messages that could not be read (first new: {}: {})",
+            skipped,
+            total,
+            id,
+            e
+        );
+    }
+    messages
+}
+
+/// Importance a chat message is sent with
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Importance {
+    #[default]
+    Normal,
+    High,
+    Urgent,
+}
+
+impl Importance {
+    /// Parse Graph's `importance`, treating anything unknown as normal
+    pub fn from_graph(value: Option<&str>) -> Self {
+        match value.map(str::to_ascii_lowercase).as_deref() {
+            Some("high") => Importance::High,
+            Some("urgent") => Importance::Urgent,
+            _ => Importance::Normal,
+        }
+    }
+
+    pub fn as_graph(self) -> &'static str {
+        match self {
+            Importance::Normal => "normal",
+            Importance::High => "high",
+            Importance::Urgent => "urgent",
+        }
+    }
+
+    /// Cycle normal → high → urgent → normal
+    pub fn next(self) -> Self {
+        match self {
+            Importance::Normal => Importance::High,
+            Importance::High => Importance::Urgent,
+            Importance::Urgent => Importance::Normal,
+        }
+    }
+
+    /// Marker shown on flagged messages and in the compose title
+    pub fn label(self) -> Option<&'static str> {
+        match self {
+            Importance::Normal => None,
+            Importance::High => Some("IMPORTANT"),
+            Importance::Urgent => Some("URGENT"),
+        }
+    }
 }
 
 #[derive(Debug, Serialize)]
 struct SendMessageRequest {
     body: SendMessageBody,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    attachments: Vec<SendMessageAttachment>,
+    /// Left out for normal messages, which is Graph's default
+    #[serde(skip_serializing_if = "Option::is_none")]
+    importance: Option<&'static str>,
 }
 
 #[derive(Debug, Serialize)]
 struct SendMessageBody {
+    #[serde(rename = "contentType", skip_serializing_if = "Option::is_none")]
+    content_type: Option<&'static str>,
+    content: String,
+}
+
+/// A `messageReference` attachment quoting the message being replied to
+#[derive(Debug, Serialize)]
+struct SendMessageAttachment {
+    id: String,
+    #[serde(rename = "contentType")]
+    content_type: &'static str,
+    /// JSON-encoded reference, as Graph expects a string here
     content: String,
 }
 
-pub async fn send_message(access_token: &str, chat_id: &str, content: &str) -> Result<()> {
-    let client = reqwest::Client::new();
-    let url = format!("{}/chats/{}/messages", GRAPH_API_BASE, chat_id);
+/// The message a reply quotes
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ReplyTo {
+    pub message_id: String,
+    pub sender_id: Option<String>,
+    pub sender_name: String,
+    /// Plain-text preview of the quoted message
+    pub preview: String,
+}
+
+impl SendMessageRequest {
+    fn plain(content: &str) -> Self {
+        Self {
+            body: SendMessageBody {
+                content_type: None,
+                content: content.to_string(),
+            },
+            attachments: Vec::new(),
+            importance: None,
+        }
+    }
+
+    fn with_importance(mut self, importance: Importance) -> Self {
+        self.importance = (importance != Importance::Normal).then(|| importance.as_graph());
+        self
+    }
+
+    /// Reply carrying a `messageReference` attachment, which Teams renders as a quote card
+    fn reply(content: &str, reply_to: &ReplyTo) -> Self {
+        let reference = serde_json::json!({
+            "messageId": reply_to.message_id,
+            "messagePreview": reply_to.preview,
+            "messageSender": {
+                "user": {
+                    "id": reply_to.sender_id,
+                    "displayName": reply_to.sender_name,
+                    "userIdentityType": "aadUser",
+                }
+            }
+        });
+        Self {
+            body: SendMessageBody {
+                content_type: Some("html"),
+                content: format!(
+                    "<attachment id=\"{}\"></attachment>{}",
+                    reply_to.message_id,
+                    crate::html::escape_html(content)
+                ),
+            },
+            attachments: vec![SendMessageAttachment {
+                id: reply_to.message_id.clone(),
+                content_type: "messageReference",
+                content: reference.to_string(),
+            }],
+            importance: None,
+        }
+    }
+
+    /// Fallback for chats that reject message references: quote the text inline
+    fn quoted(content: &str, reply_to: &ReplyTo) -> Self {
+        Self {
+            body: SendMessageBody {
+                content_type: Some("html"),
+                content: format!(
+                    "<blockquote>{}: {}</blockquote>{}",
+                    crate::html::escape_html(&reply_to.sender_name),
+                    crate::html::escape_html(&reply_to.preview),
+                    crate::html::escape_html(content)
+                ),
+            },
+            attachments: Vec::new(),
+            importance: None,
+        }
+    }
+}
+
+pub async fn send_message(
+    graph: &Graph,
+    access_token: &str,
+    chat_id: &str,
+    content: &str,
+    reply_to: Option<&ReplyTo>,
+    importance: Importance,
+) -> Result<()> {
+    let client = crate::http::client();
+    let url = format!("{}/chats/{}/messages", graph.base, chat_id);
+
+    let request_body = match reply_to {
+        Some(reply_to) => SendMessageRequest::reply(content, reply_to),
+        None => SendMessageRequest::plain(content),
+    }
+    .with_importance(importance);
+
+    let response = send_throttled(
+        client
+            .post(&url)
+            .header("Authorization", format!("Bearer {}", access_token))
+            .header("Content-Type", "application/json")
+            .json(&request_body),
+    )
+    .await?;
 
-    let request_body = SendMessageRequest {
-        body: SendMessageBody {
-            content: content.to_string(),
-        },
+    // Some chats don't accept message references; retry once as a plain quote
+    let response = match reply_to {
+        Some(reply_to) if response.status() == reqwest::StatusCode::BAD_REQUEST => {
+            send_throttled(
+                client
+                    .post(&url)
+                    .header("Authorization", format!("Bearer {}", access_token))
+                    .header("Content-Type", "application/json")
+                    .json(
+                        &SendMessageRequest::quoted(content, reply_to).with_importance(importance),
+                    ),
+            )
+            .await?
+        }
+        _ => response,
     };
 
+    if !response.status().is_success() {
+        let status = response.status();
+        let text = response.text().await?;
+        log_error!("Failed to send message: {} - {}", status, redact(&text));
+        match send_failure_reason(status) {
+            Some(reason) => anyhow::bail!("{}", reason),
+            None => anyhow::bail!("server returned {}", status),
+        }
+    }
+
+    Ok(())
+}
+
+/// Times a throttled (429) send is tried again before the error is shown
+const THROTTLE_RETRIES: u32 = 2;
+
+/// Longest `Retry-After` waited out; a longer one is reported to the user instead
+const MAX_RETRY_AFTER: std::time::Duration = std::time::Duration::from_secs(10);
+
+/// Send a request, waiting and trying again when Graph throttles it with 429
+///
+/// Graph says in `Retry-After` how many seconds to wait. Throttled requests were
+/// never processed, so sending them again can't post anything twice.
+async fn send_throttled(mut request: reqwest::RequestBuilder) -> Result<reqwest::Response> {
+    let mut retries = 0;
+    loop {
+        let retry = request.try_clone();
+        let response = request.send().await.map_err(crate::http::request_error)?;
+        if response.status() != reqwest::StatusCode::TOO_MANY_REQUESTS
+            || retries == THROTTLE_RETRIES
+        {
+            return Ok(response);
+        }
+        let wait = response
+            .headers()
+            .get(reqwest::header::RETRY_AFTER)
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| value.trim().parse::<u64>().ok())
+            .map_or(
+                std::time::Duration::from_secs(1),
+                std::time::Duration::from_secs,
+            );
+        let Some(next) = retry.filter(|_| wait <= MAX_RETRY_AFTER) else {
+            return Ok(response);
+        };
+        log_warn!(
+            "Graph is throttling requests; retrying in {}s",
+            wait.as_secs()
+        );
+        tokio::time::sleep(wait).await;
+        request = next;
+        retries += 1;
+    }
+}
+
+/// Teams web link that opens a chat at a message
+///
+/// Chat ids (e.g. "19:abc@thread.v2") are escaped as a path segment; the context
+/// tells Teams the id is a chat rather than a channel.
+pub fn message_permalink(chat_id: &str, message_id: &str) -> String {
+    let mut url =
+        reqwest::Url::parse("https://teams.microsoft.com/l/message/").expect("static URL parses");
+    if let Ok(mut segments) = url.path_segments_mut() {
+        segments.pop_if_empty().push(chat_id).push(message_id);
+    }
+    url.query_pairs_mut()
+        .append_pair("context", r#"{"contextType":"chat"}"#);
+    url.to_string()
+}
+
+/// Plain explanation for the send failures a user can do something about
+fn send_failure_reason(status: reqwest::StatusCode) -> Option<&'static str> {
+    match status {
+        reqwest::StatusCode::UNAUTHORIZED => Some("sign-in expired; press A to sign in again"),
+        reqwest::StatusCode::FORBIDDEN => {
+            Some("not allowed to post here (you may no longer be a member of this chat)")
+        }
+        reqwest::StatusCode::NOT_FOUND => Some("this chat no longer exists"),
+        reqwest::StatusCode::PAYLOAD_TOO_LARGE => Some("the message is too large"),
+        reqwest::StatusCode::TOO_MANY_REQUESTS => {
+            Some("Teams is rate limiting sends; try again shortly")
+        }
+        _ => None,
+    }
+}
+
+/// React to a message with one of the `REACTIONS` types
+pub async fn set_reaction(
+    graph: &Graph,
+    access_token: &str,
+    chat_id: &str,
+    message_id: &str,
+    reaction_type: &str,
+) -> Result<()> {
+    post_reaction(
+        graph,
+        access_token,
+        chat_id,
+        message_id,
+        reaction_type,
+        "setReaction",
+    )
+    .await
+}
+
+/// Take back a reaction previously given with `set_reaction`
+pub async fn unset_reaction(
+    graph: &Graph,
+    access_token: &str,
+    chat_id: &str,
+    message_id: &str,
+    reaction_type: &str,
+) -> Result<()> {
+    post_reaction(
+        graph,
+        access_token,
+        chat_id,
+        message_id,
+        reaction_type,
+        "unsetReaction",
+    )
+    .await
+}
+
+async fn post_reaction(
+    graph: &Graph,
+    access_token: &str,
+    chat_id: &str,
+    message_id: &str,
+    reaction_type: &str,
+    action: &str,
+) -> Result<()> {
+    let client = crate::http::client();
+    let url = format!(
+        "{}/chats/{}/messages/{}/{}",
+        graph.base, chat_id, message_id, action
+    );
+    let body = serde_json::json!({ "reactionType": reaction_type });
+
     let response = client
         .post(&url)
         .header("Authorization", format!("Bearer {}", access_token))
-        .header("Content-Type", "application/json")
-        .json(&request_body)
+        .json(&body)
         .send()
-        .await?;
+        .await
+        .map_err(crate::http::request_error)?;
 
     if !response.status().is_success() {
         let status = response.status();
         let text = response.text().await?;
-        anyhow::bail!("Failed to send message: {} - {}", status, text);
+        anyhow::bail!("Failed to {}: {} - {}", action, status, redact(&text));
     }
-
     Ok(())
 }
 
-pub async fn get_chats(access_token: &str) -> Result<(Vec<Chat>, Option<String>)> {
-    let client = reqwest::Client::new();
-    let url = format!("{}/me/chats", GRAPH_API_BASE);
+/// Whether a message from `sender` with this text already reached the chat since `sent_at`
+///
+/// Checked before retrying a failed send: a request that timed out may still have been
+/// accepted by the server, and posting it again would duplicate it.
+pub async fn find_sent_message(
+    graph: &Graph,
+    access_token: &str,
+    chat_id: &str,
+    sender: Option<&str>,
+    text: &str,
+    sent_at: Option<chrono::DateTime<chrono::FixedOffset>>,
+) -> Result<bool> {
+    let page = get_messages(graph, access_token, chat_id, MAX_PAGE_SIZE).await?;
+    Ok(page
+        .messages
+        .iter()
+        .any(|msg| msg.is_echo_of(sender, text, sent_at)))
+}
+
+/// Mark a chat as read up to now for the signed-in user
+///
+/// Returns `Ok(false)` when the tenant or chat doesn't offer `markChatReadForUser`,
+/// so callers can treat that as a no-op rather than an error.
+pub async fn mark_chat_read(
+    graph: &Graph,
+    access_token: &str,
+    chat_id: &str,
+    user_id: &str,
+    tenant_id: &str,
+) -> Result<bool> {
+    let client = crate::http::client();
+    let url = format!("{}/chats/{}/markChatReadForUser", graph.base, chat_id);
+    let body = serde_json::json!({
+        "user": { "id": user_id, "tenantId": tenant_id }
+    });
+
+    let response = client
+        .post(&url)
+        .header("Authorization", format!("Bearer {}", access_token))
+        .json(&body)
+        .send()
+        .await
+        .map_err(crate::http::request_error)?;
+
+    let status = response.status();
+    if status.is_success() {
+        return Ok(true);
+    }
+    if matches!(
+        status,
+        reqwest::StatusCode::NOT_FOUND
+            | reqwest::StatusCode::METHOD_NOT_ALLOWED
+            | reqwest::StatusCode::NOT_IMPLEMENTED
+    ) {
+        return Ok(false);
+    }
+    let text = response.text().await?;
+    anyhow::bail!(
+        "Failed to mark chat as read: {} - {}",
+        status,
+        redact(&text)
+    );
+}
+
+/// Order chats newest activity first; chats without a parseable timestamp go last.
+/// The sort is stable, so chats with equal timestamps keep Graph's relative order.
+pub fn sort_chats_by_recency(chats: &mut [Chat]) {
+    chats.sort_by_cached_key(|chat| {
+        std::cmp::Reverse(
+            chat.last_updated
+                .as_deref()
+                .and_then(crate::time::parse_teams_datetime),
+        )
+    });
+}
+
+/// How the chat list is ordered and how untitled groups are named
+#[derive(Debug, Clone, Copy)]
+pub struct ChatListOptions {
+    pub sort_by_recency: bool,
+    pub group_name_member_count: usize,
+    pub abbreviate_group_names: bool,
+}
+
+impl From<&crate::config::Config> for ChatListOptions {
+    fn from(config: &crate::config::Config) -> Self {
+        Self {
+            sort_by_recency: config.sort_chats_by_recency,
+            group_name_member_count: config.group_name_member_count,
+            abbreviate_group_names: config.abbreviate_group_names,
+        }
+    }
+}
+
+pub async fn get_chats(
+    graph: &Graph,
+    access_token: &str,
+    options: ChatListOptions,
+) -> Result<(Vec<Chat>, Option<String>)> {
+    get_chats_with_progress(graph, access_token, options, |_, _| {}).await
+}
+
+/// `get_chats`, reporting (done, total) as each chat's members are fetched
+pub async fn get_chats_with_progress(
+    graph: &Graph,
+    access_token: &str,
+    options: ChatListOptions,
+    on_progress: impl Fn(usize, usize),
+) -> Result<(Vec<Chat>, Option<String>)> {
+    let client = crate::http::client();
+    let url = format!("{}/me/chats?$expand=lastMessagePreview", graph.base);
 
     let response = client
         .get(&url)
         .header("Authorization", format!("Bearer {}", access_token))
         .send()
-        .await?;
+        .await
+        .map_err(crate::http::request_error)?;
 
     if !response.status().is_success() {
         let status = response.status();
         let text = response.text().await?;
-        anyhow::bail!("Failed to get chats: {} - {}", status, text);
+        anyhow::bail!("Failed to get chats: {} - {}", status, redact(&text));
     }
 
     let chats_response = response.json::<ChatsResponse>().await?;
+    Ok(label_chat_list(
+        graph,
+        access_token,
+        chats_response.value,
+        options,
+        on_progress,
+    )
+    .await)
+}
 
+/// Fetch members for a fresh list of chats, detect the current user and name the chats
+async fn label_chat_list(
+    graph: &Graph,
+    access_token: &str,
+    chats: Vec<Chat>,
+    options: ChatListOptions,
+    on_progress: impl Fn(usize, usize),
+) -> (Vec<Chat>, Option<String>) {
     // Filter out meeting chats - only show oneOnOne and group chats
-    let mut filtered_chats: Vec<Chat> = chats_response
-        .value
+    let mut filtered_chats: Vec<Chat> = chats
         .into_iter()
         .filter(|chat| chat.chat_type == "oneOnOne" || chat.chat_type == "group")
         .collect();
 
+    if options.sort_by_recency {
+        sort_chats_by_recency(&mut filtered_chats);
+    }
+
     // Fetch members for each chat to get display names
-    for chat in &mut filtered_chats {
-        chat.members = get_chat_members(access_token, &chat.id)
+    let total = filtered_chats.len();
+    for (done, chat) in filtered_chats.iter_mut().enumerate() {
+        on_progress(done, total);
+        chat.members = get_chat_members(graph, access_token, &chat.id)
             .await
             .unwrap_or_default();
     }
@@ -342,62 +1328,961 @@ pub async fn get_chats(access_token: &str) -> Result<(Vec<Chat>, Option<String>)
         }
     }
 
-    // Now filter out the current user from all chats by name
-    if let Some(user_name) = &current_user_name {
-        for chat in &mut filtered_chats {
-            chat.members.retain(|m| {
-                m.display_name
-                    .as_ref()
-                    .map(|name| name != user_name)
-                    .unwrap_or(true)
-            });
+    for chat in &mut filtered_chats {
+        label_chat(chat, current_user_name.as_deref(), options);
+    }
+
+    (filtered_chats, current_user_name)
+}
+
+/// Chats added, changed or removed since a previous delta query
+#[derive(Debug, Default)]
+pub struct ChatDelta {
+    /// Changed chats as Graph sent them; members are not fetched
+    pub changed: Vec<serde_json::Value>,
+    pub removed: Vec<String>,
+    /// Link for the next round; `None` if Graph didn't return one
+    pub delta_link: Option<String>,
+}
+
+/// Query `/me/chats/delta`, starting a new round when `delta_link` is `None`
+///
+/// Returns `Ok(None)` when Graph reports the delta link as expired (410 Gone), in
+/// which case the caller has to resync with a full fetch.
+pub async fn get_chats_delta(
+    graph: &Graph,
+    access_token: &str,
+    delta_link: Option<&str>,
+) -> Result<Option<ChatDelta>> {
+    let client = crate::http::client();
+    let mut url = match delta_link {
+        Some(link) => link.to_string(),
+        None => format!("{}/me/chats/delta", graph.base),
+    };
+    let mut delta = ChatDelta::default();
+
+    // Changes can span several pages; the delta link comes with the last one
+    loop {
+        let response = client
+            .get(&url)
+            .header("Authorization", format!("Bearer {}", access_token))
+            .send()
+            .await
+            .map_err(crate::http::request_error)?;
+
+        let status = response.status();
+        if status == reqwest::StatusCode::GONE {
+            return Ok(None);
+        }
+        if !status.is_success() {
+            let text = response.text().await?;
+            anyhow::bail!("Failed to get chat changes: {} - {}", status, redact(&text));
+        }
+
+        let page = response.json::<ChatsDeltaResponse>().await?;
+        for item in page.value {
+            if item.get("@removed").is_some() {
+                if let Some(id) = item.get("id").and_then(|id| id.as_str()) {
+                    delta.removed.push(id.to_string());
+                }
+            } else {
+                delta.changed.push(item);
+            }
+        }
+
+        match page.next_link {
+            Some(next) => url = next,
+            None => {
+                delta.delta_link = page.delta_link;
+                return Ok(Some(delta));
+            }
         }
     }
+}
 
-    // Compute display names for all chats
-    for chat in &mut filtered_chats {
-        chat.cached_display_name = if chat.chat_type == "oneOnOne" {
-            // For oneOnOne, use the first member's name
-            chat.members.first().and_then(|m| m.display_name.clone())
-        } else if chat.chat_type == "group" {
-            // For group, prefer topic, otherwise show member names
-            if let Some(topic) = &chat.topic {
-                if !topic.is_empty() {
-                    Some(topic.clone())
-                } else {
-                    // Show up to 3 member names (abbreviated)
-                    let names: Vec<String> = chat
-                        .members
-                        .iter()
-                        .filter_map(|m| m.display_name.as_ref().map(|n| abbreviate_name(n)))
-                        .take(3)
-                        .collect();
-
-                    if !names.is_empty() {
-                        Some(names.join(", "))
-                    } else {
-                        Some("Unnamed Group".to_string())
+/// Full refreshes to make after a failed delta round before trying delta again
+const DELTA_RETRY_ROUNDS: u32 = 10;
+
+/// Chat list kept current by the background refresh
+///
+/// The first refresh (and any after the delta link expires) starts a
+/// `/me/chats/delta` round, which lists every chat. Later ones ask the delta link
+/// for what changed and only fetch members and the newest message for those chats.
+/// When delta fails, that refresh falls back to a full `get_chats`, and a new round
+/// is tried again a few refreshes later.
+pub struct ChatSync {
+    graph: Graph,
+    chats: Vec<Chat>,
+    current_user_name: Option<String>,
+    delta_link: Option<String>,
+    /// Full refreshes left before a new delta round is tried after one failed
+    delta_backoff: u32,
+    options: ChatListOptions,
+}
+
+impl ChatSync {
+    pub fn new(graph: Graph, options: ChatListOptions) -> Self {
+        Self {
+            graph,
+            chats: Vec::new(),
+            current_user_name: None,
+            delta_link: None,
+            delta_backoff: 0,
+            options,
+        }
+    }
+
+    /// Bring the chat list up to date, returning it with the detected current user
+    pub async fn refresh(&mut self, access_token: &str) -> Result<(Vec<Chat>, Option<String>)> {
+        if let Some(link) = self.delta_link.clone() {
+            match get_chats_delta(&self.graph, access_token, Some(&link)).await {
+                Ok(Some(delta)) => {
+                    self.apply_delta(access_token, delta).await;
+                    return Ok(self.snapshot());
+                }
+                Ok(None) => {
+                    log_info!("Chat delta link expired; resyncing");
+                    self.delta_link = None;
+                }
+                // The link is kept: a network blip doesn't make it invalid
+                Err(e) => {
+                    log_warn!("Chat delta refresh failed, fetching all chats: {:#}", e);
+                    return self.full_refresh(access_token).await;
+                }
+            }
+        }
+
+        if self.delta_backoff > 0 {
+            self.delta_backoff -= 1;
+            return self.full_refresh(access_token).await;
+        }
+        let error = match get_chats_delta(&self.graph, access_token, None).await {
+            Ok(Some(delta)) => {
+                self.seed(access_token, delta).await;
+                return Ok(self.snapshot());
+            }
+            Ok(None) => anyhow::anyhow!("the new delta round was already expired"),
+            Err(e) => e,
+        };
+        log_warn!(
+            "Chat delta sync failed, using full refreshes for now: {:#}",
+            error
+        );
+        self.delta_backoff = DELTA_RETRY_ROUNDS;
+        self.full_refresh(access_token).await
+    }
+
+    fn snapshot(&self) -> (Vec<Chat>, Option<String>) {
+        (self.chats.clone(), self.current_user_name.clone())
+    }
+
+    async fn full_refresh(&mut self, access_token: &str) -> Result<(Vec<Chat>, Option<String>)> {
+        let (chats, current_user_name) = get_chats(&self.graph, access_token, self.options).await?;
+        self.chats = chats;
+        self.current_user_name = current_user_name;
+        Ok(self.snapshot())
+    }
+
+    /// Replace the list with the chats of a new delta round
+    async fn seed(&mut self, access_token: &str, delta: ChatDelta) {
+        let mut chats: Vec<Chat> = delta
+            .changed
+            .into_iter()
+            .filter_map(|item| serde_json::from_value::<Chat>(item).ok())
+            .filter(|chat| chat.chat_type == "oneOnOne" || chat.chat_type == "group")
+            .collect();
+        for chat in &mut chats {
+            if chat.last_message.is_none() {
+                chat.last_message = self.newest_message(access_token, &chat.id).await;
+            }
+        }
+        let (chats, current_user_name) =
+            label_chat_list(&self.graph, access_token, chats, self.options, |_, _| {}).await;
+        self.chats = chats;
+        self.current_user_name = current_user_name;
+        self.delta_link = delta.delta_link;
+    }
+
+    async fn apply_delta(&mut self, access_token: &str, delta: ChatDelta) {
+        self.chats.retain(|chat| !delta.removed.contains(&chat.id));
+
+        for item in delta.changed {
+            let Some(id) = item
+                .get("id")
+                .and_then(|id| id.as_str())
+                .map(str::to_string)
+            else {
+                continue;
+            };
+            let existing = self.chats.iter().position(|chat| chat.id == id);
+            let mut chat = match (serde_json::from_value::<Chat>(item.clone()), existing) {
+                (Ok(chat), _) => chat,
+                // Partial update: only the changed properties were sent
+                (Err(_), Some(index)) => {
+                    let mut chat = self.chats[index].clone();
+                    if let Some(topic) = item.get("topic") {
+                        chat.topic = topic.as_str().map(str::to_string);
+                    }
+                    if let Some(ts) = item.get("lastUpdatedDateTime").and_then(|v| v.as_str()) {
+                        chat.last_updated = Some(ts.to_string());
+                    }
+                    if let Some(viewpoint) = item.get("viewpoint") {
+                        chat.viewpoint = serde_json::from_value(viewpoint.clone()).ok();
                     }
+                    chat
                 }
-            } else {
-                // No topic - show member names (abbreviated)
-                let names: Vec<String> = chat
-                    .members
-                    .iter()
-                    .filter_map(|m| m.display_name.as_ref().map(|n| abbreviate_name(n)))
-                    .take(3)
-                    .collect();
-
-                if !names.is_empty() {
-                    Some(names.join(", "))
-                } else {
-                    Some("Unnamed Group".to_string())
+                (Err(_), None) => continue,
+            };
+
+            // Meeting chats are never listed
+            if chat.chat_type != "oneOnOne" && chat.chat_type != "group" {
+                if let Some(index) = existing {
+                    self.chats.remove(index);
                 }
+                continue;
+            }
+
+            // Delta items don't carry the expanded preview; a change usually means a
+            // new message, so ask for it rather than keep showing the old one
+            if item.get("lastMessagePreview").is_none_or(|v| v.is_null()) {
+                chat.last_message = match self.newest_message(access_token, &chat.id).await {
+                    Some(message) => Some(message),
+                    None => existing.and_then(|index| self.chats[index].last_message.clone()),
+                };
+            }
+            chat.members = get_chat_members(&self.graph, access_token, &chat.id)
+                .await
+                .unwrap_or_default();
+            label_chat(&mut chat, self.current_user_name.as_deref(), self.options);
+            match existing {
+                Some(index) => self.chats[index] = chat,
+                None => self.chats.push(chat),
+            }
+        }
+
+        if let Some(link) = delta.delta_link {
+            self.delta_link = Some(link);
+        }
+        if self.options.sort_by_recency {
+            sort_chats_by_recency(&mut self.chats);
+        }
+    }
+
+    /// A chat's newest message, or `None` if it can't be fetched
+    async fn newest_message(&self, access_token: &str, chat_id: &str) -> Option<Message> {
+        match get_messages(&self.graph, access_token, chat_id, 1).await {
+            Ok(page) => page.messages.into_iter().next(),
+            Err(e) => {
+                log_warn!("Could not fetch the newest message of {}: {:#}", chat_id, e);
+                None
             }
-        } else {
-            Some("Unknown Chat".to_string())
+        }
+    }
+}
+
+/// Drop the current user from a chat's members and resolve its display name
+fn label_chat(chat: &mut Chat, current_user_name: Option<&str>, options: ChatListOptions) {
+    // Filter out the current user by name
+    if let Some(user_name) = current_user_name {
+        chat.members.retain(|m| {
+            m.display_name
+                .as_ref()
+                .map(|name| name != user_name)
+                .unwrap_or(true)
+        });
+    }
+
+    // Compute the display name
+    chat.cached_display_name = if chat.chat_type == "oneOnOne" {
+        // For oneOnOne, use the first member's name
+        chat.members.first().and_then(|m| m.display_name.clone())
+    } else if chat.chat_type == "group" {
+        // For group, prefer topic, otherwise show member names
+        match &chat.topic {
+            Some(topic) if !topic.is_empty() => Some(topic.clone()),
+            _ => Some(group_member_names(chat, options)),
+        }
+    } else {
+        Some("Unknown Chat".to_string())
+    };
+    chat.last_message_preview = chat
+        .last_message
+        .as_ref()
+        .and_then(|m| message_preview(m, current_user_name));
+}
+
+/// Name for an untitled group: the first few members, abbreviated unless configured
+/// otherwise. The chat list truncates whatever doesn't fit its width.
+fn group_member_names(chat: &Chat, options: ChatListOptions) -> String {
+    let names: Vec<String> = chat
+        .members
+        .iter()
+        .filter_map(|m| m.display_name.as_deref())
+        .map(|n| {
+            if options.abbreviate_group_names {
+                abbreviate_name(n)
+            } else {
+                n.to_string()
+            }
+        })
+        .take(options.group_name_member_count)
+        .collect();
+
+    if names.is_empty() {
+        "Unnamed Group".to_string()
+    } else {
+        names.join(", ")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_message_permalink_escapes_ids() {
+        assert_eq!(
+            message_permalink("19:abc def@thread.v2", "1715000000000"),
+            "https://teams.microsoft.com/l/message/19:abc%20def@thread.v2/1715000000000\
+             ?context=%7B%22contextType%22%3A%22chat%22%7D"
+        );
+        assert!(
+            message_permalink("19:x/y@unq.gbl.spaces", "1").contains("/19:x%2Fy@unq.gbl.spaces/1?")
+        );
+    }
+
+    #[test]
+    fn test_system_event_text() {
+        let event = |detail: serde_json::Value| -> Message {
+            serde_json::from_value(serde_json::json!({
+                "id": "1",
+                "messageType": "systemEventMessage",
+                "createdDateTime": "2024-05-01T10:00:00Z",
+                "eventDetail": detail,
+            }))
+            .unwrap()
+        };
+        let added = event(serde_json::json!({
+            "@odata.type": "#microsoft.graph.membersAddedEventMessageDetail",
+            "initiator": { "user": { "displayName": "Alice" } },
+            "members": [{ "id": "b", "displayName": "Bob" }],
+        }));
+        assert_eq!(
+            added.system_event_text().as_deref(),
+            Some("Alice added Bob")
+        );
+        let left = event(serde_json::json!({
+            "@odata.type": "#microsoft.graph.membersLeftEventMessageDetail",
+            "members": [{ "id": "b", "displayName": null }, { "id": "c" }],
+        }));
+        assert_eq!(left.system_event_text().as_deref(), Some("2 people left"));
+        let renamed = event(serde_json::json!({
+            "@odata.type": "#microsoft.graph.chatRenamedEventMessageDetail",
+            "chatDisplayName": "Project X",
+        }));
+        assert_eq!(
+            renamed.system_event_text().as_deref(),
+            Some("Someone renamed the chat to 'Project X'")
+        );
+        let unknown = event(serde_json::json!({
+            "@odata.type": "#microsoft.graph.somethingNewEventMessageDetail",
+        }));
+        assert_eq!(unknown.system_event_text(), None);
+    }
+
+    #[test]
+    fn test_parse_messages_skips_malformed() {
+        let values = vec![
+            serde_json::json!({ "id": "1", "createdDateTime": "2024-05-01T10:00:00Z" }),
+            serde_json::json!({ "id": "2", "createdDateTime": 42 }),
+            serde_json::json!({ "id": "3", "createdDateTime": "2024-05-01T09:00:00Z",
+                "attachments": "not a list" }),
+            serde_json::json!({ "id": "4", "createdDateTime": "2024-05-01T08:00:00Z" }),
+        ];
+        let ids: Vec<String> = parse_messages(values).into_iter().map(|m| m.id).collect();
+        assert_eq!(ids, ["1", "4"]);
+    }
+
+    #[test]
+    fn test_mentions_matches_at_tags() {
+        let message = |html: &str| -> Message {
+            serde_json::from_value(serde_json::json!({
+                "id": "1",
+                "createdDateTime": "2024-05-01T10:00:00Z",
+                "body": { "content": html },
+            }))
+            .unwrap()
+        };
+        assert!(message("<p>hey <at id=\"0\">Bob Smith</at></p>").mentions("Bob Smith"));
+        assert!(message("<at id=\"0\">bob</at>, look").mentions("Bob Smith"));
+        assert!(!message("<at id=\"0\">Alice</at> and Bob").mentions("Bob Smith"));
+        assert!(!message("<p>Bob Smith</p>").mentions("Bob Smith"));
+
+        // Resolved mentions match by id even when the tag shows a nickname
+        let resolved: Message = serde_json::from_value(serde_json::json!({
+            "id": "2",
+            "createdDateTime": "2024-05-01T10:00:00Z",
+            "body": { "content": "<at id=\"0\">Bobby</at> ping" },
+            "mentions": [{
+                "id": 0,
+                "mentionText": "Bobby",
+                "mentioned": { "user": { "id": "u-bob", "displayName": "Bob Smith" } }
+            }],
+        }))
+        .unwrap();
+        assert!(resolved.mentions_me(Some("u-bob"), Some("Bob Smith")));
+        assert!(!resolved.mentions_me(Some("u-alice"), Some("Alice")));
+    }
+
+    #[test]
+    fn test_reaction_summary_and_own_reaction() {
+        let reaction = |kind: &str, user: &str| serde_json::json!({ "reactionType": kind, "user": { "user": { "id": user } } });
+        let message: Message = serde_json::from_value(serde_json::json!({
+            "id": "1",
+            "createdDateTime": "2024-05-01T10:00:00Z",
+            "reactions": [
+                reaction("laugh", "a"),
+                reaction("like", "a"),
+                reaction("like", "b"),
+                reaction("🎉", "b"),
+            ],
+        }))
+        .unwrap();
+        assert_eq!(
+            message.reaction_summary(),
+            vec![("👍", 2), ("😆", 1), ("🎉", 1)]
+        );
+        assert!(message.has_reaction("like", Some("b")));
+        assert!(!message.has_reaction("laugh", Some("b")));
+        assert!(!message.has_reaction("like", None));
+    }
+
+    #[test]
+    fn test_message_preview_is_one_plain_line() {
+        let message = |sender: &str, html: &str| -> Message {
+            serde_json::from_value(serde_json::json!({
+                "id": "1",
+                "createdDateTime": "2024-05-01T10:00:00Z",
+                "from": { "user": { "displayName": sender } },
+                "body": { "content": html },
+            }))
+            .unwrap()
+        };
+        assert_eq!(
+            message_preview(&message("Bob", "<p>see you</p><p>at 3</p>"), Some("Ann")),
+            Some("see you at 3".to_string())
+        );
+        assert_eq!(
+            message_preview(&message("Ann", "<p>ok &amp; thanks</p>"), Some("Ann")),
+            Some("You: ok & thanks".to_string())
+        );
+        assert_eq!(message_preview(&message("Bob", "<p></p>"), None), None);
+    }
+
+    #[test]
+    fn test_edited_and_deleted_states() {
+        let message = |extra: serde_json::Value| -> Message {
+            let mut json = serde_json::json!({
+                "id": "1",
+                "createdDateTime": "2024-05-01T10:00:00Z",
+            });
+            json.as_object_mut()
+                .unwrap()
+                .extend(extra.as_object().unwrap().clone());
+            serde_json::from_value(json).unwrap()
+        };
+        let plain = message(serde_json::json!({
+            "lastModifiedDateTime": "2024-05-01T10:00:00Z",
+            "lastEditedDateTime": null,
+            "deletedDateTime": null,
+            "policyViolation": null,
+        }));
+        assert!(!plain.is_edited() && !plain.is_deleted() && !plain.is_policy_hidden());
+        assert!(
+            message(serde_json::json!({ "lastEditedDateTime": "2024-05-01T10:05:00Z" }))
+                .is_edited()
+        );
+        assert!(!message(serde_json::json!({ "lastEditedDateTime": "yesterday" })).is_edited());
+        let deleted = message(serde_json::json!({
+            "lastEditedDateTime": "2024-05-01T10:05:00Z",
+            "deletedDateTime": "2024-05-01T10:06:00Z",
+        }));
+        assert!(deleted.is_deleted() && !deleted.is_edited());
+        assert!(
+            message(serde_json::json!({ "policyViolation": { "policyTip": {} } }))
+                .is_policy_hidden()
+        );
+    }
+
+    #[test]
+    fn test_is_image_by_extension_and_mime() {
+        let extensions = crate::config::Config::default().image_extensions;
+        let attachment = |content_type: &str, name: &str| MessageAttachment {
+            id: None,
+            content_type: Some(content_type.to_string()),
+            content_url: None,
+            name: Some(name.to_string()),
+            thumbnail_url: None,
+        };
+
+        let cases = [
+            ("reference", "photo.png", true),
+            ("reference", "Scan.JPEG", true),
+            ("reference", "diagram.tiff", true),
+            ("reference", "favicon.ico", true),
+            ("reference", "frame.qoi", true),
+            ("reference", "report.pdf", false),
+            ("reference", "notes.png.txt", false),
+            ("reference", "png", false),
+            ("image/heic", "photo.heic", true),
+            ("application/octet-stream", "photo.png", false),
+        ];
+        for (content_type, name, expected) in cases {
+            assert_eq!(
+                attachment(content_type, name).is_image(&extensions),
+                expected,
+                "{} {}",
+                content_type,
+                name
+            );
+        }
+
+        // Configured extensions extend what reference attachments count as images
+        let mut with_heic = extensions.clone();
+        with_heic.push("heic".to_string());
+        assert!(!attachment("reference", "IMG_1.HEIC").is_image(&extensions));
+        assert!(attachment("reference", "IMG_1.HEIC").is_image(&with_heic));
+    }
+
+    #[test]
+    fn test_reply_request_includes_message_reference() {
+        let reply_to = ReplyTo {
+            message_id: "1700000000000".to_string(),
+            sender_id: Some("user-1".to_string()),
+            sender_name: "Bob".to_string(),
+            preview: "Lunch?".to_string(),
+        };
+        let json = serde_json::to_value(SendMessageRequest::reply("Sure <3", &reply_to)).unwrap();
+
+        assert_eq!(json["body"]["contentType"], "html");
+        assert_eq!(
+            json["body"]["content"],
+            "<attachment id=\"1700000000000\"></attachment>Sure &lt;3"
+        );
+        assert_eq!(json["attachments"][0]["contentType"], "messageReference");
+        let reference: serde_json::Value =
+            serde_json::from_str(json["attachments"][0]["content"].as_str().unwrap()).unwrap();
+        assert_eq!(reference["messageId"], "1700000000000");
+        assert_eq!(reference["messageSender"]["user"]["displayName"], "Bob");
+
+        // Plain sends keep the original minimal body
+        let plain = serde_json::to_value(SendMessageRequest::plain("hi")).unwrap();
+        assert_eq!(plain, serde_json::json!({ "body": { "content": "hi" } }));
+    }
+
+    // Requests against a mock Graph server
+
+    use serde_json::json;
+    use wiremock::matchers::{body_partial_json, header, method, path, query_param};
+    use wiremock::{Mock, MockServer, ResponseTemplate};
+
+    /// Graph client for a mock server
+    fn mock_graph(server: &MockServer) -> Graph {
+        Graph::new(format!("{}/v1.0", server.uri()))
+    }
+
+    fn json_response(status: u16, body: serde_json::Value) -> ResponseTemplate {
+        ResponseTemplate::new(status).set_body_json(body)
+    }
+
+    fn message_json(id: &str, created: &str, text: &str) -> serde_json::Value {
+        json!({
+            "id": id,
+            "messageType": "message",
+            "createdDateTime": created,
+            "from": { "user": { "id": "u-bob", "displayName": "Bob Smith" } },
+            "body": { "contentType": "html", "content": text },
+        })
+    }
+
+    #[tokio::test]
+    async fn test_get_messages_pages_through_history() {
+        let server = MockServer::start().await;
+        let graph = mock_graph(&server);
+        let next_link = format!(
+            "{}/v1.0/chats/c1/messages?$top=2&$skiptoken=abc",
+            server.uri()
+        );
+        Mock::given(method("GET"))
+            .and(path("/v1.0/chats/c1/messages"))
+            .and(query_param("$skiptoken", "abc"))
+            .respond_with(json_response(
+                200,
+                json!({ "value": [message_json("1", "2024-05-01T10:00:00Z", "first")] }),
+            ))
+            .with_priority(1)
+            .mount(&server)
+            .await;
+        Mock::given(method("GET"))
+            .and(path("/v1.0/chats/c1/messages"))
+            .and(query_param("$top", "2"))
+            .and(header("authorization", "Bearer token-1"))
+            .respond_with(json_response(
+                200,
+                json!({
+                    "value": [
+                        message_json("3", "2024-05-01T10:02:00Z", "third"),
+                        message_json("2", "2024-05-01T10:01:00Z", "second"),
+                    ],
+                    "@odata.nextLink": next_link,
+                }),
+            ))
+            .expect(1)
+            .mount(&server)
+            .await;
+
+        let page = get_messages(&graph, "token-1", "c1", 2).await.unwrap();
+        let ids: Vec<&str> = page.messages.iter().map(|m| m.id.as_str()).collect();
+        assert_eq!(ids, ["3", "2"]);
+        assert_eq!(page.next_link.as_deref(), Some(next_link.as_str()));
+
+        let older = get_older_messages("token-1", &next_link).await.unwrap();
+        assert_eq!(older.messages.len(), 1);
+        assert_eq!(older.messages[0].id, "1");
+        assert_eq!(older.next_link, None);
+    }
+
+    #[tokio::test]
+    async fn test_get_messages_errors() {
+        let server = MockServer::start().await;
+        let graph = mock_graph(&server);
+        Mock::given(path("/v1.0/chats/gone/messages"))
+            .respond_with(json_response(
+                404,
+                json!({ "error": { "code": "NotFound" } }),
+            ))
+            .mount(&server)
+            .await;
+        Mock::given(path("/v1.0/chats/busy/messages"))
+            .respond_with(json_response(
+                503,
+                json!({ "error": { "code": "ServiceUnavailable" } }),
+            ))
+            .mount(&server)
+            .await;
+
+        let gone = get_messages(&graph, "t", "gone", 50).await.unwrap_err();
+        assert!(gone.is::<ChatUnavailable>());
+        let busy = get_messages(&graph, "t", "busy", 50).await.unwrap_err();
+        assert!(!busy.is::<ChatUnavailable>());
+        assert!(busy.to_string().contains("503"), "{}", busy);
+    }
+
+    #[tokio::test]
+    async fn test_send_message_body_and_failures() {
+        let server = MockServer::start().await;
+        let graph = mock_graph(&server);
+        Mock::given(method("POST"))
+            .and(path("/v1.0/chats/c1/messages"))
+            .and(body_partial_json(json!({
+                "body": { "content": "hi <b>" },
+                "importance": "high",
+            })))
+            .respond_with(json_response(201, json!({ "id": "9" })))
+            .expect(1)
+            .mount(&server)
+            .await;
+        Mock::given(method("POST"))
+            .and(path("/v1.0/chats/c2/messages"))
+            .respond_with(json_response(
+                403,
+                json!({ "error": { "code": "Forbidden" } }),
+            ))
+            .mount(&server)
+            .await;
+
+        send_message(&graph, "t", "c1", "hi <b>", None, Importance::High)
+            .await
+            .unwrap();
+
+        let error = send_message(&graph, "t", "c2", "hi", None, Importance::Normal)
+            .await
+            .unwrap_err();
+        assert!(
+            error.to_string().contains("not allowed to post"),
+            "{}",
+            error
+        );
+    }
+
+    #[tokio::test]
+    async fn test_send_message_waits_out_throttling() {
+        let server = MockServer::start().await;
+        let graph = mock_graph(&server);
+        let throttled = || {
+            ResponseTemplate::new(429)
+                .insert_header("Retry-After", "0")
+                .set_body_json(json!({ "error": { "code": "TooManyRequests" } }))
+        };
+        // Throttled once, then accepted
+        Mock::given(method("POST"))
+            .and(path("/v1.0/chats/c1/messages"))
+            .respond_with(throttled())
+            .up_to_n_times(1)
+            .with_priority(1)
+            .expect(1)
+            .mount(&server)
+            .await;
+        Mock::given(method("POST"))
+            .and(path("/v1.0/chats/c1/messages"))
+            .respond_with(json_response(201, json!({ "id": "9" })))
+            .expect(1)
+            .mount(&server)
+            .await;
+        // Throttled every time: the first try and each retry, then the error
+        Mock::given(method("POST"))
+            .and(path("/v1.0/chats/c2/messages"))
+            .respond_with(throttled())
+            .expect(u64::from(THROTTLE_RETRIES) + 1)
+            .mount(&server)
+            .await;
+
+        send_message(&graph, "t", "c1", "hi", None, Importance::Normal)
+            .await
+            .unwrap();
+        let error = send_message(&graph, "t", "c2", "hi", None, Importance::Normal)
+            .await
+            .unwrap_err();
+        assert!(error.to_string().contains("rate limiting"), "{}", error);
+    }
+
+    #[tokio::test]
+    async fn test_get_chats_detects_me_and_names_chats() {
+        let server = MockServer::start().await;
+        let graph = mock_graph(&server);
+        let chat = |id: &str, chat_type: &str, topic: Option<&str>, updated: &str| {
+            json!({
+                "id": id,
+                "chatType": chat_type,
+                "topic": topic,
+                "lastUpdatedDateTime": updated,
+            })
+        };
+        Mock::given(method("GET"))
+            .and(path("/v1.0/me/chats"))
+            .and(query_param("$expand", "lastMessagePreview"))
+            .respond_with(json_response(
+                200,
+                json!({ "value": [
+                    chat("one", "oneOnOne", None, "2024-05-01T09:00:00Z"),
+                    chat("two", "oneOnOne", None, "2024-05-01T11:00:00Z"),
+                    chat("meeting", "meeting", Some("Standup"), "2024-05-01T12:00:00Z"),
+                    chat("group", "group", None, "2024-05-01T10:00:00Z"),
+                ] }),
+            ))
+            .mount(&server)
+            .await;
+        let members = |names: &[&str]| {
+            json!({ "value": names
+                .iter()
+                .map(|name| json!({ "displayName": name }))
+                .collect::<Vec<_>>() })
+        };
+        for (chat_id, names) in [
+            ("one", &["Me Myself", "Alice Jones"][..]),
+            ("two", &["Carol White", "Me Myself"][..]),
+            ("group", &["Me Myself", "Alice Jones", "Dave Brown"][..]),
+        ] {
+            Mock::given(method("GET"))
+                .and(path(format!("/v1.0/chats/{}/members", chat_id)))
+                .respond_with(json_response(200, members(names)))
+                .mount(&server)
+                .await;
+        }
+
+        let options = ChatListOptions {
+            sort_by_recency: true,
+            group_name_member_count: 3,
+            abbreviate_group_names: true,
+        };
+        let (chats, me) = get_chats(&graph, "t", options).await.unwrap();
+        assert_eq!(me.as_deref(), Some("Me Myself"));
+        let names: Vec<(&str, &str)> = chats
+            .iter()
+            .map(|c| {
+                (
+                    c.id.as_str(),
+                    c.cached_display_name.as_deref().unwrap_or(""),
+                )
+            })
+            .collect();
+        assert_eq!(
+            names,
+            [
+                ("two", "Carol White"),
+                ("group", "Alice J, Dave B"),
+                ("one", "Alice Jones"),
+            ]
+        );
+    }
+
+    /// Answer `/v1.0/chats/{chat_id}/members` with these display names
+    async fn mount_members(server: &MockServer, chat_id: &str, names: &[&str]) {
+        let members: Vec<serde_json::Value> = names
+            .iter()
+            .map(|name| json!({ "displayName": name }))
+            .collect();
+        Mock::given(method("GET"))
+            .and(path(format!("/v1.0/chats/{}/members", chat_id)))
+            .respond_with(json_response(200, json!({ "value": members })))
+            .mount(server)
+            .await;
+    }
+
+    /// Answer a chat's `$top=1` message request with one message
+    async fn mount_newest(server: &MockServer, chat_id: &str, message: serde_json::Value) {
+        Mock::given(method("GET"))
+            .and(path(format!("/v1.0/chats/{}/messages", chat_id)))
+            .and(query_param("$top", "1"))
+            .respond_with(json_response(200, json!({ "value": [message] })))
+            .mount(server)
+            .await;
+    }
+
+    /// Start a `ChatSync` from a delta round listing chats "a" and "b"
+    async fn seeded_chat_sync(server: &MockServer) -> ChatSync {
+        let delta_link = format!("{}/v1.0/me/chats/delta?$deltatoken=1", server.uri());
+        Mock::given(method("GET"))
+            .and(path("/v1.0/me/chats/delta"))
+            .respond_with(json_response(
+                200,
+                json!({
+                    "value": [
+                        { "id": "a", "chatType": "oneOnOne", "lastUpdatedDateTime": "2024-05-01T09:00:00Z" },
+                        { "id": "b", "chatType": "oneOnOne", "lastUpdatedDateTime": "2024-05-01T10:00:00Z" },
+                        { "id": "m", "chatType": "meeting", "lastUpdatedDateTime": "2024-05-01T11:00:00Z" },
+                    ],
+                    "@odata.deltaLink": delta_link,
+                }),
+            ))
+            .expect(1)
+            .mount(server)
+            .await;
+        // The delta round is the whole first sync
+        Mock::given(path("/v1.0/me/chats"))
+            .respond_with(json_response(500, json!({})))
+            .expect(0)
+            .mount(server)
+            .await;
+        mount_members(server, "a", &["Me Myself", "Alice Jones"]).await;
+        mount_members(server, "b", &["Bob Smith", "Me Myself"]).await;
+        mount_newest(
+            server,
+            "a",
+            message_json("1", "2024-05-01T09:00:00Z", "morning"),
+        )
+        .await;
+        mount_newest(
+            server,
+            "b",
+            message_json("2", "2024-05-01T10:00:00Z", "hello"),
+        )
+        .await;
+
+        let options = ChatListOptions {
+            sort_by_recency: true,
+            group_name_member_count: 3,
+            abbreviate_group_names: true,
         };
+        let mut sync = ChatSync::new(mock_graph(server), options);
+        let (chats, me) = sync.refresh("t").await.unwrap();
+        assert_eq!(me.as_deref(), Some("Me Myself"));
+        let ids: Vec<&str> = chats.iter().map(|c| c.id.as_str()).collect();
+        assert_eq!(ids, ["b", "a"]);
+        assert_eq!(chats[0].last_message_preview.as_deref(), Some("hello"));
+        server.verify().await;
+        server.reset().await;
+        sync
+    }
+
+    #[tokio::test]
+    async fn test_chat_sync_refreshes_changed_chat_previews() {
+        let server = MockServer::start().await;
+        let mut sync = seeded_chat_sync(&server).await;
+
+        // Chat "a" got a new message; the delta item only says when
+        Mock::given(method("GET"))
+            .and(path("/v1.0/me/chats/delta"))
+            .and(query_param("$deltatoken", "1"))
+            .respond_with(json_response(
+                200,
+                json!({ "value": [
+                    { "id": "a", "lastUpdatedDateTime": "2024-05-01T12:00:00Z" },
+                ] }),
+            ))
+            .expect(1)
+            .mount(&server)
+            .await;
+        mount_members(&server, "a", &["Me Myself", "Alice Jones"]).await;
+        mount_newest(
+            &server,
+            "a",
+            message_json("3", "2024-05-01T12:00:00Z", "lunch?"),
+        )
+        .await;
+
+        let (chats, _) = sync.refresh("t").await.unwrap();
+        let ids: Vec<&str> = chats.iter().map(|c| c.id.as_str()).collect();
+        assert_eq!(ids, ["a", "b"]);
+        assert_eq!(
+            chats[0].last_message.as_ref().map(|m| m.id.as_str()),
+            Some("3")
+        );
+        assert_eq!(chats[0].last_message_preview.as_deref(), Some("lunch?"));
+        assert_eq!(chats[1].last_message_preview.as_deref(), Some("hello"));
     }
 
-    Ok((filtered_chats, current_user_name))
+    #[tokio::test]
+    async fn test_chat_sync_falls_back_when_delta_fails() {
+        let server = MockServer::start().await;
+        let mut sync = seeded_chat_sync(&server).await;
+
+        // The delta link fails once: that refresh fetches every chat instead
+        Mock::given(method("GET"))
+            .and(path("/v1.0/me/chats/delta"))
+            .respond_with(json_response(
+                503,
+                json!({ "error": { "code": "ServiceUnavailable" } }),
+            ))
+            .up_to_n_times(1)
+            .with_priority(1)
+            .expect(1)
+            .mount(&server)
+            .await;
+        Mock::given(method("GET"))
+            .and(path("/v1.0/me/chats"))
+            .respond_with(json_response(
+                200,
+                json!({ "value": [
+                    { "id": "b", "chatType": "oneOnOne", "lastUpdatedDateTime": "2024-05-01T10:00:00Z" },
+                ] }),
+            ))
+            .expect(1)
+            .mount(&server)
+            .await;
+        mount_members(&server, "b", &["Bob Smith", "Me Myself"]).await;
+        let (chats, _) = sync.refresh("t").await.unwrap();
+        assert_eq!(chats.len(), 1);
+
+        // The next refresh goes back to the same delta link
+        Mock::given(method("GET"))
+            .and(path("/v1.0/me/chats/delta"))
+            .and(query_param("$deltatoken", "1"))
+            .respond_with(json_response(200, json!({ "value": [] })))
+            .expect(1)
+            .mount(&server)
+            .await;
+        let (chats, _) = sync.refresh("t").await.unwrap();
+        assert_eq!(chats.len(), 1);
+    }
 }
@@ -1,8 +1,43 @@
-use crate::api::{Chat, Message};
+use crate::api::{
+    Chat, Importance, Message, MessageBody, MessageFrom, MessagePage, MessageReaction, MessageUser,
+    ReplyTo, SendState, REACTIONS,
+};
+use crate::config::{Config, NotifPref};
 use crate::image_display::{ImageCache, ImagePicker};
+use crate::logging::log_warn;
+use crate::state::UiState;
+use crate::time::DisplayZone;
+use image::DynamicImage;
 use ratatui::layout::Rect;
+use ratatui::text::Line;
+use ratatui::widgets::ListState;
 use ratatui_image::protocol::StatefulProtocol;
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
+use std::time::{Duration, Instant};
+use unicode_segmentation::UnicodeSegmentation;
+
+/// Allowed chat list width, as a percentage of the window
+const SPLIT_RATIO_RANGE: (u16, u16) = (15, 60);
+const DEFAULT_SPLIT_RATIO: u16 = 30;
+/// Consecutive failed chat list refreshes (`list_poll_secs` apart) before the UI admits it is stale
+const RECONNECT_BADGE_AFTER: u32 = 2;
+
+/// Token lookups that must fail in a row before the app goes offline (read-only)
+const OFFLINE_AFTER: u32 = 3;
+/// Wheel ticks closer together than this count as one fast spin
+const WHEEL_ACCELERATION_WINDOW: Duration = Duration::from_millis(80);
+/// Most a fast spin multiplies `scroll_step` by
+const MAX_WHEEL_MULTIPLIER: u16 = 6;
+/// Longest side of a gallery thumbnail, in pixels
+pub const THUMBNAIL_PIXELS: u32 = 256;
+/// Pixels each image cache may hold, about 128 MB of RGBA
+const IMAGE_CACHE_PIXELS: u64 = 32_000_000;
+/// Without input for this long, cached images are dropped until needed again
+const IDLE_IMAGE_RELEASE: Duration = Duration::from_secs(10 * 60);
+/// Largest message body Teams accepts, in bytes
+pub const MAX_MESSAGE_BYTES: usize = 28 * 1024;
+/// An identical message to the same chat within this window is treated as a double send
+const DUPLICATE_SEND_WINDOW: Duration = Duration::from_secs(10);
 
 #[derive(Clone, Copy, PartialEq, Eq)]
 pub enum ActivePane {
@@ -16,88 +51,1041 @@ pub enum FocusedPane {
     Messages,
 }
 
+/// Remove the last user-perceived character (grapheme cluster) from a text buffer
+///
+/// Backspace should take an emoji with its skin tone or a letter with its accent
+/// in one go, not leave half of it behind.
+pub fn pop_grapheme(buffer: &mut String) {
+    if let Some((index, _)) = buffer.grapheme_indices(true).next_back() {
+        buffer.truncate(index);
+    }
+}
+
+/// A message sent from this client, echoed locally until the server confirms it
+///
+/// The local message id is generated once per compose and kept across retries, so a
+/// retry updates the same echo. Graph takes no idempotency key for chat messages;
+/// instead a retry first looks for the message on the server (see
+/// `api::find_sent_message`) and only posts it again if it isn't there.
+#[derive(Clone)]
+pub struct OutgoingMessage {
+    pub chat_id: String,
+    pub message: Message,
+    pub reply_to: Option<ReplyTo>,
+    queued_at: Instant,
+}
+
+impl OutgoingMessage {
+    pub fn content(&self) -> String {
+        self.message
+            .body
+            .as_ref()
+            .and_then(|b| b.content.clone())
+            .unwrap_or_default()
+    }
+
+    /// Plain text and send time, as matched against the server's copy
+    pub fn sent_text(&self) -> (String, Option<chrono::DateTime<chrono::FixedOffset>>) {
+        (
+            crate::html::html_to_plain(&self.content()),
+            crate::time::parse_teams_datetime(&self.message.created_date_time),
+        )
+    }
+}
+
 #[derive(Clone)]
 pub struct ViewableImage {
     pub name: String,
     pub url: String,
+    /// Pixel dimensions, known once the image has been decoded
+    pub dimensions: Option<(u32, u32)>,
+    /// Shareable direct link resolved while downloading (SharePoint/OneDrive)
+    pub download_url: Option<String>,
+    /// Full-resolution original when `url` is a thumbnail
+    pub full_url: Option<String>,
+    /// Whether the viewer shows `full_url` rather than the thumbnail
+    pub full_resolution: bool,
+}
+
+impl ViewableImage {
+    /// URL the viewer downloads for the resolution being shown
+    pub fn source_url(&self) -> &str {
+        match self.full_url {
+            Some(ref full_url) if self.full_resolution => full_url,
+            _ => &self.url,
+        }
+    }
+
+    /// URL to try when `source_url` fails: the original behind a thumbnail,
+    /// which often still works after the thumbnail link has expired
+    pub fn fallback_url(&self) -> Option<&str> {
+        self.full_url.as_deref().filter(|_| !self.full_resolution)
+    }
+}
+
+/// Frames of an animated GIF playing in the image viewer
+pub struct GifAnimation {
+    pub frames: Vec<(DynamicImage, Duration)>,
+    pub current: usize,
+    pub next_frame_at: Instant,
+    pub paused: bool,
+}
+
+/// A URL's position in the rendered messages, for mouse hit-testing
+pub struct MessageLink {
+    pub line: usize,
+    /// Display columns, end exclusive
+    pub start_col: u16,
+    pub end_col: u16,
+    pub url: String,
+}
+
+/// One row of the attachments drawer
+pub struct AttachmentEntry {
+    pub message_id: String,
+    pub name: String,
+    /// Short type label, e.g. "PDF" or "image/png"
+    pub kind: String,
+    pub sender: String,
+    pub created_date_time: String,
+    pub is_image: bool,
+    /// Where the file lives (SharePoint/OneDrive for shared files)
+    pub url: Option<String>,
+}
+
+/// A reaction picked in the reaction picker, to be sent to Graph
+pub struct ReactionChange {
+    pub chat_id: String,
+    pub message_id: String,
+    pub reaction_type: &'static str,
+    /// Take the reaction back instead of adding it
+    pub remove: bool,
 }
 
 pub struct App {
+    pub config: Config,
+    pub display_zone: DisplayZone,
+    pub ui_state: UiState,
+    /// Chats as listed: muted ones last, or left out unless `show_muted`
     pub chats: Vec<Chat>,
+    /// Every chat from the last refresh, before muting and deleted chats are filtered out
+    all_chats: Vec<Chat>,
+    /// List muted chats (dimmed, at the bottom) so they can be unmuted
+    pub show_muted: bool,
     pub status: String,
     pub selected_index: usize,
     pub current_user_name: Option<String>,
+    /// Graph id of the signed-in user, from `/me`
+    pub current_user_id: Option<String>,
+    /// The token carries a file scope, so SharePoint/OneDrive images can be downloaded
+    pub can_view_files: bool,
+    /// Background chat refreshes that have failed in a row
+    refresh_failures: u32,
+    /// Background token lookups that have failed in a row
+    auth_failures: u32,
+    /// A token could be had recently; offline, composing is disabled until the
+    /// user signs in again
+    pub online: bool,
     pub messages: Vec<Message>,
     pub loading_messages: bool,
+    /// Why the selected chat's messages could not be loaded, if they couldn't
+    pub messages_error: Option<String>,
+    /// Chats Graph reports as deleted; hidden from the list from the next refresh on
+    unavailable_chats: HashSet<String>,
+    /// `@odata.nextLink` of the oldest page loaded so far
+    pub next_page_link: Option<String>,
+    /// Older pages fetched by scrolling past the top
+    pub older_pages_loaded: usize,
+    pub loading_older: bool,
+    older_requested: bool,
+    /// Older pages pushed the newest messages out of the cache; polled pages no
+    /// longer join up with what's loaded until the user jumps back to the newest
+    newest_trimmed: bool,
+    /// Message under the J/K cursor, by id so it survives new messages arriving
+    pub focused_message_id: Option<String>,
+    /// Message the compose box is replying to
+    pub reply_to: Option<ReplyTo>,
+    /// Importance the next message is sent with, cycled with Ctrl+U while composing
+    pub compose_importance: Importance,
     pub input_mode: bool,
     pub input_buffer: String,
+    /// Byte offset of the compose cursor in `input_buffer`, always on a grapheme boundary
+    pub cursor_pos: usize,
+    /// Chat the current `input_buffer` draft belongs to
+    draft_chat_id: Option<String>,
+    pub confirm_quit: bool,
+    /// Recipients of a send awaiting confirmation, `Some` while the prompt is shown
+    pub confirm_send: Option<usize>,
+    /// Scroll offset of the member list overlay, `Some` while it is open
+    pub members_overlay: Option<usize>,
+    /// Whether `--debug` aids are available
+    pub debug: bool,
+    /// Raw HTML of a message as received, and the overlay's scroll offset
+    pub raw_html_overlay: Option<(String, usize)>,
+    /// Highlighted row of the notification preference overlay, `Some` while it is open
+    pub notif_overlay: Option<usize>,
+    /// A chat list refresh brought a message that should ring the terminal bell
+    pub notify_pending: bool,
+    /// Highlighted entry of the reaction picker, `Some` while it is open
+    pub reaction_picker: Option<usize>,
+    /// Messages whose long quotes are shown in full
+    pub expanded_quotes: HashSet<String>,
+    /// Highlighted row of the attachments drawer, `Some` while it is open
+    pub attachments_drawer: Option<usize>,
     pub scroll_offset: u16,
     pub max_scroll: u16,
     pub snap_to_bottom: bool,
+    /// First 'g' of a "gg" was pressed
+    pub pending_g: bool,
+    /// Time and direction (up = true) of the last wheel tick, for acceleration
+    last_wheel: Option<(Instant, bool)>,
+    /// Consecutive fast wheel ticks in one direction
+    wheel_streak: u16,
     pub active_pane: ActivePane,
     pub focused_pane: FocusedPane,
     pub chat_list_area: Rect,
+    /// Scroll position of the chat list, kept by ratatui so the selection stays visible
+    pub chat_list_state: ListState,
     pub messages_area: Rect,
+    /// Bar of pinned messages above the messages pane, and the message on each of its rows
+    pub pinned_area: Rect,
+    pub pinned_rows: Vec<String>,
+    /// Area shared by the chat list and messages pane
+    pub content_area: Rect,
+    /// Chat list width as a percentage of `content_area`
+    pub split_ratio: u16,
+    pub dragging_split: bool,
     pub image_picker: Option<ImagePicker>,
-    #[allow(dead_code)]
     pub image_cache: ImageCache,
-    pub image_protocols: HashMap<String, StatefulProtocol>,
+    pub image_protocols: ImageCache<StatefulProtocol>,
     pub viewing_image: Option<ViewableImage>,
+    /// Last key or mouse event, for releasing image memory while idle
+    pub last_input: Instant,
+    /// Last input or new message, for redrawing less often while idle
+    last_activity: Instant,
     pub current_image_protocol: Option<StatefulProtocol>,
+    pub animation: Option<GifAnimation>,
+    /// Decoded image (or current GIF frame) behind `current_image_protocol`
+    pub viewer_image: Option<DynamicImage>,
+    /// Scale the image to the popup (default) or show it at 1:1 pixels
+    pub zoom_fit: bool,
+    /// Top-left pixel of the visible part of the image in actual-size mode
+    pub image_pan: (i32, i32),
+    pub image_viewport: Rect,
     pub loading_image: bool,
     pub image_error: Option<String>,
     pub viewable_images: Vec<ViewableImage>,
     pub selected_image_index: usize,
+    /// Thumbnail grid of `viewable_images` is open; `selected_image_index` is its selection
+    pub gallery_mode: bool,
+    /// Thumbnails per row at the last draw, for moving the selection up and down
+    pub gallery_columns: usize,
+    /// Thumbnails requested for the gallery that haven't arrived yet
+    gallery_pending: HashSet<String>,
+    pub command_mode: bool,
+    pub command_buffer: String,
+    pub search_mode: bool,
+    pub search_query: String,
+    /// Indices into `messages` of messages matching `search_query`, oldest first
+    pub search_matches: Vec<usize>,
+    pub current_match: usize,
+    /// Message index the messages pane should scroll to on the next draw
+    pub pending_scroll_to: Option<usize>,
+    /// Read time of a chat just opened with unreads, until its first load positions the view
+    unread_since: Option<chrono::DateTime<chrono::FixedOffset>>,
+    /// Oldest message the user hadn't read when the chat was opened; the divider sits above it
+    pub first_unread_id: Option<String>,
+    pub outgoing: Vec<OutgoingMessage>,
+    next_local_id: usize,
+    /// Mouse selection in the messages pane as (anchor, cursor) positions,
+    /// each a (column, content line) pair independent of scrolling
+    pub selection: Option<((u16, u16), (u16, u16))>,
+    /// Plain text of each rendered line in the messages pane, for copying selections
+    pub message_lines: Vec<String>,
+    pub message_links: Vec<MessageLink>,
+    /// Rendered messages pane content, rebuilt only when `messages_dirty` is set
+    /// or the pane width changes
+    pub message_cache: Vec<Line<'static>>,
+    /// Line each cached message starts at, keyed by index into `messages`
+    pub message_starts: HashMap<usize, u16>,
+    pub message_cache_width: usize,
+    /// Something the messages pane shows changed since `message_cache` was built
+    pub messages_dirty: bool,
 }
 
 #[allow(dead_code)]
 impl App {
     pub fn new() -> Self {
+        let ui_state = UiState::load();
+        let config = crate::config::load_config();
+        // The terminal is already in raw mode here, so it can answer the query
+        let image_picker = ImagePicker::new().unwrap_or_else(|e| {
+            let font_size = config.fallback_font_size();
+            log_warn!("{:#}; assuming a {:?} pixel cell size", e, font_size);
+            ImagePicker::with_fallback_fontsize(font_size)
+        });
+        Self::with_parts(config, ui_state, Some(image_picker))
+    }
+
+    /// An app with default settings and no saved state or terminal, for tests
+    #[cfg(test)]
+    pub fn for_test() -> Self {
+        Self::with_parts(Config::default(), UiState::default(), None)
+    }
+
+    fn with_parts(config: Config, ui_state: UiState, image_picker: Option<ImagePicker>) -> Self {
+        let split_ratio = ui_state
+            .split_ratio
+            .unwrap_or(DEFAULT_SPLIT_RATIO)
+            .clamp(SPLIT_RATIO_RANGE.0, SPLIT_RATIO_RANGE.1);
+        let display_zone = DisplayZone::from_config(config.timezone.as_deref());
         App {
+            config,
+            display_zone,
+            ui_state,
             chats: Vec::new(),
+            all_chats: Vec::new(),
+            show_muted: false,
             status: String::new(),
             selected_index: 0,
             current_user_name: None,
+            current_user_id: None,
+            can_view_files: true,
+            refresh_failures: 0,
+            auth_failures: 0,
+            online: true,
             messages: Vec::new(),
             loading_messages: false,
+            messages_error: None,
+            unavailable_chats: HashSet::new(),
+            next_page_link: None,
+            older_pages_loaded: 0,
+            loading_older: false,
+            older_requested: false,
+            newest_trimmed: false,
+            focused_message_id: None,
+            reply_to: None,
+            compose_importance: Importance::Normal,
             input_mode: false,
             input_buffer: String::new(),
+            cursor_pos: 0,
+            draft_chat_id: None,
+            confirm_quit: false,
+            confirm_send: None,
+            members_overlay: None,
+            debug: false,
+            raw_html_overlay: None,
+            notif_overlay: None,
+            notify_pending: false,
+            reaction_picker: None,
+            expanded_quotes: HashSet::new(),
+            attachments_drawer: None,
             scroll_offset: 0,
             max_scroll: 0,
             snap_to_bottom: true,
+            pending_g: false,
+            last_wheel: None,
+            wheel_streak: 0,
             active_pane: ActivePane::ChatList,
             focused_pane: FocusedPane::ChatList,
             chat_list_area: Rect::default(),
+            chat_list_state: ListState::default(),
             messages_area: Rect::default(),
-            image_picker: None,
-            image_cache: ImageCache::new(10),
-            image_protocols: HashMap::new(),
+            pinned_area: Rect::default(),
+            pinned_rows: Vec::new(),
+            content_area: Rect::default(),
+            split_ratio,
+            dragging_split: false,
+            image_picker,
+            image_cache: ImageCache::new(10, IMAGE_CACHE_PIXELS),
+            image_protocols: ImageCache::new(usize::MAX, IMAGE_CACHE_PIXELS),
+            last_input: Instant::now(),
+            last_activity: Instant::now(),
             viewing_image: None,
             current_image_protocol: None,
+            animation: None,
+            viewer_image: None,
+            zoom_fit: true,
+            image_pan: (0, 0),
+            image_viewport: Rect::default(),
             loading_image: false,
             image_error: None,
             viewable_images: Vec::new(),
             selected_image_index: 0,
+            gallery_mode: false,
+            gallery_columns: 1,
+            gallery_pending: HashSet::new(),
+            command_mode: false,
+            command_buffer: String::new(),
+            search_mode: false,
+            search_query: String::new(),
+            search_matches: Vec::new(),
+            current_match: 0,
+            pending_scroll_to: None,
+            unread_since: None,
+            first_unread_id: None,
+            outgoing: Vec::new(),
+            next_local_id: 0,
+            selection: None,
+            message_lines: Vec::new(),
+            message_links: Vec::new(),
+            message_cache: Vec::new(),
+            message_starts: HashMap::new(),
+            message_cache_width: 0,
+            messages_dirty: true,
         }
     }
 
-    pub fn set_chats(&mut self, chats: Vec<Chat>) {
-        self.chats = chats;
+    /// Replace the chat list, keeping the selection on the same chat
+    ///
+    /// Returns true when the selected chat left the list and another took its place,
+    /// so the caller should load that chat's messages.
+    pub fn set_chats(&mut self, mut chats: Vec<Chat>) -> bool {
+        let selected_id = self.get_selected_chat().map(|c| c.id.clone());
+        // A refresh can carry an older preview than the open chat's latest message
+        for chat in &mut chats {
+            let Some(previous) = self.all_chats.iter().find(|c| c.id == chat.id) else {
+                continue;
+            };
+            let newer = match (&previous.last_message, &chat.last_message) {
+                (Some(old), Some(new)) => {
+                    crate::time::parse_teams_datetime(&old.created_date_time)
+                        > crate::time::parse_teams_datetime(&new.created_date_time)
+                }
+                (Some(_), None) => true,
+                _ => false,
+            };
+            if newer {
+                chat.last_message = previous.last_message.clone();
+                chat.last_message_preview = previous.last_message_preview.clone();
+                continue;
+            }
+            // A new latest message in a chat other than the open one may notify
+            let arrived = chat
+                .last_message
+                .as_ref()
+                .filter(|new| previous.last_message.as_ref().map(|old| &old.id) != Some(&new.id));
+            if let Some(message) = arrived {
+                if selected_id.as_ref() != Some(&chat.id) && self.should_notify(&chat.id, message) {
+                    self.notify_pending = true;
+                }
+            }
+        }
+        self.all_chats = chats;
+        let moved = self.rebuild_chat_list();
         self.status = format!("Loaded {} chats", self.chats.len());
+        moved
+    }
+
+    /// Select the chat to open on launch: `default_chat_id` if it is listed, else
+    /// the chat open when the app last quit, else the first one
+    pub fn select_startup_chat(&mut self) {
+        let candidates = [
+            self.config.default_chat_id.as_deref(),
+            self.ui_state.last_chat_id.as_deref(),
+        ];
+        self.selected_index = candidates
+            .into_iter()
+            .flatten()
+            .find_map(|id| self.chats.iter().position(|c| c.id == id))
+            .unwrap_or(0);
+    }
+
+    fn rebuild_chat_list(&mut self) -> bool {
+        let selected_id = self.get_selected_chat().map(|c| c.id.clone());
+        let (mut chats, muted): (Vec<Chat>, Vec<Chat>) = self
+            .all_chats
+            .iter()
+            .filter(|c| !self.unavailable_chats.contains(&c.id))
+            .cloned()
+            .partition(|c| !self.is_muted(&c.id));
+        if self.show_muted {
+            chats.extend(muted);
+        }
+        self.chats = chats;
+
+        let Some(id) = selected_id else {
+            return false;
+        };
+        match self.chats.iter().position(|c| c.id == id) {
+            Some(index) => {
+                self.selected_index = index;
+                false
+            }
+            None => {
+                self.messages_dirty = true;
+                self.selected_index = self.selected_index.min(self.chats.len().saturating_sub(1));
+                !self.chats.is_empty()
+            }
+        }
+    }
+
+    pub fn is_muted(&self, chat_id: &str) -> bool {
+        self.ui_state.muted_chats.iter().any(|id| id == chat_id)
+    }
+
+    /// Muted chats hidden from the list right now
+    pub fn hidden_muted_count(&self) -> usize {
+        if self.show_muted {
+            return 0;
+        }
+        self.all_chats
+            .iter()
+            .filter(|c| self.is_muted(&c.id))
+            .count()
+    }
+
+    pub fn notif_pref(&self, chat_id: &str) -> NotifPref {
+        self.config
+            .notification_prefs
+            .get(chat_id)
+            .copied()
+            .unwrap_or_default()
+    }
+
+    /// Open the notification overlay on the selected chat's current preference
+    pub fn open_notif_overlay(&mut self) {
+        if let Some(chat) = self.get_selected_chat() {
+            let pref = self.notif_pref(&chat.id);
+            self.notif_overlay = NotifPref::ALL.iter().position(|p| *p == pref);
+        }
+    }
+
+    pub fn move_notif_selection(&mut self, delta: isize) {
+        if let Some(row) = self.notif_overlay.as_mut() {
+            *row = row
+                .saturating_add_signed(delta)
+                .min(NotifPref::ALL.len() - 1);
+        }
+    }
+
+    /// Save the highlighted preference for the selected chat and close the overlay
+    pub fn apply_notif_selection(&mut self) {
+        let Some(row) = self.notif_overlay.take() else {
+            return;
+        };
+        let Some(chat_id) = self.get_selected_chat().map(|c| c.id.clone()) else {
+            return;
+        };
+        let pref = NotifPref::ALL[row];
+        if pref == NotifPref::default() {
+            self.config.notification_prefs.remove(&chat_id);
+        } else {
+            self.config.notification_prefs.insert(chat_id, pref);
+        }
+        self.status = match crate::config::save_notification_prefs(&self.config.notification_prefs)
+        {
+            Ok(()) => format!("Notifications: {}", pref.label()),
+            Err(e) => {
+                log_warn!("Could not save notification preferences: {:#}", e);
+                format!("Notifications: {} (not saved: {})", pref.label(), e)
+            }
+        };
+    }
+
+    /// Re-read config.json and apply what can change while running
+    ///
+    /// Sign-in, cloud, timeout, page size and chat list naming settings keep their startup
+    /// values; the status bar names any that changed and need a restart.
+    pub fn reload_config(&mut self) {
+        let mut config = match crate::config::reload_config() {
+            Ok(config) => config,
+            Err(e) => {
+                log_warn!("Could not reload config: {:#}", e);
+                self.status = format!("Config not reloaded: {:#}", e);
+                return;
+            }
+        };
+        let restart_needed = config.keep_startup_settings(&self.config);
+        self.config = config;
+        self.display_zone = DisplayZone::from_config(self.config.timezone.as_deref());
+        self.update_viewable_images();
+        self.messages_dirty = true;
+        self.status = if restart_needed.is_empty() {
+            "Config reloaded".to_string()
+        } else {
+            format!(
+                "Config reloaded; restart to apply {}",
+                restart_needed.join(", ")
+            )
+        };
+    }
+
+    /// Whether a new message in a chat should ring the terminal bell
+    ///
+    /// My own messages and muted chats never notify; otherwise the chat's
+    /// preference decides, with "Mentions" looking for me in the `<at>` tags.
+    pub fn should_notify(&self, chat_id: &str, message: &Message) -> bool {
+        let sender = message
+            .from
+            .as_ref()
+            .and_then(|f| f.user.as_ref())
+            .and_then(|u| u.display_name.as_deref());
+        let me = self.current_user_name.as_deref();
+        if message.is_system() || (sender.is_some() && sender == me) || self.is_muted(chat_id) {
+            return false;
+        }
+        match self.notif_pref(chat_id) {
+            NotifPref::All => true,
+            NotifPref::Mentions => message.mentions_me(self.current_user_id.as_deref(), me),
+            NotifPref::None => false,
+        }
+    }
+
+    /// Mute or unmute the selected chat; returns true if the selection moved to another chat
+    pub fn toggle_mute_selected(&mut self) -> bool {
+        let Some(chat) = self.get_selected_chat() else {
+            return false;
+        };
+        let id = chat.id.clone();
+        let name = chat
+            .cached_display_name
+            .clone()
+            .unwrap_or_else(|| "chat".to_string());
+        if self.is_muted(&id) {
+            self.ui_state.muted_chats.retain(|muted| *muted != id);
+            self.status = format!("Unmuted {}", name);
+        } else {
+            self.ui_state.muted_chats.push(id);
+            self.status = if self.show_muted {
+                format!("Muted {}", name)
+            } else {
+                format!("Muted {} (X shows muted chats)", name)
+            };
+        }
+        if let Err(e) = self.ui_state.save() {
+            log_warn!("Could not save muted chats: {}", e);
+        }
+        self.rebuild_chat_list()
+    }
+
+    /// Show or hide muted chats; returns true if the selection moved to another chat
+    pub fn toggle_show_muted(&mut self) -> bool {
+        self.show_muted = !self.show_muted;
+        self.rebuild_chat_list()
     }
 
     pub fn set_current_user(&mut self, name: String) {
+        self.messages_dirty = true;
         self.current_user_name = Some(name);
     }
 
+    /// Everyone in the selected chat as (name, email) rows, the current user first
+    ///
+    /// `Chat::members` has the current user filtered out, so they are added back here.
+    pub fn selected_chat_members(&self) -> Vec<(String, Option<String>)> {
+        let Some(chat) = self.get_selected_chat() else {
+            return Vec::new();
+        };
+        let mut members: Vec<(String, Option<String>)> = chat
+            .members
+            .iter()
+            .map(|m| {
+                (
+                    m.display_name
+                        .clone()
+                        .unwrap_or_else(|| "Unknown".to_string()),
+                    m.email.clone().filter(|e| !e.is_empty()),
+                )
+            })
+            .collect();
+        members.sort_by_key(|(name, _)| name.to_lowercase());
+        if let Some(me) = &self.current_user_name {
+            members.insert(0, (format!("{} (you)", me), None));
+        }
+        members
+    }
+
+    /// Scroll the member list overlay; the renderer clamps it to the list length
+    pub fn scroll_members(&mut self, delta: isize) {
+        if let Some(offset) = self.members_overlay.as_mut() {
+            *offset = offset.saturating_add_signed(delta);
+        }
+    }
+
+    /// Show the body of the message under the cursor exactly as Graph sent it
+    pub fn open_raw_html(&mut self) {
+        let Some(message) = self
+            .focused_message_id
+            .as_ref()
+            .and_then(|id| self.messages.iter().find(|m| &m.id == id))
+        else {
+            self.status = "Move to a message with J/K first".to_string();
+            return;
+        };
+        let content = message
+            .body
+            .as_ref()
+            .and_then(|b| b.content.clone())
+            .unwrap_or_default();
+        self.raw_html_overlay = Some((content, 0));
+    }
+
+    /// Scroll the raw HTML overlay; the renderer clamps it to the content
+    pub fn scroll_raw_html(&mut self, delta: isize) {
+        if let Some((_, offset)) = self.raw_html_overlay.as_mut() {
+            *offset = offset.saturating_add_signed(delta);
+        }
+    }
+
+    /// Whether an image can be downloaded with the scopes this session was granted
+    pub fn can_view_image(&self, url: &str) -> bool {
+        self.can_view_files || !crate::image_display::is_file_storage_url(url)
+    }
+
+    /// Count a background refresh outcome; any success resets the streak
+    pub fn record_refresh(&mut self, ok: bool) {
+        if ok {
+            self.refresh_failures = 0;
+        } else {
+            self.refresh_failures = self.refresh_failures.saturating_add(1);
+        }
+    }
+
+    /// Count a background token lookup; enough expired sign-ins in a row go
+    /// offline, and any success comes back online. Transient failures aren't counted.
+    pub fn record_auth(&mut self, ok: bool) {
+        if ok {
+            self.auth_failures = 0;
+            if !self.online {
+                self.online = true;
+                self.status = "Back online".to_string();
+            }
+        } else {
+            self.auth_failures = self.auth_failures.saturating_add(1);
+            if self.online && self.auth_failures >= OFFLINE_AFTER {
+                self.online = false;
+                self.status = "Offline: sign-in expired — press A to re-authenticate".to_string();
+            }
+        }
+    }
+
+    /// Whether enough refreshes have failed that the shown data may be stale
+    pub fn is_reconnecting(&self) -> bool {
+        self.refresh_failures >= RECONNECT_BADGE_AFTER
+    }
+
+    /// Clear a chat's unread marker without waiting for the next chat refresh
+    pub fn mark_chat_read_locally(&mut self, chat_id: &str) {
+        let read = chrono::Utc::now().to_rfc3339();
+        for chat in self
+            .chats
+            .iter_mut()
+            .chain(self.all_chats.iter_mut())
+            .filter(|c| c.id == chat_id)
+        {
+            chat.viewpoint = Some(crate::api::ChatViewpoint {
+                last_message_read: Some(read.clone()),
+            });
+        }
+    }
+
+    /// Show the open chat's newest delivered message as its chat list preview
+    fn update_last_message_preview(&mut self) {
+        let Some(chat_id) = self.get_selected_chat().map(|c| c.id.clone()) else {
+            return;
+        };
+        let Some(newest) = self
+            .messages
+            .iter()
+            .find(|m| m.send_state.is_none() && !m.is_system())
+        else {
+            return;
+        };
+        let me = self.current_user_name.as_deref();
+        for chat in self
+            .chats
+            .iter_mut()
+            .chain(self.all_chats.iter_mut())
+            .filter(|c| c.id == chat_id)
+        {
+            chat.set_last_message(newest.clone(), me);
+        }
+    }
+
     pub fn set_messages(&mut self, messages: Vec<Message>) {
-        self.messages = messages;
+        self.messages_dirty = true;
+        self.reconcile_outgoing(&messages);
+
+        // Local echoes for this chat go first, since messages are ordered newest first
+        let chat_id = self.get_selected_chat().map(|c| c.id.clone());
+        let mut merged: Vec<Message> = self
+            .outgoing
+            .iter()
+            .rev()
+            .filter(|o| Some(&o.chat_id) == chat_id.as_ref())
+            .map(|o| o.message.clone())
+            .collect();
+
+        // Keep history fetched via pagination: displayed messages older than this page
+        let over_cap = self.messages.len() + messages.len() > self.config.max_cached_messages;
+        if self.older_pages_loaded > 0 && over_cap && self.snap_to_bottom {
+            // Over the cap and back at the newest: the oldest have to go, and the
+            // page link beyond them would then skip messages, so drop all paged
+            // history and page it in again on demand. While scrolled up it's kept,
+            // so new messages don't pull history out from under the reader.
+            merged.extend(messages);
+            self.reset_history();
+        } else if self.older_pages_loaded > 0 {
+            let page_ids: HashSet<&str> = messages.iter().map(|m| m.id.as_str()).collect();
+            let page_oldest = messages
+                .last()
+                .and_then(|m| crate::time::parse_teams_datetime(&m.created_date_time));
+            let history: Vec<Message> = self
+                .messages
+                .iter()
+                .filter(|m| m.send_state.is_none() && !page_ids.contains(m.id.as_str()))
+                .filter(|m| {
+                    let created = crate::time::parse_teams_datetime(&m.created_date_time);
+                    matches!((created, page_oldest), (Some(c), Some(o)) if c < o)
+                })
+                .cloned()
+                .collect();
+            merged.extend(messages);
+            merged.extend(history);
+        } else {
+            merged.extend(messages);
+        }
+
+        self.messages = merged;
+        self.update_last_message_preview();
         self.loading_messages = false;
+        self.messages_error = None;
         self.update_viewable_images();
+        if !self.search_mode && !self.search_query.is_empty() {
+            self.update_search_matches();
+            self.current_match = self
+                .current_match
+                .min(self.search_matches.len().saturating_sub(1));
+        }
+    }
+
+    /// Show a freshly polled page of the selected chat
+    ///
+    /// Loading and any earlier error are cleared even when the page matches what
+    /// is shown, e.g. an empty chat whose first load comes back empty.
+    pub fn apply_messages_page(&mut self, page: MessagePage) {
+        // Check if messages actually changed to avoid unnecessary snaps/renders
+        if self.messages_changed(&page.messages) {
+            self.note_activity();
+            self.set_messages(page.messages);
+            if !self.jump_to_first_unread() {
+                self.snap_to_bottom = true;
+            }
+        } else if self.loading_messages || self.messages_error.is_some() {
+            self.messages_dirty = true;
+            self.loading_messages = false;
+            self.messages_error = None;
+        }
+        // After the merge, which may have dropped paged history over the cap
+        self.set_next_page(page.next_link);
     }
 
     pub fn set_loading_messages(&mut self, loading: bool) {
+        self.messages_dirty = true;
         self.loading_messages = loading;
+        if loading {
+            self.messages_error = None;
+        }
+    }
+
+    /// Remember where the next older page starts, unless history is already being
+    /// paged (then the oldest loaded page's link is the one that matters)
+    pub fn set_next_page(&mut self, next_link: Option<String>) {
+        self.messages_dirty = true;
+        if self.older_pages_loaded == 0 {
+            self.next_page_link = next_link;
+        }
+    }
+
+    /// Forget paged history, e.g. when switching chats
+    pub fn reset_history(&mut self) {
+        self.messages_dirty = true;
+        self.next_page_link = None;
+        self.older_pages_loaded = 0;
+        self.loading_older = false;
+        self.older_requested = false;
+        self.newest_trimmed = false;
+    }
+
+    /// Number of newest messages to render: the configured limit, or everything
+    /// once older pages have been loaded on request
+    pub fn display_limit(&self) -> usize {
+        if self.older_pages_loaded > 0 {
+            self.messages.len()
+        } else {
+            self.config.message_display_limit
+        }
+    }
+
+    /// Scroll the messages pane up; scrolling while already at the top asks for
+    /// the next older page
+    pub fn scroll_messages_up(&mut self, lines: u16) {
+        self.snap_to_bottom = false;
+        if self.scroll_offset == 0 {
+            self.older_requested = true;
+        }
+        self.scroll_offset = self.scroll_offset.saturating_sub(lines);
+    }
+
+    /// Lines to scroll for a wheel tick, growing while ticks arrive in quick succession
+    pub fn wheel_step(&mut self, up: bool) -> u16 {
+        let now = Instant::now();
+        let fast = self.last_wheel.is_some_and(|(at, was_up)| {
+            was_up == up && now.duration_since(at) < WHEEL_ACCELERATION_WINDOW
+        });
+        self.last_wheel = Some((now, up));
+        if !self.config.scroll_acceleration {
+            return self.config.scroll_step;
+        }
+        self.wheel_streak = if fast { self.wheel_streak + 1 } else { 0 };
+        let multiplier = (1 + self.wheel_streak / 4).min(MAX_WHEEL_MULTIPLIER);
+        self.config.scroll_step.saturating_mul(multiplier)
+    }
+
+    /// On the first load of a chat opened with unreads, scroll to the oldest unread message
+    ///
+    /// Returns false when there is nothing to jump to, so the caller snaps to the bottom.
+    pub fn jump_to_first_unread(&mut self) -> bool {
+        let Some(read) = self.unread_since.take() else {
+            return false;
+        };
+        // Newest first: the last message newer than the read time is the oldest unread
+        let index = self.messages.iter().rposition(|m| {
+            m.send_state.is_none()
+                && crate::time::parse_teams_datetime(&m.created_date_time)
+                    .is_some_and(|created| created > read)
+        });
+        let Some(index) = index.filter(|&i| i < self.display_limit()) else {
+            return false;
+        };
+        self.first_unread_id = Some(self.messages[index].id.clone());
+        self.messages_dirty = true;
+        self.snap_to_bottom = false;
+        self.pending_scroll_to = Some(index);
+        true
+    }
+
+    /// Jump to the newest message and stay pinned there as new ones arrive
+    pub fn scroll_to_newest(&mut self) {
+        self.snap_to_bottom = true;
+        self.pending_scroll_to = None;
+        // The newest were dropped for older history; the next poll brings them back
+        if self.newest_trimmed {
+            self.reset_history();
+        }
+    }
+
+    /// Jump to the oldest loaded message; older pages still need scrolling up
+    pub fn scroll_to_oldest(&mut self) {
+        self.snap_to_bottom = false;
+        self.pending_scroll_to = None;
+        self.scroll_offset = 0;
+    }
+
+    /// Link of the older page to fetch now, if one was requested and none is in flight
+    pub fn take_older_request(&mut self) -> Option<String> {
+        if !std::mem::take(&mut self.older_requested) || self.loading_older {
+            return None;
+        }
+        let link = self.next_page_link.clone()?;
+        self.loading_older = true;
+        self.messages_dirty = true;
+        Some(link)
+    }
+
+    /// Append an older page below the loaded messages, keeping the view on the
+    /// message that used to be at the top
+    pub fn append_older_page(&mut self, page: MessagePage) {
+        self.messages_dirty = true;
+        self.loading_older = false;
+        self.next_page_link = page.next_link;
+        self.older_pages_loaded += 1;
+
+        let previous_oldest = self.messages.len().checked_sub(1);
+        let known: HashSet<String> = self.messages.iter().map(|m| m.id.clone()).collect();
+        self.messages
+            .extend(page.messages.into_iter().filter(|m| !known.contains(&m.id)));
+
+        // Reading far back: past the cap, drop the newest (messages are newest first)
+        let excess = self
+            .messages
+            .len()
+            .saturating_sub(self.config.max_cached_messages);
+        if excess > 0 {
+            self.messages.drain(..excess);
+            self.newest_trimmed = true;
+        }
+
+        self.pending_scroll_to = previous_oldest.map(|index| index.saturating_sub(excess));
+        self.update_viewable_images();
+        if !self.search_mode && !self.search_query.is_empty() {
+            self.update_search_matches();
+        }
+    }
+
+    /// Record that a chat was deleted server-side; it drops out of the list on the next refresh
+    pub fn mark_chat_unavailable(&mut self, chat_id: &str) {
+        self.unavailable_chats.insert(chat_id.to_string());
+        if self.get_selected_chat().is_some_and(|c| c.id == chat_id) {
+            self.messages_dirty = true;
+            self.loading_messages = false;
+            self.messages.clear();
+        }
+    }
+
+    pub fn selected_chat_unavailable(&self) -> bool {
+        self.get_selected_chat()
+            .is_some_and(|c| self.unavailable_chats.contains(&c.id))
+    }
+
+    /// Recipients to confirm before sending to the selected chat, if it is a group
+    /// bigger than `confirm_send_group_threshold`
+    pub fn send_needs_confirmation(&self) -> Option<usize> {
+        let threshold = self.config.confirm_send_group_threshold;
+        // Members are usually listed without me already, but not when my name
+        // couldn't be worked out when the chat list loaded
+        let recipients = self
+            .get_selected_chat()?
+            .members
+            .iter()
+            .filter(|m| {
+                let by_id = m.user_id.is_some() && m.user_id == self.current_user_id;
+                let by_name = m.display_name.is_some() && m.display_name == self.current_user_name;
+                !by_id && !by_name
+            })
+            .count();
+        (threshold > 0 && recipients > threshold).then_some(recipients)
+    }
+
+    /// Whether the compose box holds more than Teams will accept in one message
+    pub fn compose_too_long(&self) -> bool {
+        self.input_buffer.len() > MAX_MESSAGE_BYTES
+    }
+
+    /// Reset per-chat state after the selection moves, before its messages are loaded
+    pub fn prepare_chat_switch(&mut self) {
+        self.first_unread_id = None;
+        self.unread_since = self
+            .get_selected_chat()
+            .filter(|c| c.is_unread())
+            .and_then(|c| c.last_read_at());
+        self.clear_search();
+        self.switch_draft();
+        self.messages_dirty = true;
+        self.reset_history();
+        self.clear_message_focus();
+        self.reply_to = None;
+        self.compose_importance = Importance::Normal;
+        self.set_messages(Vec::new()); // Clear old messages immediately
+        self.set_loading_messages(true); // After the clear, which marks loading done
+        self.snap_to_bottom = true; // Snap to bottom for new chat
+    }
+
+    pub fn set_messages_error(&mut self, error: String) {
+        self.messages_dirty = true;
+        self.loading_messages = false;
+        self.messages_error = Some(error);
     }
 
     pub fn get_selected_chat(&self) -> Option<&Chat> {
@@ -105,6 +1093,7 @@ impl App {
     }
 
     pub fn next_chat(&mut self) {
+        self.messages_dirty = true;
         if !self.chats.is_empty() {
             self.selected_index = (self.selected_index + 1) % self.chats.len();
             self.image_protocols.clear();
@@ -114,6 +1103,7 @@ impl App {
     }
 
     pub fn previous_chat(&mut self) {
+        self.messages_dirty = true;
         if !self.chats.is_empty() {
             if self.selected_index > 0 {
                 self.selected_index -= 1;
@@ -134,20 +1124,74 @@ impl App {
 
     pub fn prepare_image(&mut self, url: &str, image: image::DynamicImage) {
         if let Some(ref mut picker) = self.image_picker {
-            let protocol = picker.new_resize_protocol(image);
-            self.image_protocols.insert(url.to_string(), protocol);
+            let pixels = u64::from(image.width()) * u64::from(image.height());
+            match picker.new_resize_protocol(image) {
+                Ok(protocol) => {
+                    self.image_protocols
+                        .insert_sized(url.to_string(), protocol, pixels);
+                }
+                Err(e) => log_warn!("Could not prepare {}: {:#}", url, e),
+            }
         }
     }
 
+    /// Query the terminal's graphics support again, e.g. after moving to another
+    /// multiplexer pane, and prepare images afresh for whatever it reports
+    pub fn redetect_image_picker(&mut self) {
+        let picker = match ImagePicker::new() {
+            Ok(picker) => picker,
+            Err(e) => {
+                let font_size = self.config.fallback_font_size();
+                log_warn!("{:#}; assuming a {:?} pixel cell size", e, font_size);
+                ImagePicker::with_fallback_fontsize(font_size)
+            }
+        };
+        self.status = format!("Image support: {}", picker.protocol_name());
+        self.image_picker = Some(picker);
+        self.image_protocols.clear();
+        self.current_image_protocol = None;
+        if let Some(image) = self.viewer_image.take() {
+            self.show_viewer_image(image);
+        }
+    }
+
+    /// Something happened worth redrawing promptly for: input or new messages
+    pub fn note_activity(&mut self) {
+        self.last_activity = Instant::now();
+    }
+
+    /// Whether nothing has happened for `idle_after_secs`
+    pub fn is_idle(&self) -> bool {
+        self.config
+            .idle_after()
+            .is_some_and(|after| self.last_activity.elapsed() >= after)
+    }
+
+    /// Drop cached images once the app has sat idle, unless one is on screen
+    ///
+    /// Anything dropped is downloaded again the next time it is opened.
+    pub fn release_idle_images(&mut self) {
+        if self.last_input.elapsed() < IDLE_IMAGE_RELEASE
+            || self.is_viewing_image()
+            || self.gallery_mode
+        {
+            return;
+        }
+        self.image_protocols.clear();
+        self.image_cache.clear();
+        self.current_image_protocol = None;
+        self.viewer_image = None;
+    }
+
     pub fn has_prepared_image(&self, url: &str) -> bool {
-        self.image_protocols.contains_key(url)
+        self.image_protocols.contains(url)
     }
 
     fn update_viewable_images(&mut self) {
         self.viewable_images.clear();
         for msg in &self.messages {
             for attachment in &msg.attachments {
-                if attachment.is_image() {
+                if attachment.is_image(&self.config.image_extensions) {
                     if let Some(url) = attachment.get_image_url() {
                         self.viewable_images.push(ViewableImage {
                             name: attachment
@@ -155,6 +1199,13 @@ impl App {
                                 .clone()
                                 .unwrap_or_else(|| "image".to_string()),
                             url: url.to_string(),
+                            dimensions: None,
+                            download_url: None,
+                            full_url: attachment
+                                .content_url
+                                .clone()
+                                .filter(|full_url| full_url != url),
+                            full_resolution: false,
                         });
                     }
                 }
@@ -171,10 +1222,94 @@ impl App {
     pub fn stop_viewing_image(&mut self) {
         self.viewing_image = None;
         self.current_image_protocol = None;
+        self.animation = None;
+        self.viewer_image = None;
+        self.image_pan = (0, 0);
         self.loading_image = false;
         self.image_error = None;
     }
 
+    /// Every image and file shared in the loaded messages, newest first
+    pub fn chat_attachments(&self) -> Vec<AttachmentEntry> {
+        let mut entries = Vec::new();
+        for msg in &self.messages {
+            let sender = msg
+                .from
+                .as_ref()
+                .and_then(|f| f.user.as_ref())
+                .and_then(|u| u.display_name.clone())
+                .unwrap_or_else(|| "Unknown".to_string());
+            for attachment in &msg.attachments {
+                let is_image = attachment.is_image(&self.config.image_extensions);
+                // Quoted messages and cards are attachments too, but have no name
+                let Some(name) = attachment
+                    .name
+                    .clone()
+                    .or_else(|| is_image.then(|| "image".to_string()))
+                else {
+                    continue;
+                };
+                let content_type = attachment.content_type.as_deref().unwrap_or("");
+                let kind = match name.rsplit_once('.') {
+                    Some((_, ext)) if content_type == "reference" => ext.to_uppercase(),
+                    _ => content_type.to_string(),
+                };
+                let url = if is_image {
+                    attachment.get_image_url()
+                } else {
+                    attachment.content_url.as_deref()
+                };
+                entries.push(AttachmentEntry {
+                    message_id: msg.id.clone(),
+                    name,
+                    kind,
+                    sender: sender.clone(),
+                    created_date_time: msg.created_date_time.clone(),
+                    is_image,
+                    url: url.map(str::to_string),
+                });
+            }
+        }
+        entries
+    }
+
+    /// Drawer rows of the files (not images) on the message under the J/K cursor
+    pub fn focused_file_rows(&self) -> Vec<usize> {
+        let Some(id) = self.focused_message_id.as_ref() else {
+            return Vec::new();
+        };
+        self.chat_attachments()
+            .iter()
+            .enumerate()
+            .filter(|(_, entry)| &entry.message_id == id && !entry.is_image && entry.url.is_some())
+            .map(|(row, _)| row)
+            .collect()
+    }
+
+    pub fn open_attachments_drawer(&mut self) {
+        if self.chat_attachments().is_empty() {
+            self.status = "No attachments in the loaded messages".to_string();
+        } else {
+            self.attachments_drawer = Some(0);
+        }
+    }
+
+    pub fn move_drawer_selection(&mut self, delta: isize) {
+        let count = self.chat_attachments().len();
+        if let Some(row) = self.attachments_drawer.as_mut() {
+            *row = row
+                .saturating_add_signed(delta)
+                .min(count.saturating_sub(1));
+        }
+    }
+
+    /// Select the image with this URL, so the viewer shows it
+    pub fn select_image_by_url(&mut self, url: &str) -> Option<ViewableImage> {
+        let index = self.viewable_images.iter().position(|img| img.url == url)?;
+        self.selected_image_index = index;
+        self.viewable_images.get(index).cloned()
+    }
+
     pub fn get_current_viewable_image(&self) -> Option<&ViewableImage> {
         if self.viewable_images.is_empty() {
             None
@@ -200,11 +1335,232 @@ impl App {
         }
     }
 
+    /// Open the thumbnail grid, returning the images that still need downloading
+    pub fn open_gallery(&mut self) -> Vec<ViewableImage> {
+        if self.viewable_images.is_empty() {
+            self.status = "No images in this chat".to_string();
+            return Vec::new();
+        }
+        self.gallery_mode = true;
+        // Thumbnails are enough for the grid, whatever the viewer last showed
+        let images: Vec<ViewableImage> = self
+            .viewable_images
+            .iter()
+            .filter(|img| self.can_view_image(&img.url) && !self.has_prepared_image(&img.url))
+            .filter(|img| !self.gallery_pending.contains(&img.url))
+            .map(|img| ViewableImage {
+                full_resolution: false,
+                ..img.clone()
+            })
+            .collect();
+        self.gallery_pending
+            .extend(images.iter().map(|img| img.url.clone()));
+        images
+    }
+
+    /// Move the gallery selection, stopping at the first and last image
+    pub fn move_gallery_selection(&mut self, delta: isize) {
+        let last = self.viewable_images.len().saturating_sub(1);
+        self.selected_image_index = self
+            .selected_image_index
+            .saturating_add_signed(delta)
+            .min(last);
+    }
+
+    /// Whether a downloaded image was requested as a gallery thumbnail
+    pub fn take_gallery_request(&mut self, url: &str) -> bool {
+        self.gallery_pending.remove(url)
+    }
+
+    pub fn is_gallery_pending(&self, url: &str) -> bool {
+        self.gallery_pending.contains(url)
+    }
+
+    /// Keep a thumbnail, at most `THUMBNAIL_PIXELS` a side, for the gallery grid
+    pub fn set_gallery_thumbnail(&mut self, url: &str, thumbnail: DynamicImage) {
+        self.prepare_image(url, thumbnail);
+    }
+
+    /// Reload the image being viewed from its full-resolution original
+    ///
+    /// Returns the image to download, or `None` when the viewer already shows the
+    /// best resolution there is.
+    pub fn view_full_resolution(&mut self) -> Option<ViewableImage> {
+        let mut image = self.viewing_image.clone()?;
+        if image.full_resolution || image.full_url.is_none() {
+            return None;
+        }
+        image.full_resolution = true;
+        image.dimensions = None;
+        image.download_url = None;
+        // Stepping away and back keeps the full-size copy
+        if let Some(entry) = self.viewable_images.get_mut(self.selected_image_index) {
+            if entry.url == image.url {
+                entry.full_resolution = true;
+            }
+        }
+        self.start_viewing_image(image.clone());
+        Some(image)
+    }
+
+    pub fn set_image_dimensions(&mut self, width: u32, height: u32) {
+        if let Some(ref mut image) = self.viewing_image {
+            image.dimensions = Some((width, height));
+        }
+    }
+
+    pub fn set_image_download_url(&mut self, download_url: Option<String>) {
+        if let Some(ref mut image) = self.viewing_image {
+            image.download_url = download_url;
+        }
+    }
+
+    /// The best link to share for the image being viewed: the resolved direct
+    /// download link when there is one, otherwise the attachment URL
+    pub fn viewing_image_link(&self) -> Option<&str> {
+        self.viewing_image
+            .as_ref()
+            .map(|image| image.download_url.as_deref().unwrap_or(&image.url))
+    }
+
     pub fn set_image_protocol(&mut self, protocol: StatefulProtocol) {
         self.current_image_protocol = Some(protocol);
         self.loading_image = false;
         self.image_error = None;
     }
+
+    /// Display a decoded image in the viewer, cropped to the viewport in
+    /// actual-size mode
+    pub fn show_viewer_image(&mut self, image: DynamicImage) {
+        let shown = if self.zoom_fit {
+            image.clone()
+        } else {
+            self.crop_to_viewport(&image)
+        };
+        self.viewer_image = Some(image);
+        if let Some(ref mut picker) = self.image_picker {
+            match picker.new_resize_protocol(shown) {
+                Ok(protocol) => self.set_image_protocol(protocol),
+                Err(e) => self.set_image_error(e.to_string()),
+            }
+        }
+    }
+
+    /// Cut out the part of the image visible at 1:1 scale, clamping the pan
+    /// offset so it never runs past the image edges
+    fn crop_to_viewport(&mut self, image: &DynamicImage) -> DynamicImage {
+        let Some((view_w, view_h)) = self.viewport_pixels() else {
+            return image.clone();
+        };
+        let max_x = image.width().saturating_sub(view_w) as i32;
+        let max_y = image.height().saturating_sub(view_h) as i32;
+        self.image_pan = (
+            self.image_pan.0.clamp(0, max_x),
+            self.image_pan.1.clamp(0, max_y),
+        );
+        image.crop_imm(
+            self.image_pan.0 as u32,
+            self.image_pan.1 as u32,
+            view_w.min(image.width()),
+            view_h.min(image.height()),
+        )
+    }
+
+    /// Size of the image viewport in pixels, once the viewer has been drawn
+    fn viewport_pixels(&self) -> Option<(u32, u32)> {
+        let (font_w, font_h) = self.image_picker.as_ref()?.font_size();
+        let width = u32::from(self.image_viewport.width) * u32::from(font_w);
+        let height = u32::from(self.image_viewport.height) * u32::from(font_h);
+        (width > 0 && height > 0).then_some((width, height))
+    }
+
+    /// Switch between fit-to-popup and actual-size rendering
+    pub fn toggle_zoom(&mut self) {
+        self.zoom_fit = !self.zoom_fit;
+        self.image_pan = (0, 0);
+        if let Some(image) = self.viewer_image.take() {
+            self.show_viewer_image(image);
+        }
+    }
+
+    /// Pan the actual-size view by a quarter of the viewport per step
+    pub fn pan_image(&mut self, dx: i32, dy: i32) {
+        if self.zoom_fit {
+            return;
+        }
+        let Some((view_w, view_h)) = self.viewport_pixels() else {
+            return;
+        };
+        self.image_pan.0 += dx * (view_w as i32 / 4).max(1);
+        self.image_pan.1 += dy * (view_h as i32 / 4).max(1);
+        if let Some(image) = self.viewer_image.take() {
+            self.show_viewer_image(image);
+        }
+    }
+
+    /// Record where the image is drawn; actual-size crops depend on it
+    pub fn set_image_viewport(&mut self, area: Rect) {
+        if self.image_viewport == area {
+            return;
+        }
+        self.image_viewport = area;
+        if !self.zoom_fit {
+            if let Some(image) = self.viewer_image.take() {
+                self.show_viewer_image(image);
+            }
+        }
+    }
+
+    /// Start playing an animated GIF, showing its first frame
+    pub fn start_animation(&mut self, frames: Vec<(DynamicImage, Duration)>) {
+        let Some((first, delay)) = frames.first().cloned() else {
+            return;
+        };
+        if self.image_picker.is_some() {
+            self.show_viewer_image(first);
+            self.animation = Some(GifAnimation {
+                frames,
+                current: 0,
+                next_frame_at: Instant::now() + delay,
+                paused: false,
+            });
+        }
+    }
+
+    /// Advance the animation if the current frame's delay has elapsed
+    pub fn tick_animation(&mut self) {
+        let now = Instant::now();
+        let Some(ref mut animation) = self.animation else {
+            return;
+        };
+        if animation.paused || now < animation.next_frame_at {
+            return;
+        }
+        animation.current = (animation.current + 1) % animation.frames.len();
+        let (frame, delay) = animation.frames[animation.current].clone();
+        animation.next_frame_at = now + delay;
+
+        self.show_viewer_image(frame);
+    }
+
+    /// Time until the next animation frame is due, if an animation is playing
+    pub fn time_to_next_frame(&self) -> Option<Duration> {
+        self.animation
+            .as_ref()
+            .filter(|a| !a.paused)
+            .map(|a| a.next_frame_at.saturating_duration_since(Instant::now()))
+    }
+
+    pub fn toggle_animation_pause(&mut self) {
+        if let Some(ref mut animation) = self.animation {
+            animation.paused = !animation.paused;
+            if !animation.paused {
+                let delay = animation.frames[animation.current].1;
+                animation.next_frame_at = Instant::now() + delay;
+            }
+        }
+    }
+
     pub fn is_viewing_image(&self) -> bool {
         self.viewing_image.is_some()
     }
@@ -213,6 +1569,849 @@ impl App {
         self.viewing_image = Some(image);
         self.loading_image = true;
         self.current_image_protocol = None;
+        self.animation = None;
+        self.viewer_image = None;
+        self.image_pan = (0, 0);
         self.image_error = None;
     }
+
+    fn update_search_matches(&mut self) {
+        self.messages_dirty = true;
+        self.search_matches.clear();
+        if self.search_query.is_empty() {
+            return;
+        }
+
+        let query = self.search_query.to_lowercase();
+        // Only the newest messages are rendered; walk them oldest first to match screen order
+        for (index, msg) in self
+            .messages
+            .iter()
+            .enumerate()
+            .take(self.display_limit())
+            .rev()
+        {
+            let content = msg
+                .body
+                .as_ref()
+                .and_then(|b| b.content.as_deref())
+                .unwrap_or("");
+            if crate::html::html_to_plain(content)
+                .to_lowercase()
+                .contains(&query)
+            {
+                self.search_matches.push(index);
+            }
+        }
+    }
+
+    pub fn start_search(&mut self) {
+        self.clear_search();
+        self.search_mode = true;
+    }
+
+    pub fn submit_search(&mut self) {
+        self.search_mode = false;
+        self.update_search_matches();
+        if self.search_matches.is_empty() {
+            self.status = format!("No matches for \"{}\"", self.search_query);
+        } else {
+            // Start from the newest match, which is closest to the bottom of the pane
+            self.current_match = self.search_matches.len() - 1;
+            self.scroll_to_current_match();
+        }
+    }
+
+    pub fn clear_search(&mut self) {
+        self.messages_dirty = true;
+        self.search_mode = false;
+        self.search_query.clear();
+        self.search_matches.clear();
+        self.current_match = 0;
+    }
+
+    pub fn next_match(&mut self) {
+        if !self.search_matches.is_empty() {
+            self.current_match = (self.current_match + 1) % self.search_matches.len();
+            self.scroll_to_current_match();
+        }
+    }
+
+    pub fn previous_match(&mut self) {
+        if !self.search_matches.is_empty() {
+            if self.current_match > 0 {
+                self.current_match -= 1;
+            } else {
+                self.current_match = self.search_matches.len() - 1;
+            }
+            self.scroll_to_current_match();
+        }
+    }
+
+    fn scroll_to_current_match(&mut self) {
+        if let Some(&index) = self.search_matches.get(self.current_match) {
+            self.snap_to_bottom = false;
+            self.pending_scroll_to = Some(index);
+            self.status = format!(
+                "Match {}/{} for \"{}\" (n/N to cycle, ESC to clear)",
+                self.current_match + 1,
+                self.search_matches.len(),
+                self.search_query
+            );
+        }
+    }
+
+    /// Whether a freshly fetched message list differs from what is displayed
+    pub fn messages_changed(&self, messages: &[Message]) -> bool {
+        // Polled pages don't join up with history once the newest were dropped
+        if self.newest_trimmed {
+            return false;
+        }
+        // Local echoes awaiting confirmation need every refresh to reconcile against
+        if self
+            .messages
+            .iter()
+            .any(|m| matches!(m.send_state, Some(SendState::Sending | SendState::Sent)))
+        {
+            return true;
+        }
+
+        let current: Vec<&Message> = self
+            .messages
+            .iter()
+            .filter(|m| m.send_state.is_none())
+            .collect();
+
+        // With paged history the fetched page only covers the newest part of the list;
+        // edits and reactions show up as a new revision of a message already shown
+        if self.older_pages_loaded > 0 {
+            return current.len() < messages.len()
+                || current
+                    .iter()
+                    .zip(messages)
+                    .any(|(shown, fetched)| !shown.same_revision(fetched));
+        }
+
+        current.len() != messages.len()
+            || current
+                .iter()
+                .zip(messages)
+                .any(|(shown, fetched)| !shown.same_revision(fetched))
+    }
+
+    /// Echo an outgoing message in the current chat before the server confirms it
+    pub fn add_outgoing_message(
+        &mut self,
+        chat_id: &str,
+        content: &str,
+        reply_to: Option<ReplyTo>,
+        importance: Importance,
+    ) -> String {
+        let local_id = format!("local-{}", self.next_local_id);
+        self.next_local_id += 1;
+
+        let message = Message {
+            id: local_id.clone(),
+            message_type: Some("message".to_string()),
+            created_date_time: chrono::Utc::now().to_rfc3339(),
+            last_modified_date_time: None,
+            last_edited_date_time: None,
+            deleted_date_time: None,
+            policy_violation: None,
+            from: Some(MessageFrom {
+                user: Some(MessageUser {
+                    id: None,
+                    display_name: self.current_user_name.clone(),
+                }),
+            }),
+            body: Some(MessageBody {
+                content: Some(content.to_string()),
+                content_type: Some("text".to_string()),
+            }),
+            attachments: Vec::new(),
+            reactions: Vec::new(),
+            mentioned: Vec::new(),
+            event_detail: None,
+            subject: None,
+            importance: Some(importance.as_graph().to_string()),
+            send_state: Some(SendState::Sending),
+        };
+
+        if self.get_selected_chat().map(|c| c.id.as_str()) == Some(chat_id) {
+            self.messages.insert(0, message.clone());
+            self.messages_dirty = true;
+        }
+        self.outgoing.push(OutgoingMessage {
+            chat_id: chat_id.to_string(),
+            reply_to,
+            message,
+            queued_at: Instant::now(),
+        });
+        local_id
+    }
+
+    /// Update the delivery state of a locally echoed message
+    pub fn set_outgoing_state(&mut self, local_id: &str, state: SendState) {
+        self.messages_dirty = true;
+        for outgoing in self.outgoing.iter_mut() {
+            if outgoing.message.id == local_id {
+                outgoing.message.send_state = Some(state);
+            }
+        }
+        for msg in self.messages.iter_mut() {
+            if msg.id == local_id {
+                msg.send_state = Some(state);
+            }
+        }
+    }
+
+    /// Most recent failed outgoing message in the selected chat
+    pub fn last_failed_outgoing(&self) -> Option<OutgoingMessage> {
+        let chat_id = self.get_selected_chat()?.id.clone();
+        self.outgoing
+            .iter()
+            .rev()
+            .find(|o| o.chat_id == chat_id && o.message.send_state == Some(SendState::Failed))
+            .cloned()
+    }
+
+    /// Whether the same text is already on its way to this chat from a send moments ago
+    ///
+    /// Guards against a double Enter (or a send that looks stuck) posting twice.
+    pub fn is_duplicate_send(&self, chat_id: &str, content: &str) -> bool {
+        self.outgoing.iter().any(|o| {
+            o.chat_id == chat_id
+                && o.message.send_state != Some(SendState::Failed)
+                && o.queued_at.elapsed() < DUPLICATE_SEND_WINDOW
+                && o.content() == content
+        })
+    }
+
+    /// Drop local echoes of the selected chat that now appear in the server's message list
+    ///
+    /// Each server message from the current user confirms at most one echo with the same
+    /// text (see `Message::is_echo_of`). Failed echoes are matched too: a send that timed
+    /// out may still have been accepted, and showing both copies would look like a duplicate.
+    fn reconcile_outgoing(&mut self, messages: &[Message]) {
+        let Some(chat_id) = self.get_selected_chat().map(|c| c.id.clone()) else {
+            return;
+        };
+        let me = self.current_user_name.clone();
+        let mut used = vec![false; messages.len()];
+
+        self.outgoing.retain(|outgoing| {
+            if outgoing.chat_id != chat_id {
+                return true;
+            }
+            let (text, sent_at) = outgoing.sent_text();
+            let confirmed = messages
+                .iter()
+                .enumerate()
+                .find(|(i, msg)| !used[*i] && msg.is_echo_of(me.as_deref(), &text, sent_at));
+
+            match confirmed {
+                Some((i, _)) => {
+                    used[i] = true;
+                    false
+                }
+                None => true,
+            }
+        });
+    }
+
+    /// Map a screen position to a (column, content line) position in the messages pane
+    ///
+    /// The position is clamped to the text area so the borders and the padding column
+    /// next to the right border are never part of a selection.
+    pub fn messages_position(&self, x: u16, y: u16) -> (u16, u16) {
+        let area = self.messages_area;
+        let max_col = area.width.saturating_sub(4);
+        let max_row = area.height.saturating_sub(3);
+        let col = x.saturating_sub(area.x + 1).min(max_col);
+        let row = y.saturating_sub(area.y + 1).min(max_row);
+        (col, self.scroll_offset.saturating_add(row))
+    }
+
+    /// Selection normalized to (start, end) in reading order, if anything is selected
+    pub fn selection_range(&self) -> Option<((u16, u16), (u16, u16))> {
+        let (anchor, cursor) = self.selection?;
+        if anchor == cursor {
+            return None;
+        }
+        // Compare by line first, then column
+        if (anchor.1, anchor.0) <= (cursor.1, cursor.0) {
+            Some((anchor, cursor))
+        } else {
+            Some((cursor, anchor))
+        }
+    }
+
+    /// Text covered by the current selection, one trimmed line per rendered line
+    pub fn selected_text(&self) -> Option<String> {
+        let ((start_col, start_line), (end_col, end_line)) = self.selection_range()?;
+        let mut selected = Vec::new();
+        for line_index in start_line..=end_line {
+            let Some(line) = self.message_lines.get(line_index as usize) else {
+                break;
+            };
+            let from = if line_index == start_line {
+                start_col as usize
+            } else {
+                0
+            };
+            let to = if line_index == end_line {
+                end_col as usize + 1
+            } else {
+                usize::MAX
+            };
+            selected.push(slice_columns(line, from, to).trim().to_string());
+        }
+        Some(selected.join("\n"))
+    }
+
+    /// Stash the compose buffer as a draft for its chat and load the selected chat's draft
+    pub fn switch_draft(&mut self) {
+        if let Some(chat_id) = self.draft_chat_id.take() {
+            let draft = std::mem::take(&mut self.input_buffer);
+            if draft.is_empty() {
+                self.ui_state.drafts.remove(&chat_id);
+            } else {
+                self.ui_state.drafts.insert(chat_id, draft);
+            }
+        }
+
+        if let Some(chat_id) = self.get_selected_chat().map(|c| c.id.clone()) {
+            self.input_buffer = self.ui_state.drafts.remove(&chat_id).unwrap_or_default();
+            self.draft_chat_id = Some(chat_id);
+        }
+        self.cursor_pos = self.input_buffer.len();
+    }
+
+    /// Take the composed text, leaving an empty compose box
+    pub fn take_input(&mut self) -> String {
+        self.cursor_pos = 0;
+        std::mem::take(&mut self.input_buffer)
+    }
+
+    /// Type a character at the compose cursor
+    pub fn insert_at_cursor(&mut self, c: char) {
+        self.input_buffer.insert(self.cursor_pos, c);
+        self.cursor_pos += c.len_utf8();
+    }
+
+    /// Backspace: remove the grapheme before the compose cursor
+    pub fn delete_before_cursor(&mut self) {
+        if let Some((index, _)) = self.input_buffer[..self.cursor_pos]
+            .grapheme_indices(true)
+            .next_back()
+        {
+            self.input_buffer.replace_range(index..self.cursor_pos, "");
+            self.cursor_pos = index;
+        }
+    }
+
+    /// Delete: remove the grapheme under the compose cursor
+    pub fn delete_at_cursor(&mut self) {
+        if let Some(grapheme) = self.input_buffer[self.cursor_pos..].graphemes(true).next() {
+            let end = self.cursor_pos + grapheme.len();
+            self.input_buffer.replace_range(self.cursor_pos..end, "");
+        }
+    }
+
+    /// Move the compose cursor one grapheme left (negative) or right (positive)
+    pub fn move_cursor(&mut self, delta: isize) {
+        if delta < 0 {
+            if let Some((index, _)) = self.input_buffer[..self.cursor_pos]
+                .grapheme_indices(true)
+                .next_back()
+            {
+                self.cursor_pos = index;
+            }
+        } else if let Some(grapheme) = self.input_buffer[self.cursor_pos..].graphemes(true).next() {
+            self.cursor_pos += grapheme.len();
+        }
+    }
+
+    /// Persist UI state, including the draft currently being composed
+    pub fn save_ui_state(&mut self) {
+        if let Some(chat_id) = self.draft_chat_id.clone() {
+            if self.input_buffer.is_empty() {
+                self.ui_state.drafts.remove(&chat_id);
+            } else {
+                self.ui_state
+                    .drafts
+                    .insert(chat_id, self.input_buffer.clone());
+            }
+        }
+        self.ui_state.split_ratio = Some(self.split_ratio);
+        self.ui_state.last_chat_id = self.get_selected_chat().map(|c| c.id.clone());
+        // The terminal is still in raw mode, so failures can't be reported usefully
+        let _ = self.ui_state.save();
+    }
+
+    pub fn set_split_ratio(&mut self, ratio: u16) {
+        self.split_ratio = ratio.clamp(SPLIT_RATIO_RANGE.0, SPLIT_RATIO_RANGE.1);
+    }
+
+    /// Move the chat list/messages divider to a screen column while dragging
+    pub fn drag_split_to(&mut self, x: u16) {
+        let area = self.content_area;
+        if area.width == 0 {
+            return;
+        }
+        let offset = u32::from(x.saturating_sub(area.x));
+        let ratio = (offset * 100 / u32::from(area.width)) as u16;
+        self.set_split_ratio(ratio);
+    }
+
+    /// Whether a screen position is on the border between the chat list and messages pane
+    pub fn is_on_split_divider(&self, x: u16, y: u16) -> bool {
+        let list = self.chat_list_area;
+        let within_rows = y >= list.y && y < list.y + list.height;
+        within_rows && (x + 1 == list.x + list.width || x == self.messages_area.x)
+    }
+
+    /// Move the message cursor by `delta` messages (positive = newer), starting
+    /// at the newest message when nothing is focused yet
+    pub fn move_message_focus(&mut self, delta: isize) {
+        let visible = self.messages.len().min(self.display_limit());
+        if visible == 0 {
+            return;
+        }
+        let index = match self
+            .focused_message_id
+            .as_ref()
+            .and_then(|id| self.messages.iter().position(|m| &m.id == id))
+        {
+            // Messages are newest first, so "newer" means a lower index
+            Some(current) => (current as isize - delta).clamp(0, visible as isize - 1) as usize,
+            None => 0,
+        };
+        self.focused_message_id = Some(self.messages[index].id.clone());
+        self.messages_dirty = true;
+        self.pending_scroll_to = Some(index);
+    }
+
+    /// Pin the message under the cursor above the messages pane, or unpin it
+    pub fn toggle_pin_focused(&mut self) {
+        let Some(chat_id) = self.get_selected_chat().map(|c| c.id.clone()) else {
+            return;
+        };
+        let Some(message) = self
+            .focused_message_id
+            .as_ref()
+            .and_then(|id| self.messages.iter().find(|m| &m.id == id))
+        else {
+            self.status = "Move to a message with J/K to pin it".to_string();
+            return;
+        };
+        // A local echo's id goes away when the server's copy replaces it
+        if message.send_state.is_some() {
+            self.status = "This message can be pinned once it has been sent".to_string();
+            return;
+        }
+        let id = message.id.clone();
+        let pinned = self.ui_state.pinned_messages.entry(chat_id).or_default();
+        if let Some(index) = pinned.iter().position(|p| *p == id) {
+            pinned.remove(index);
+            self.status = "Unpinned message".to_string();
+        } else {
+            pinned.push(id);
+            self.status = "Pinned message (p again to unpin)".to_string();
+        }
+        self.ui_state
+            .pinned_messages
+            .retain(|_, ids| !ids.is_empty());
+        if let Err(e) = self.ui_state.save() {
+            log_warn!("Could not save pinned messages: {}", e);
+        }
+        self.messages_dirty = true;
+    }
+
+    /// Pinned messages of the selected chat that are shown, newest pin first,
+    /// and how many more are pinned further back in history
+    pub fn pinned_messages(&self) -> (Vec<&Message>, usize) {
+        let Some(ids) = self
+            .get_selected_chat()
+            .and_then(|chat| self.ui_state.pinned_messages.get(&chat.id))
+        else {
+            return (Vec::new(), 0);
+        };
+        let shown = &self.messages[..self.messages.len().min(self.display_limit())];
+        let loaded: Vec<&Message> = ids
+            .iter()
+            .rev()
+            .filter_map(|id| shown.iter().find(|m| &m.id == id))
+            .collect();
+        let not_loaded = ids.len() - loaded.len();
+        (loaded, not_loaded)
+    }
+
+    /// Pinned message on the bar row at a screen position
+    pub fn pinned_at(&self, x: u16, y: u16) -> Option<&str> {
+        let area = self.pinned_area;
+        if x <= area.x || x + 1 >= area.x + area.width || y <= area.y {
+            return None;
+        }
+        self.pinned_rows
+            .get((y - area.y - 1) as usize)
+            .map(String::as_str)
+    }
+
+    /// Move the cursor to a message and scroll it into view
+    pub fn jump_to_message(&mut self, id: &str) {
+        let Some(index) = self.messages.iter().position(|m| m.id == id) else {
+            return;
+        };
+        self.focused_message_id = Some(id.to_string());
+        self.snap_to_bottom = false;
+        self.pending_scroll_to = Some(index);
+        self.messages_dirty = true;
+    }
+
+    pub fn clear_message_focus(&mut self) {
+        self.messages_dirty = true;
+        self.focused_message_id = None;
+    }
+
+    /// URL under a (column, content line) position in the messages pane
+    pub fn link_at(&self, (col, line): (u16, u16)) -> Option<&str> {
+        self.message_links
+            .iter()
+            .find(|link| {
+                link.line == line as usize && (link.start_col..link.end_col).contains(&col)
+            })
+            .map(|link| link.url.as_str())
+    }
+
+    /// Links in the message under the J/K cursor
+    pub fn focused_message_links(&self) -> Vec<String> {
+        let Some(message) = self
+            .focused_message_id
+            .as_ref()
+            .and_then(|id| self.messages.iter().find(|m| &m.id == id))
+        else {
+            return Vec::new();
+        };
+        let text = message
+            .body
+            .as_ref()
+            .and_then(|b| b.content.as_deref())
+            .map(crate::html::html_to_plain)
+            .unwrap_or_default();
+        crate::html::find_urls(&text)
+            .into_iter()
+            .map(|url| text[url].to_string())
+            .collect()
+    }
+
+    /// Web link to the message under the J/K cursor, once the server has it
+    pub fn focused_message_permalink(&self) -> Option<String> {
+        let chat_id = &self.get_selected_chat()?.id;
+        let message = self
+            .focused_message_id
+            .as_ref()
+            .and_then(|id| self.messages.iter().find(|m| &m.id == id))?;
+        // A local echo has no server id to link to until the server's copy replaces it
+        if message.send_state.is_some() {
+            return None;
+        }
+        Some(crate::api::message_permalink(chat_id, &message.id))
+    }
+
+    /// Expand or collapse the long quotes of the focused message
+    pub fn toggle_quote_expansion(&mut self) {
+        let Some(id) = self.focused_message_id.clone() else {
+            return;
+        };
+        if !self.expanded_quotes.remove(&id) {
+            self.expanded_quotes.insert(id);
+        }
+        self.messages_dirty = true;
+    }
+
+    /// Open the reaction picker on the focused message
+    pub fn open_reaction_picker(&mut self) {
+        let Some(message) = self
+            .focused_message_id
+            .as_ref()
+            .and_then(|id| self.messages.iter().find(|m| &m.id == id))
+        else {
+            self.status = "Select a message with J/K first to react to it".to_string();
+            return;
+        };
+        if message.send_state.is_some() || message.is_system() {
+            self.status = "Can't react to this message".to_string();
+            return;
+        }
+        self.reaction_picker = Some(0);
+    }
+
+    pub fn move_reaction_selection(&mut self, delta: isize) {
+        if let Some(index) = self.reaction_picker.as_mut() {
+            *index = index.saturating_add_signed(delta).min(REACTIONS.len() - 1);
+        }
+    }
+
+    /// Apply the highlighted reaction to the focused message and close the picker
+    ///
+    /// Picking a reaction I already gave takes it back. The message is updated
+    /// straight away; the returned change is for the caller to send to Graph.
+    pub fn apply_reaction_selection(&mut self) -> Option<ReactionChange> {
+        let index = self.reaction_picker.take()?;
+        let chat_id = self.get_selected_chat()?.id.clone();
+        let message_id = self.focused_message_id.clone()?;
+        let user_id = self.current_user_id.clone();
+        let message = self.messages.iter().find(|m| m.id == message_id)?;
+        let (reaction_type, emoji) = REACTIONS[index];
+
+        let remove = message.has_reaction(reaction_type, user_id.as_deref());
+        self.status = if remove {
+            format!("Removed {}", emoji)
+        } else {
+            format!("Reacted {}", emoji)
+        };
+        let change = ReactionChange {
+            chat_id,
+            message_id,
+            reaction_type,
+            remove,
+        };
+        self.set_my_reaction(&change.message_id, reaction_type, !remove);
+        Some(change)
+    }
+
+    /// Undo the local part of a reaction change Graph refused
+    pub fn revert_reaction(&mut self, change: &ReactionChange) {
+        if self.get_selected_chat().map(|c| c.id.as_str()) == Some(change.chat_id.as_str()) {
+            self.set_my_reaction(&change.message_id, change.reaction_type, change.remove);
+        }
+    }
+
+    /// Add or take back my reaction of one type on a loaded message
+    fn set_my_reaction(&mut self, message_id: &str, reaction_type: &str, on: bool) {
+        let user_id = self.current_user_id.clone();
+        let user_name = self.current_user_name.clone();
+        let Some(message) = self.messages.iter_mut().find(|m| m.id == message_id) else {
+            return;
+        };
+        if on == message.has_reaction(reaction_type, user_id.as_deref()) {
+            return;
+        }
+        if on {
+            message.reactions.push(MessageReaction {
+                reaction_type: reaction_type.to_string(),
+                user: Some(MessageFrom {
+                    user: Some(MessageUser {
+                        id: user_id,
+                        display_name: user_name,
+                    }),
+                }),
+            });
+        } else {
+            message
+                .reactions
+                .retain(|r| r.reaction_type != reaction_type || r.user_id() != user_id.as_deref());
+        }
+        self.messages_dirty = true;
+    }
+
+    /// Start composing a reply to the focused message
+    pub fn start_reply(&mut self) {
+        let Some(message) = self
+            .focused_message_id
+            .as_ref()
+            .and_then(|id| self.messages.iter().find(|m| &m.id == id))
+        else {
+            self.status = "Select a message with J/K first to reply to it".to_string();
+            return;
+        };
+        // Local echoes have no server id to reference yet
+        if message.send_state.is_some() {
+            self.status = "Can't reply to a message that hasn't been delivered".to_string();
+            return;
+        }
+        let user = message.from.as_ref().and_then(|f| f.user.as_ref());
+        let preview = message
+            .body
+            .as_ref()
+            .and_then(|b| b.content.as_deref())
+            .map(crate::html::html_to_plain)
+            .unwrap_or_default()
+            .replace('\n', " ");
+
+        self.reply_to = Some(ReplyTo {
+            message_id: message.id.clone(),
+            sender_id: user.and_then(|u| u.id.clone()),
+            sender_name: user
+                .and_then(|u| u.display_name.clone())
+                .unwrap_or_else(|| "Unknown".to_string()),
+            preview: preview.chars().take(120).collect(),
+        });
+        self.input_mode = true;
+    }
+}
+
+/// Take the part of `line` between display columns `from` (inclusive) and `to` (exclusive)
+fn slice_columns(line: &str, from: usize, to: usize) -> String {
+    use unicode_width::UnicodeWidthChar;
+
+    let mut column = 0;
+    let mut out = String::new();
+    for c in line.chars() {
+        if column >= to {
+            break;
+        }
+        if column >= from {
+            out.push(c);
+        }
+        column += c.width().unwrap_or(0);
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::api::ChatMember;
+
+    fn group_chat(member_ids: &[&str]) -> Chat {
+        let mut chat: Chat = serde_json::from_value(serde_json::json!({
+            "id": "group",
+            "topic": null,
+            "chatType": "group",
+            "lastUpdatedDateTime": null,
+        }))
+        .unwrap();
+        chat.members = member_ids
+            .iter()
+            .map(|id| ChatMember {
+                id: None,
+                user_id: Some(id.to_string()),
+                display_name: Some(format!("User {}", id)),
+                email: None,
+            })
+            .collect();
+        chat
+    }
+
+    #[test]
+    fn test_send_confirmation_threshold_excludes_me() {
+        let mut app = App::for_test();
+        app.config.confirm_send_group_threshold = 3;
+        app.current_user_id = Some("me".to_string());
+
+        // Three others plus me is at the threshold, not over it
+        app.chats = vec![group_chat(&["me", "a", "b", "c"])];
+        app.selected_index = 0;
+        assert_eq!(app.send_needs_confirmation(), None);
+
+        app.chats = vec![group_chat(&["me", "a", "b", "c", "d"])];
+        assert_eq!(app.send_needs_confirmation(), Some(4));
+    }
+
+    #[test]
+    fn test_empty_first_page_finishes_loading() {
+        let mut app = App::for_test();
+        app.set_loading_messages(true);
+        app.apply_messages_page(MessagePage {
+            messages: Vec::new(),
+            next_link: None,
+        });
+        assert!(!app.loading_messages);
+
+        // A retry that comes back just as empty clears the error too
+        app.set_messages_error("Failed to load".to_string());
+        app.apply_messages_page(MessagePage {
+            messages: Vec::new(),
+            next_link: None,
+        });
+        assert!(app.messages_error.is_none());
+    }
+
+    #[tokio::test]
+    async fn test_message_from_chat_sync_rings_bell() {
+        use crate::api::{ChatListOptions, ChatSync, Graph};
+        use serde_json::json;
+        use wiremock::matchers::{method, path, query_param};
+        use wiremock::{Mock, MockServer, ResponseTemplate};
+
+        let server = MockServer::start().await;
+        let mount = |route: String, body: serde_json::Value| {
+            Mock::given(method("GET"))
+                .and(path(route))
+                .respond_with(ResponseTemplate::new(200).set_body_json(body))
+        };
+        let message = |id: &str, created: &str| {
+            json!({
+                "id": id,
+                "messageType": "message",
+                "createdDateTime": created,
+                "from": { "user": { "id": "u-bob", "displayName": "Bob Smith" } },
+                "body": { "contentType": "text", "content": "hi" },
+            })
+        };
+        mount(
+            "/v1.0/me/chats/delta".to_string(),
+            json!({
+                "value": [
+                    { "id": "open", "chatType": "oneOnOne", "lastUpdatedDateTime": "2024-05-01T10:00:00Z" },
+                    { "id": "other", "chatType": "oneOnOne", "lastUpdatedDateTime": "2024-05-01T09:00:00Z" },
+                ],
+                "@odata.deltaLink": format!("{}/v1.0/me/chats/delta?$deltatoken=1", server.uri()),
+            }),
+        )
+        .mount(&server)
+        .await;
+        for (chat_id, newest) in [("open", "1"), ("other", "2")] {
+            mount(
+                format!("/v1.0/chats/{}/members", chat_id),
+                json!({ "value": [{ "displayName": "Me Myself" }, { "displayName": "Bob Smith" }] }),
+            )
+            .mount(&server)
+            .await;
+            mount(
+                format!("/v1.0/chats/{}/messages", chat_id),
+                json!({ "value": [message(newest, "2024-05-01T09:00:00Z")] }),
+            )
+            .mount(&server)
+            .await;
+        }
+
+        let mut app = App::for_test();
+        app.set_current_user("Me Myself".to_string());
+        let mut sync = ChatSync::new(
+            Graph::new(format!("{}/v1.0", server.uri())),
+            ChatListOptions::from(&app.config),
+        );
+        app.set_chats(sync.refresh("t").await.unwrap().0);
+        app.selected_index = app.chats.iter().position(|c| c.id == "open").unwrap();
+        assert!(!app.notify_pending);
+
+        // Bob writes in the other chat; the delta only says it changed
+        server.reset().await;
+        Mock::given(method("GET"))
+            .and(path("/v1.0/me/chats/delta"))
+            .and(query_param("$deltatoken", "1"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({ "value": [
+                { "id": "other", "lastUpdatedDateTime": "2024-05-01T11:00:00Z" },
+            ] })))
+            .mount(&server)
+            .await;
+        mount(
+            "/v1.0/chats/other/members".to_string(),
+            json!({ "value": [{ "displayName": "Me Myself" }, { "displayName": "Bob Smith" }] }),
+        )
+        .mount(&server)
+        .await;
+        mount(
+            "/v1.0/chats/other/messages".to_string(),
+            json!({ "value": [message("3", "2024-05-01T11:00:00Z")] }),
+        )
+        .mount(&server)
+        .await;
+
+        app.set_chats(sync.refresh("t").await.unwrap().0);
+        assert!(app.notify_pending);
+    }
 }
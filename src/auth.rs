@@ -1,35 +1,12 @@
-use anyhow::{Context, Result};
+use crate::logging::{log_info, log_warn, redact};
+use anyhow::Result;
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
 use keyring::Entry;
 use serde::{Deserialize, Serialize};
 use std::fs;
 use std::path::PathBuf;
 use std::time::{Duration, SystemTime, UNIX_EPOCH};
 
-#[derive(Debug, Serialize, Deserialize)]
-struct Config {
-    pub client_id: Option<String>,
-    pub tenant_id: Option<String>,
-}
-
-fn get_app_dir() -> Result<PathBuf> {
-    let config_dir = dirs::config_dir().context("Could not find config directory")?;
-    let app_dir = config_dir.join(crate::config::APP_DIR_NAME);
-    fs::create_dir_all(&app_dir)?;
-    Ok(app_dir)
-}
-
-fn load_config() -> Option<Config> {
-    let app_dir = get_app_dir().ok()?;
-    let config_path = app_dir.join("config.json");
-
-    if !config_path.exists() {
-        return None;
-    }
-
-    let json = fs::read_to_string(config_path).ok()?;
-    serde_json::from_str(&json).ok()
-}
-
 fn get_client_id() -> String {
     // 1. Try env var (dotenv should be initialized at startup)
     if let Ok(id) = std::env::var("CLIENT_ID") {
@@ -37,15 +14,14 @@ fn get_client_id() -> String {
     }
 
     // 2. Try config file
-    if let Some(config) = load_config() {
-        if let Some(id) = config.client_id {
-            return id;
-        }
+    if let Some(id) = crate::config::load_config().client_id {
+        return id;
     }
 
-    // 3. Fallback (public sample client) - still warn
-    eprintln!("Warning: CLIENT_ID not found in environment or config, using default fallback.");
-    "d3590ed6-52b3-4102-aeff-aad2292ab01c".to_string()
+    // 3. Fallback (public sample client); the setup wizard normally writes one to config
+    static WARNED: std::sync::Once = std::sync::Once::new();
+    WARNED.call_once(|| log_warn!("No client_id in environment or config; using the default"));
+    crate::setup::DEFAULT_CLIENT_ID.to_string()
 }
 
 fn get_tenant() -> String {
@@ -55,17 +31,95 @@ fn get_tenant() -> String {
     }
 
     // Then config file
-    if let Some(config) = load_config() {
-        if let Some(t) = config.tenant_id {
-            return t;
-        }
+    if let Some(t) = crate::config::load_config().tenant_id {
+        return t;
     }
 
     // Default
     "common".to_string()
 }
 
-const SCOPES: &str = "User.Read Chat.ReadWrite Sites.Read.All Files.Read.All offline_access";
+/// Claims of an access token
+///
+/// The token is only decoded, not validated; it came straight from the sign-in service.
+fn token_claims(access_token: &str) -> Option<serde_json::Value> {
+    let payload = access_token.split('.').nth(1)?;
+    let bytes = URL_SAFE_NO_PAD.decode(payload.trim_end_matches('=')).ok()?;
+    serde_json::from_slice(&bytes).ok()
+}
+
+/// Tenant (`tid` claim) the access token was issued for
+pub fn tenant_id_from_token(access_token: &str) -> Option<String> {
+    token_claims(access_token)?
+        .get("tid")?
+        .as_str()
+        .map(str::to_string)
+}
+
+/// Whether the token may read SharePoint/OneDrive files, which shared images live in
+///
+/// Tenants can withhold `Files.Read.All` and `Sites.Read.All` while granting the rest.
+/// Tokens that can't be decoded (some personal accounts issue opaque ones) are given
+/// the benefit of the doubt.
+pub fn can_read_files(access_token: &str) -> bool {
+    let Some(scopes) = token_claims(access_token).and_then(|claims| {
+        claims
+            .get("scp")
+            .and_then(|scp| scp.as_str())
+            .map(str::to_string)
+    }) else {
+        return true;
+    };
+    scopes.split_whitespace().any(|scope| {
+        scope.eq_ignore_ascii_case("Files.Read.All") || scope.eq_ignore_ascii_case("Sites.Read.All")
+    })
+}
+
+/// Scopes requested when config.json doesn't set `scopes`
+const DEFAULT_SCOPES: &str =
+    "User.Read Chat.ReadWrite Sites.Read.All Files.Read.All offline_access";
+
+/// Scopes the app can't work without: chats, and staying signed in
+const REQUIRED_SCOPES: [&str; 2] = ["Chat.ReadWrite", "offline_access"];
+
+/// Delegated scopes to request: `scopes` from config.json, or the defaults
+fn get_scopes() -> String {
+    let Some(scopes) = crate::config::load_config()
+        .scopes
+        .filter(|scopes| !scopes.trim().is_empty())
+    else {
+        return DEFAULT_SCOPES.to_string();
+    };
+    let missing = missing_required_scopes(&scopes);
+    if !missing.is_empty() {
+        static WARNED: std::sync::Once = std::sync::Once::new();
+        WARNED.call_once(|| {
+            log_warn!(
+                "Configured scopes lack {}; chats may fail to load or sign-in may not last",
+                missing.join(", ")
+            );
+        });
+    }
+    scopes
+}
+
+/// Which of `REQUIRED_SCOPES` a space-separated scope list leaves out
+///
+/// Scopes qualified with a resource (e.g. "https://graph.microsoft.us/Chat.ReadWrite")
+/// count too.
+fn missing_required_scopes(scopes: &str) -> Vec<&'static str> {
+    REQUIRED_SCOPES
+        .into_iter()
+        .filter(|required| {
+            !scopes.split_whitespace().any(|scope| {
+                scope
+                    .rsplit('/')
+                    .next()
+                    .is_some_and(|name| name.eq_ignore_ascii_case(required))
+            })
+        })
+        .collect()
+}
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct DeviceCodeResponse {
@@ -92,11 +146,21 @@ struct TokenErrorResponse {
     error: String,
 }
 
+/// The saved sign-in can't be refreshed any more (no refresh token, or the server
+/// rejected it); only signing in again helps
+#[derive(Debug)]
+pub struct SignInExpired;
+
+impl std::fmt::Display for SignInExpired {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Sign-in expired")
+    }
+}
+
+impl std::error::Error for SignInExpired {}
+
 fn get_token_path() -> Result<PathBuf> {
-    let config_dir = dirs::config_dir().context("Could not find config directory")?;
-    let app_dir = config_dir.join(crate::config::APP_DIR_NAME);
-    fs::create_dir_all(&app_dir)?;
-    Ok(app_dir.join("token.json"))
+    Ok(crate::config::get_app_dir()?.join("token.json"))
 }
 
 fn save_token(token: &TokenResponse) -> Result<()> {
@@ -115,7 +179,13 @@ fn save_token(token: &TokenResponse) -> Result<()> {
     // Write atomically: write to temp then rename
     let tmp_path = path.with_extension("tmp");
     let json = serde_json::to_string_pretty(&token_to_save)?;
-    fs::write(&tmp_path, json)?;
+    // Readable by the owner only: the access token is as good as a password
+    let _ = fs::remove_file(&tmp_path);
+    let mut options = fs::OpenOptions::new();
+    options.write(true).create(true).truncate(true);
+    #[cfg(unix)]
+    std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+    std::io::Write::write_all(&mut options.open(&tmp_path)?, json.as_bytes())?;
     fs::rename(tmp_path, path)?;
     Ok(())
 }
@@ -147,23 +217,37 @@ fn load_token() -> Result<Option<TokenResponse>> {
 }
 
 pub async fn start_device_flow() -> Result<DeviceCodeResponse> {
-    let client = reqwest::Client::new();
+    let client = crate::http::client();
     let tenant = get_tenant();
     let url = format!(
-        "https://login.microsoftonline.com/{}/oauth2/v2.0/devicecode",
+        "{}/{}/oauth2/v2.0/devicecode",
+        crate::cloud::login_base(),
         tenant
     );
 
     let client_id = get_client_id();
-    let params = [("client_id", client_id.as_str()), ("scope", SCOPES)];
+    let scopes = crate::cloud::qualify_scopes(&get_scopes());
+    let params = [
+        ("client_id", client_id.as_str()),
+        ("scope", scopes.as_str()),
+    ];
 
-    let response = client.post(&url).form(&params).send().await?;
+    let response = client
+        .post(&url)
+        .form(&params)
+        .send()
+        .await
+        .map_err(crate::http::request_error)?;
 
     // Check if the request was successful
     if !response.status().is_success() {
         let status = response.status();
         let error_text = response.text().await?;
-        anyhow::bail!("Failed to start device flow ({}): {}", status, error_text);
+        anyhow::bail!(
+            "Failed to start device flow ({}): {}",
+            status,
+            redact(&error_text)
+        );
     }
 
     // Try to parse as DeviceCodeResponse, but show the actual response if it fails
@@ -172,24 +256,67 @@ pub async fn start_device_flow() -> Result<DeviceCodeResponse> {
         Ok(device_code) => Ok(device_code),
         Err(e) => {
             eprintln!("Failed to parse response. Raw response:");
-            eprintln!("{}", response_text);
+            eprintln!("{}", redact(&response_text));
             anyhow::bail!("Failed to parse device code response: {}", e)
         }
     }
 }
 
-pub async fn poll_for_token(device_code: &str, interval: u64) -> Result<TokenResponse> {
-    let client = reqwest::Client::new();
+/// Minimum extra delay after a `slow_down` response (RFC 8628, section 3.5)
+const SLOW_DOWN_INCREMENT_SECS: u64 = 5;
+/// Upper bound for the backed-off polling interval
+const MAX_POLL_INTERVAL_SECS: u64 = 60;
+/// Device codes are typically valid for 15 minutes if the server doesn't say
+const DEFAULT_DEVICE_CODE_LIFETIME_SECS: u64 = 900;
+
+/// Back off after a `slow_down` response: double the interval, but always add
+/// at least the RFC-mandated increment
+fn slowed_interval(interval: u64) -> u64 {
+    (interval * 2)
+        .min(MAX_POLL_INTERVAL_SECS)
+        .max(interval + SLOW_DOWN_INCREMENT_SECS)
+}
+
+/// Up to 500ms of jitter so polling doesn't happen in lockstep with other clients
+fn poll_jitter() -> Duration {
+    let nanos = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.subsec_nanos())
+        .unwrap_or(0);
+    Duration::from_millis(u64::from(nanos % 500))
+}
+
+pub async fn poll_for_token(
+    device_code: &str,
+    interval: u64,
+    expires_in: u64,
+) -> Result<TokenResponse> {
+    let client = crate::http::client();
     let tenant = get_tenant();
     let url = format!(
-        "https://login.microsoftonline.com/{}/oauth2/v2.0/token",
+        "{}/{}/oauth2/v2.0/token",
+        crate::cloud::login_base(),
         tenant
     );
 
     let client_id = get_client_id();
+    let lifetime = if expires_in == 0 {
+        DEFAULT_DEVICE_CODE_LIFETIME_SECS
+    } else {
+        expires_in
+    };
+    let deadline = tokio::time::Instant::now() + Duration::from_secs(lifetime);
+    let mut interval = interval.max(1);
 
     loop {
-        tokio::time::sleep(Duration::from_secs(interval)).await;
+        let wait = Duration::from_secs(interval) + poll_jitter();
+        if tokio::time::Instant::now() + wait > deadline {
+            anyhow::bail!(
+                "Timed out waiting for authentication after {} seconds - the code has expired, please try again",
+                lifetime
+            );
+        }
+        tokio::time::sleep(wait).await;
 
         let params = [
             ("grant_type", "urn:ietf:params:oauth:grant-type:device_code"),
@@ -197,7 +324,12 @@ pub async fn poll_for_token(device_code: &str, interval: u64) -> Result<TokenRes
             ("device_code", device_code),
         ];
 
-        let response = client.post(&url).form(&params).send().await?;
+        let response = client
+            .post(&url)
+            .form(&params)
+            .send()
+            .await
+            .map_err(crate::http::request_error)?;
 
         if response.status().is_success() {
             let mut token = response.json::<TokenResponse>().await?;
@@ -210,6 +342,10 @@ pub async fn poll_for_token(device_code: &str, interval: u64) -> Result<TokenRes
             if error.error == "authorization_pending" {
                 // Continue polling
                 continue;
+            } else if error.error == "slow_down" {
+                // The server wants us to poll less often
+                interval = slowed_interval(interval);
+                continue;
             } else if error.error == "authorization_declined" {
                 anyhow::bail!("User declined authorization");
             } else if error.error == "expired_token" {
@@ -233,12 +369,19 @@ pub async fn get_valid_token_silent() -> Result<String> {
 
         // Try to refresh if we have a refresh token
         if let Some(refresh_token) = token.refresh_token {
-            if let Ok(new_token) = refresh_access_token(&refresh_token).await {
-                return Ok(new_token.access_token);
-            }
+            return match refresh_access_token(&refresh_token).await {
+                Ok(new_token) => {
+                    log_info!("Refreshed access token");
+                    Ok(new_token.access_token)
+                }
+                Err(e) => {
+                    log_warn!("Token refresh failed: {:#}", e);
+                    Err(e.context("Token refresh failed"))
+                }
+            };
         }
     }
-    anyhow::bail!("No valid token found and refresh failed")
+    Err(anyhow::Error::new(SignInExpired).context("No valid token found"))
 }
 
 #[allow(dead_code)]
@@ -252,34 +395,53 @@ pub async fn get_access_token() -> Result<String> {
 }
 
 async fn refresh_access_token(refresh_token: &str) -> Result<TokenResponse> {
-    let client = reqwest::Client::new();
+    let client = crate::http::client();
     let tenant = get_tenant();
     let url = format!(
-        "https://login.microsoftonline.com/{}/oauth2/v2.0/token",
+        "{}/{}/oauth2/v2.0/token",
+        crate::cloud::login_base(),
         tenant
     );
 
     let client_id = get_client_id();
+    let scopes = crate::cloud::qualify_scopes(&get_scopes());
     let params = [
         ("grant_type", "refresh_token"),
         ("client_id", client_id.as_str()),
         ("refresh_token", refresh_token),
-        ("scope", SCOPES),
+        ("scope", scopes.as_str()),
     ];
 
-    let response = client.post(&url).form(&params).send().await?;
+    let response = client
+        .post(&url)
+        .form(&params)
+        .send()
+        .await
+        .map_err(crate::http::request_error)?;
 
     if response.status().is_success() {
         let mut token = response.json::<TokenResponse>().await?;
         let now = SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs();
         token.expires_at = now + token.expires_in;
         save_token(&token)?;
-        Ok(token)
-    } else {
-        // If refresh failed in an unrecoverable way, clear stored refresh token so we don't loop
-        let kr = Entry::new("teams-tui", "refresh-token");
-        let _ = kr.delete_password();
-        anyhow::bail!("Failed to refresh token")
+        return Ok(token);
+    }
+
+    // invalid_grant covers revoked and expired refresh tokens; interaction_required
+    // means a policy wants the user back. Anything else (throttling, an outage) may
+    // pass, so the refresh token is kept for the next try.
+    let status = response.status();
+    let text = response.text().await.unwrap_or_default();
+    let error = serde_json::from_str::<TokenErrorResponse>(&text).map(|e| e.error);
+    match error.as_deref() {
+        Ok("invalid_grant" | "interaction_required") => {
+            // Clear the stored refresh token so we don't loop
+            let kr = Entry::new("teams-tui", "refresh-token");
+            let _ = kr.delete_password();
+            Err(anyhow::Error::new(SignInExpired)
+                .context(format!("Failed to refresh token: {}", redact(&text))))
+        }
+        _ => anyhow::bail!("Failed to refresh token: {} - {}", status, redact(&text)),
     }
 }
 
@@ -297,3 +459,29 @@ pub fn logout() -> Result<()> {
     let _ = kr.delete_password();
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_slowed_interval_backs_off() {
+        // Always at least the RFC 8628 increment
+        assert_eq!(slowed_interval(1), 6);
+        // Doubles once that exceeds the increment
+        assert_eq!(slowed_interval(10), 20);
+        // Capped, but never below interval + increment
+        assert_eq!(slowed_interval(40), 60);
+        assert_eq!(slowed_interval(60), 65);
+    }
+
+    #[test]
+    fn test_missing_required_scopes() {
+        assert!(missing_required_scopes(DEFAULT_SCOPES).is_empty());
+        assert_eq!(
+            missing_required_scopes("User.Read https://graph.microsoft.us/chat.readwrite"),
+            vec!["offline_access"]
+        );
+        assert_eq!(missing_required_scopes(""), REQUIRED_SCOPES.to_vec());
+    }
+}
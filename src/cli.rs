@@ -0,0 +1,24 @@
+//! Command-line arguments
+//!
+//! Parsed before authentication and terminal setup so `--help`, `--version`
+//! and bad flags never leave the terminal in raw mode.
+
+use crate::cloud::Cloud;
+use clap::Parser;
+
+/// A terminal client for Microsoft Teams chats
+#[derive(Debug, Parser)]
+#[command(name = "teams-tui", version, about)]
+pub struct Cli {
+    /// Print the session's diagnostic log to stderr after the TUI exits
+    #[arg(short, long)]
+    pub verbose: bool,
+
+    /// Microsoft cloud to sign in to: commercial, gcchigh, dod or china
+    #[arg(long, value_name = "CLOUD")]
+    pub cloud: Option<Cloud>,
+
+    /// Enable debugging aids, such as H to show the raw HTML of the message under the cursor
+    #[arg(long)]
+    pub debug: bool,
+}
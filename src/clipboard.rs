@@ -0,0 +1,29 @@
+//! System clipboard access for copying text out of the TUI
+
+use anyhow::{Context, Result};
+use std::cell::RefCell;
+
+thread_local! {
+    /// Kept alive for the whole session: on X11/Wayland the copied text is only
+    /// served while the clipboard handle exists.
+    static CLIPBOARD: RefCell<Option<arboard::Clipboard>> = const { RefCell::new(None) };
+}
+
+/// Copy text to the system clipboard
+///
+/// Fails (rather than panicking) when no clipboard is available, e.g. over SSH
+/// or in a headless session.
+pub fn copy_text(text: &str) -> Result<()> {
+    CLIPBOARD.with(|cell| {
+        let mut clipboard = cell.borrow_mut();
+        if clipboard.is_none() {
+            *clipboard = Some(arboard::Clipboard::new().context("No clipboard available")?);
+        }
+        if let Some(clipboard) = clipboard.as_mut() {
+            clipboard
+                .set_text(text.to_string())
+                .context("Failed to copy to clipboard")?;
+        }
+        Ok(())
+    })
+}
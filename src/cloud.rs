@@ -0,0 +1,158 @@
+//! National cloud endpoints
+//!
+//! Tenants in US Government (GCC High, DoD) and China (21Vianet) clouds sign in
+//! and call Graph on different hosts than the commercial cloud. The endpoints are
+//! resolved once at startup from `--cloud` and the config file, then read by
+//! `api`, `auth` and `image_display` for every request.
+
+use std::str::FromStr;
+use std::sync::OnceLock;
+
+/// A Microsoft cloud with its own sign-in and Graph hosts
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Cloud {
+    #[default]
+    Commercial,
+    GccHigh,
+    Dod,
+    China,
+}
+
+impl Cloud {
+    pub fn graph_base_url(self) -> &'static str {
+        match self {
+            Cloud::Commercial => "https://graph.microsoft.com",
+            Cloud::GccHigh => "https://graph.microsoft.us",
+            Cloud::Dod => "https://dod-graph.microsoft.us",
+            Cloud::China => "https://microsoftgraph.chinacloudapi.cn",
+        }
+    }
+
+    pub fn login_base_url(self) -> &'static str {
+        match self {
+            Cloud::Commercial => "https://login.microsoftonline.com",
+            Cloud::GccHigh | Cloud::Dod => "https://login.microsoftonline.us",
+            Cloud::China => "https://login.chinacloudapi.cn",
+        }
+    }
+}
+
+impl FromStr for Cloud {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "commercial" | "global" => Ok(Cloud::Commercial),
+            "gcchigh" | "gcc-high" => Ok(Cloud::GccHigh),
+            "dod" => Ok(Cloud::Dod),
+            "china" => Ok(Cloud::China),
+            other => Err(format!(
+                "unknown cloud '{}' (expected commercial, gcchigh, dod or china)",
+                other
+            )),
+        }
+    }
+}
+
+/// Sign-in and Graph hosts in use for this session, without trailing slashes
+#[derive(Debug, Clone)]
+pub struct Endpoints {
+    pub graph: String,
+    pub login: String,
+}
+
+impl Endpoints {
+    /// `--cloud` wins; otherwise config URLs override the commercial defaults
+    pub fn resolve(
+        cloud: Option<Cloud>,
+        graph_base_url: Option<&str>,
+        login_base_url: Option<&str>,
+    ) -> Self {
+        let trim = |url: &str| url.trim_end_matches('/').to_string();
+        match cloud {
+            Some(cloud) => Self {
+                graph: cloud.graph_base_url().to_string(),
+                login: cloud.login_base_url().to_string(),
+            },
+            None => Self {
+                graph: trim(graph_base_url.unwrap_or(Cloud::Commercial.graph_base_url())),
+                login: trim(login_base_url.unwrap_or(Cloud::Commercial.login_base_url())),
+            },
+        }
+    }
+}
+
+static ENDPOINTS: OnceLock<Endpoints> = OnceLock::new();
+
+/// Fix the endpoints for the session; later calls are ignored
+pub fn init(endpoints: Endpoints) {
+    let _ = ENDPOINTS.set(endpoints);
+}
+
+fn endpoints() -> &'static Endpoints {
+    ENDPOINTS.get_or_init(|| Endpoints::resolve(None, None, None))
+}
+
+/// Graph v1.0 API root, e.g. `https://graph.microsoft.com/v1.0`
+pub fn graph_api_base() -> String {
+    format!("{}/v1.0", endpoints().graph)
+}
+
+/// Graph host, for recognising Graph URLs that need a bearer token
+pub fn graph_host() -> &'static str {
+    endpoints()
+        .graph
+        .split("://")
+        .nth(1)
+        .unwrap_or(&endpoints().graph)
+}
+
+/// Sign-in root, e.g. `https://login.microsoftonline.com`
+pub fn login_base() -> &'static str {
+    &endpoints().login
+}
+
+/// Whether Graph is the commercial one, where scopes need no resource prefix
+pub fn is_commercial_graph() -> bool {
+    endpoints().graph == Cloud::Commercial.graph_base_url()
+}
+
+/// Qualify delegated scopes with the Graph resource outside the commercial cloud
+///
+/// `offline_access` and other OpenID scopes are never prefixed, nor are scopes that
+/// already name their resource.
+pub fn qualify_scopes(scopes: &str) -> String {
+    if is_commercial_graph() {
+        return scopes.to_string();
+    }
+    scopes
+        .split_whitespace()
+        .map(|scope| match scope {
+            "offline_access" | "openid" | "profile" | "email" => scope.to_string(),
+            _ if scope.contains("://") => scope.to_string(),
+            _ => format!("{}/{}", endpoints().graph, scope),
+        })
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_cloud_flag_overrides_config_urls() {
+        let from_config = Endpoints::resolve(None, Some("https://graph.example/"), None);
+        assert_eq!(from_config.graph, "https://graph.example");
+        assert_eq!(from_config.login, "https://login.microsoftonline.com");
+
+        let from_flag = Endpoints::resolve(
+            Some("gcchigh".parse().unwrap()),
+            Some("https://graph.example"),
+            None,
+        );
+        assert_eq!(from_flag.graph, "https://graph.microsoft.us");
+        assert_eq!(from_flag.login, "https://login.microsoftonline.us");
+        assert!("mars".parse::<Cloud>().is_err());
+    }
+}
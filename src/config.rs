@@ -1 +1,545 @@
+//! Application configuration
+//!
+//! Settings are read from `~/.config/teams-tui/config.json`. Every key is
+//! optional; missing keys fall back to their defaults.
+
+use crate::logging::log_warn;
+use anyhow::Result;
+use ratatui::style::Color;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fs;
+use std::path::PathBuf;
+use std::time::Duration;
+
 pub const APP_DIR_NAME: &str = "teams-tui";
+
+/// Environment variable that overrides the config directory
+const CONFIG_DIR_ENV: &str = "TEAMS_TUI_CONFIG_DIR";
+
+/// Cell size assumed when the terminal can't report it (8x12 pixels is common)
+pub const DEFAULT_FONT_SIZE: (u16, u16) = (8, 12);
+
+/// File extensions the `image` crate can decode with its default features
+const DEFAULT_IMAGE_EXTENSIONS: &[&str] = &[
+    "png", "jpg", "jpeg", "gif", "webp", "bmp", "tif", "tiff", "ico", "tga", "qoi", "pnm", "pbm",
+    "pgm", "ppm", "pam", "hdr", "exr", "dds", "ff",
+];
+
+/// Header label and colors for messages when the config doesn't set them
+const DEFAULT_SELF_LABEL: &str = "Me";
+const DEFAULT_SELF_COLOR: &str = "green";
+const DEFAULT_OTHER_COLOR: &str = "cyan";
+
+/// Accepted range for `message_display_limit`
+const MESSAGE_DISPLAY_LIMIT_RANGE: (usize, usize) = (10, 2000);
+
+/// Accepted range for `max_cached_messages`
+const MAX_CACHED_MESSAGES_RANGE: (usize, usize) = (200, 100_000);
+
+/// Accepted range for `message_width_percent`
+const MESSAGE_WIDTH_PERCENT_RANGE: (u16, u16) = (40, 100);
+
+/// Accepted range for `header_gap_minutes`
+const HEADER_GAP_MINUTES_RANGE: (i64, i64) = (1, 1440);
+
+/// Accepted range for `scroll_step`
+const SCROLL_STEP_RANGE: (u16, u16) = (1, 20);
+
+/// Accepted ranges for `active_poll_secs` and `list_poll_secs`
+const ACTIVE_POLL_SECS_RANGE: (u64, u64) = (1, 60);
+const LIST_POLL_SECS_RANGE: (u64, u64) = (5, 900);
+
+/// Accepted ranges for `http_timeout_secs` and `connect_timeout_secs`
+const HTTP_TIMEOUT_SECS_RANGE: (u64, u64) = (5, 300);
+const CONNECT_TIMEOUT_SECS_RANGE: (u64, u64) = (1, 60);
+
+/// Vertical spacing of the messages pane
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Density {
+    /// Blank line between sender groups and headers on their own line
+    #[default]
+    Comfortable,
+    /// No blank lines between groups; headers share the first message line
+    Compact,
+}
+
+/// When a new message in a chat may ring the terminal bell
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum NotifPref {
+    /// Every new message from someone else
+    #[default]
+    All,
+    /// Only messages that @mention me
+    Mentions,
+    /// Never, even when mentioned
+    None,
+}
+
+impl NotifPref {
+    pub const ALL: [NotifPref; 3] = [NotifPref::All, NotifPref::Mentions, NotifPref::None];
+
+    pub fn label(self) -> &'static str {
+        match self {
+            NotifPref::All => "All messages",
+            NotifPref::Mentions => "Only @mentions",
+            NotifPref::None => "Never",
+        }
+    }
+}
+
+impl Density {
+    pub fn toggled(self) -> Self {
+        match self {
+            Density::Comfortable => Density::Compact,
+            Density::Compact => Density::Comfortable,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Config {
+    pub client_id: Option<String>,
+    pub tenant_id: Option<String>,
+    /// Graph root for national clouds (e.g. "https://graph.microsoft.us"); overridden by `--cloud`
+    pub graph_base_url: Option<String>,
+    /// Sign-in root for national clouds (e.g. "https://login.microsoftonline.us")
+    pub login_base_url: Option<String>,
+    /// Space-separated delegated scopes to request instead of the defaults
+    pub scopes: Option<String>,
+    /// Number of newest messages rendered in the messages pane
+    pub message_display_limit: usize,
+    /// Messages fetched per request, up to Graph's limit of 50
+    pub page_size: usize,
+    /// Most messages of the open chat kept in memory while paging through history
+    pub max_cached_messages: usize,
+    /// Widest a message line may get, as a percentage of the messages pane
+    pub message_width_percent: u16,
+    pub density: Density,
+    /// Minutes a sender may pause before their next message gets its own header
+    pub header_gap_minutes: i64,
+    /// Lines the messages pane moves per mouse wheel tick
+    pub scroll_step: u16,
+    /// Scroll further per tick while the wheel is spun quickly
+    pub scroll_acceleration: bool,
+    /// Seconds between checks of the open chat for new messages
+    pub active_poll_secs: u64,
+    /// Seconds between refreshes of the whole chat list
+    pub list_poll_secs: u64,
+    /// Seconds without input or new messages before the UI redraws less often; 0 never
+    pub idle_after_secs: u64,
+    /// Seconds any HTTP request may take before it fails
+    pub http_timeout_secs: u64,
+    /// Seconds to wait for a connection to the server
+    pub connect_timeout_secs: u64,
+    /// Right-align my own messages; when false they share the left column
+    /// with everyone else, marked by a green gutter
+    pub right_align_self: bool,
+    /// Ask before sending to a chat with more members than this; 0 never asks
+    pub confirm_send_group_threshold: usize,
+    /// Show system events (joins, renames, ...) as dim centered lines
+    pub show_system_messages: bool,
+    /// Quotes longer than this many lines are cut until expanded; 0 never cuts
+    pub quote_collapse_lines: usize,
+    /// Show Teams emoji as ":name:" text, for terminals that draw emoji wider or
+    /// narrower than they report and so break right alignment
+    pub emoji_as_text: bool,
+    /// Put a colored initials badge beside each sender header
+    pub show_initials: bool,
+    /// Name shown in the header of my own messages
+    pub self_label: String,
+    /// Header color of my own messages: a name ("green"), an index ("42") or "#rrggbb"
+    pub self_color: String,
+    /// Header color of everyone else's messages
+    pub other_color: String,
+    /// Background behind the text of my own messages, like a chat bubble; none if unset
+    pub self_background: Option<String>,
+    /// Background behind the text of everyone else's messages
+    pub other_background: Option<String>,
+    /// IANA timezone for displayed timestamps (e.g. "Europe/London"); system zone if unset
+    pub timezone: Option<String>,
+    /// Chat to open on launch, whatever was open last time
+    pub default_chat_id: Option<String>,
+    /// Order the chat list by most recent activity instead of Graph's order
+    pub sort_chats_by_recency: bool,
+    /// Members named in the title of a group chat without a topic
+    pub group_name_member_count: usize,
+    /// Shorten those names to "First L"
+    pub abbreviate_group_names: bool,
+    /// Terminal cell size in pixels as "WxH", used when the terminal can't be queried
+    pub font_size: Option<String>,
+    /// Extensions of shared files shown as images, without the dot
+    pub image_extensions: Vec<String>,
+    /// Notification preference per chat id; chats not listed notify for everything
+    pub notification_prefs: HashMap<String, NotifPref>,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            client_id: None,
+            tenant_id: None,
+            graph_base_url: None,
+            login_base_url: None,
+            scopes: None,
+            message_display_limit: 100,
+            page_size: 50,
+            max_cached_messages: 1000,
+            message_width_percent: 90,
+            density: Density::default(),
+            header_gap_minutes: 5,
+            scroll_step: 3,
+            scroll_acceleration: false,
+            active_poll_secs: 1,
+            list_poll_secs: 15,
+            idle_after_secs: 30,
+            http_timeout_secs: 15,
+            connect_timeout_secs: 5,
+            right_align_self: true,
+            confirm_send_group_threshold: 10,
+            show_system_messages: true,
+            quote_collapse_lines: 5,
+            emoji_as_text: false,
+            show_initials: true,
+            self_label: DEFAULT_SELF_LABEL.to_string(),
+            self_color: DEFAULT_SELF_COLOR.to_string(),
+            other_color: DEFAULT_OTHER_COLOR.to_string(),
+            self_background: None,
+            other_background: None,
+            timezone: None,
+            default_chat_id: None,
+            sort_chats_by_recency: true,
+            group_name_member_count: 3,
+            abbreviate_group_names: true,
+            font_size: None,
+            image_extensions: DEFAULT_IMAGE_EXTENSIONS
+                .iter()
+                .map(|ext| ext.to_string())
+                .collect(),
+            notification_prefs: HashMap::new(),
+        }
+    }
+}
+
+impl Config {
+    /// Clamp numeric settings into their supported ranges
+    fn validate(mut self) -> Self {
+        let (min, max) = MESSAGE_DISPLAY_LIMIT_RANGE;
+        self.message_display_limit = self.message_display_limit.clamp(min, max);
+        self.page_size = self.page_size.clamp(1, crate::api::MAX_PAGE_SIZE);
+        let (min, max) = MAX_CACHED_MESSAGES_RANGE;
+        self.max_cached_messages = self
+            .max_cached_messages
+            .clamp(min, max)
+            .max(self.message_display_limit);
+        let (min, max) = MESSAGE_WIDTH_PERCENT_RANGE;
+        self.message_width_percent = self.message_width_percent.clamp(min, max);
+        let (min, max) = HEADER_GAP_MINUTES_RANGE;
+        self.header_gap_minutes = self.header_gap_minutes.clamp(min, max);
+        let (min, max) = SCROLL_STEP_RANGE;
+        self.scroll_step = self.scroll_step.clamp(min, max);
+        let (min, max) = ACTIVE_POLL_SECS_RANGE;
+        self.active_poll_secs = self.active_poll_secs.clamp(min, max);
+        let (min, max) = LIST_POLL_SECS_RANGE;
+        self.list_poll_secs = self.list_poll_secs.clamp(min, max);
+        let (min, max) = HTTP_TIMEOUT_SECS_RANGE;
+        self.http_timeout_secs = self.http_timeout_secs.clamp(min, max);
+        let (min, max) = CONNECT_TIMEOUT_SECS_RANGE;
+        self.connect_timeout_secs = self.connect_timeout_secs.clamp(min, max);
+        self.group_name_member_count = self.group_name_member_count.max(1);
+        if self.self_label.trim().is_empty() {
+            self.self_label = DEFAULT_SELF_LABEL.to_string();
+        }
+        for (key, value, default) in [
+            ("self_color", &mut self.self_color, DEFAULT_SELF_COLOR),
+            ("other_color", &mut self.other_color, DEFAULT_OTHER_COLOR),
+        ] {
+            if value.trim().parse::<Color>().is_err() {
+                log_warn!("Ignoring {} {:?}: not a color", key, value);
+                *value = default.to_string();
+            }
+        }
+        for (key, value) in [
+            ("self_background", &mut self.self_background),
+            ("other_background", &mut self.other_background),
+        ] {
+            if value
+                .as_deref()
+                .is_some_and(|color| color.trim().parse::<Color>().is_err())
+            {
+                log_warn!("Ignoring {} {:?}: not a color", key, value);
+                *value = None;
+            }
+        }
+        // Accept ".PNG" as well as "png"
+        for ext in &mut self.image_extensions {
+            *ext = ext.trim().trim_start_matches('.').to_lowercase();
+        }
+        self.image_extensions.retain(|ext| !ext.is_empty());
+        self
+    }
+
+    /// Take over `other`'s settings that only apply at startup, returning the
+    /// keys that differed so the user can be told to restart for them
+    pub fn keep_startup_settings(&mut self, other: &Config) -> Vec<&'static str> {
+        let mut kept = Vec::new();
+        macro_rules! keep {
+            ($($field:ident),*) => {$(
+                if self.$field != other.$field {
+                    self.$field = other.$field.clone();
+                    kept.push(stringify!($field));
+                }
+            )*};
+        }
+        keep!(
+            client_id,
+            tenant_id,
+            graph_base_url,
+            login_base_url,
+            scopes,
+            http_timeout_secs,
+            connect_timeout_secs,
+            page_size,
+            font_size,
+            sort_chats_by_recency,
+            group_name_member_count,
+            abbreviate_group_names
+        );
+        kept
+    }
+
+    /// Pause after which a sender's next message starts a new group with a header
+    pub fn header_gap(&self) -> chrono::Duration {
+        chrono::Duration::minutes(self.header_gap_minutes)
+    }
+
+    /// How often the open chat is polled for new messages
+    pub fn active_poll_interval(&self) -> Duration {
+        Duration::from_secs(self.active_poll_secs)
+    }
+
+    /// How often the whole chat list is refreshed
+    pub fn list_poll_interval(&self) -> Duration {
+        Duration::from_secs(self.list_poll_secs)
+    }
+
+    /// How long the app waits, untouched, before redrawing less often
+    pub fn idle_after(&self) -> Option<Duration> {
+        (self.idle_after_secs > 0).then(|| Duration::from_secs(self.idle_after_secs))
+    }
+
+    /// Longest any HTTP request may take
+    pub fn http_timeout(&self) -> Duration {
+        Duration::from_secs(self.http_timeout_secs)
+    }
+
+    /// Longest to wait for a connection to the server
+    pub fn connect_timeout(&self) -> Duration {
+        Duration::from_secs(self.connect_timeout_secs)
+    }
+
+    /// Header color of my own messages
+    pub fn self_color(&self) -> Color {
+        self.self_color.trim().parse().unwrap_or(Color::Green)
+    }
+
+    /// Header color of other people's messages
+    pub fn other_color(&self) -> Color {
+        self.other_color.trim().parse().unwrap_or(Color::Cyan)
+    }
+
+    /// Bubble background of my own messages, if any
+    pub fn self_background(&self) -> Option<Color> {
+        self.self_background.as_deref()?.trim().parse().ok()
+    }
+
+    /// Bubble background of other people's messages, if any
+    pub fn other_background(&self) -> Option<Color> {
+        self.other_background.as_deref()?.trim().parse().ok()
+    }
+
+    /// Cell size for image rendering when the terminal query fails
+    ///
+    /// An invalid `font_size` is logged and replaced by the default.
+    pub fn fallback_font_size(&self) -> (u16, u16) {
+        let Some(value) = self.font_size.as_deref() else {
+            return DEFAULT_FONT_SIZE;
+        };
+        parse_font_size(value).unwrap_or_else(|| {
+            log_warn!(
+                "Ignoring font_size {:?}: expected positive \"WxH\" pixels",
+                value
+            );
+            DEFAULT_FONT_SIZE
+        })
+    }
+}
+
+/// Parse a "WxH" cell size such as "10x20"; both dimensions must be positive
+fn parse_font_size(value: &str) -> Option<(u16, u16)> {
+    let (w, h) = value.trim().split_once(['x', 'X'])?;
+    let w: u16 = w.trim().parse().ok()?;
+    let h: u16 = h.trim().parse().ok()?;
+    (w > 0 && h > 0).then_some((w, h))
+}
+
+/// Directory for config, tokens and state, e.g. `~/.config/teams-tui`
+///
+/// `TEAMS_TUI_CONFIG_DIR` takes precedence. Without it, and without a platform
+/// config directory (minimal containers, no `HOME`), a private per-user directory
+/// under the system temp dir is used so the app still starts; settings there may
+/// not survive a reboot.
+pub fn get_app_dir() -> Result<PathBuf> {
+    let app_dir = match std::env::var_os(CONFIG_DIR_ENV).filter(|dir| !dir.is_empty()) {
+        Some(dir) => PathBuf::from(dir),
+        None => match dirs::config_dir() {
+            Some(config_dir) => config_dir.join(APP_DIR_NAME),
+            None => {
+                let fallback = private_temp_dir(APP_DIR_NAME);
+                static WARNED: std::sync::Once = std::sync::Once::new();
+                WARNED.call_once(|| match fallback {
+                    Ok(ref dir) => {
+                        eprintln!(
+                            "⚠ No config directory found; using {} (set {} to choose one)",
+                            dir.display(),
+                            CONFIG_DIR_ENV
+                        );
+                        log_warn!("No config directory found; using {}", dir.display());
+                    }
+                    Err(ref e) => eprintln!(
+                        "⚠ No config directory found and {:#}; sign-in and settings won't be saved (set {} to choose a directory)",
+                        e, CONFIG_DIR_ENV
+                    ),
+                });
+                return fallback;
+            }
+        },
+    };
+    fs::create_dir_all(&app_dir)?;
+    Ok(app_dir)
+}
+
+/// A directory under the temp dir only the current user can use, e.g.
+/// `/tmp/teams-tui-1000`
+///
+/// The temp dir is shared, so on Unix the directory is created with mode 0700
+/// and refused if it already exists as a symlink, belongs to another user or
+/// is open to others: anyone able to plant files there could redirect requests
+/// (and tokens) to a server of their choosing.
+#[cfg(unix)]
+pub fn private_temp_dir(name: &str) -> Result<PathBuf> {
+    use std::os::unix::fs::{DirBuilderExt, MetadataExt, PermissionsExt};
+
+    // SAFETY: getuid has no preconditions and cannot fail
+    let uid = unsafe { libc::getuid() };
+    let dir = std::env::temp_dir().join(format!("{}-{}", name, uid));
+    match fs::DirBuilder::new().mode(0o700).create(&dir) {
+        Ok(()) => {}
+        Err(e) if e.kind() == std::io::ErrorKind::AlreadyExists => {}
+        Err(e) => return Err(e.into()),
+    }
+    let metadata = fs::symlink_metadata(&dir)?;
+    if !metadata.is_dir() || metadata.uid() != uid || metadata.permissions().mode() & 0o077 != 0 {
+        anyhow::bail!("{} is not a private directory owned by you", dir.display());
+    }
+    Ok(dir)
+}
+
+/// The temp dir is per user on other platforms
+#[cfg(not(unix))]
+pub fn private_temp_dir(name: &str) -> Result<PathBuf> {
+    let dir = std::env::temp_dir().join(name);
+    fs::create_dir_all(&dir)?;
+    Ok(dir)
+}
+
+/// Replace top-level keys of config.json, keeping the user's other settings as written
+fn update_config_json(
+    update: impl FnOnce(&mut serde_json::Map<String, serde_json::Value>),
+) -> Result<PathBuf> {
+    let path = get_app_dir()?.join("config.json");
+    let mut json = match fs::read_to_string(&path) {
+        Ok(text) => serde_json::from_str::<serde_json::Value>(&text)?,
+        Err(_) => serde_json::json!({}),
+    };
+    let Some(object) = json.as_object_mut() else {
+        anyhow::bail!("config.json is not a JSON object");
+    };
+    update(object);
+    // Write atomically: write to temp then rename
+    let tmp_path = path.with_extension("tmp");
+    fs::write(&tmp_path, serde_json::to_string_pretty(&json)?)?;
+    fs::rename(tmp_path, &path)?;
+    Ok(path)
+}
+
+/// Write the per-chat notification preferences back to config.json
+///
+/// Only the `notification_prefs` key is replaced.
+pub fn save_notification_prefs(prefs: &HashMap<String, NotifPref>) -> Result<()> {
+    let prefs = serde_json::to_value(prefs)?;
+    update_config_json(|object| {
+        object.insert("notification_prefs".to_string(), prefs);
+    })?;
+    Ok(())
+}
+
+/// Write the setup wizard's answers; `endpoints` are the Graph and sign-in roots
+/// for a national cloud
+pub fn save_setup(
+    client_id: &str,
+    tenant_id: &str,
+    endpoints: Option<(&str, &str)>,
+) -> Result<PathBuf> {
+    update_config_json(|object| {
+        object.insert("client_id".to_string(), client_id.into());
+        object.insert("tenant_id".to_string(), tenant_id.into());
+        if let Some((graph, login)) = endpoints {
+            object.insert("graph_base_url".to_string(), graph.into());
+            object.insert("login_base_url".to_string(), login.into());
+        }
+    })
+}
+
+/// Whether a config.json has been written yet
+pub fn config_exists() -> bool {
+    get_app_dir().is_ok_and(|dir| dir.join("config.json").exists())
+}
+
+/// Load config.json, falling back to defaults if it is missing or invalid
+pub fn load_config() -> Config {
+    reload_config().unwrap_or_else(|e| {
+        log_warn!("Using the default settings: {:#}", e);
+        Config::default().validate()
+    })
+}
+
+/// Read config.json again while running; unlike at startup, a file that no
+/// longer parses is an error rather than a silent reset to the defaults
+pub fn reload_config() -> Result<Config> {
+    let path = get_app_dir()?.join("config.json");
+    if !path.exists() {
+        return Ok(Config::default().validate());
+    }
+    let json = fs::read_to_string(&path)?;
+    let config = serde_json::from_str::<Config>(&json)
+        .map_err(|e| anyhow::anyhow!("{} is not valid: {}", path.display(), e))?;
+    Ok(config.validate())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_font_size() {
+        assert_eq!(parse_font_size("10x20"), Some((10, 20)));
+        assert_eq!(parse_font_size(" 9 X 18 "), Some((9, 18)));
+        assert_eq!(parse_font_size("0x12"), None);
+        assert_eq!(parse_font_size("-8x12"), None);
+        assert_eq!(parse_font_size("8"), None);
+    }
+}
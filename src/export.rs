@@ -0,0 +1,169 @@
+//! Export of the loaded chat history to a Markdown or plain-text file
+
+use crate::api::Message;
+use anyhow::{Context, Result};
+use std::fs;
+use std::path::PathBuf;
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum ExportFormat {
+    Markdown,
+    PlainText,
+}
+
+impl ExportFormat {
+    fn extension(self) -> &'static str {
+        match self {
+            ExportFormat::Markdown => "md",
+            ExportFormat::PlainText => "txt",
+        }
+    }
+}
+
+/// Render messages (newest first, as returned by Graph) oldest first in the given format
+///
+/// `image_extensions` decides which shared files are labelled as images.
+pub fn render_export(
+    chat_name: &str,
+    messages: &[Message],
+    format: ExportFormat,
+    image_extensions: &[String],
+) -> String {
+    let mut out = String::new();
+    match format {
+        ExportFormat::Markdown => out.push_str(&format!("# {}\n\n", chat_name)),
+        ExportFormat::PlainText => out.push_str(&format!("{}\n\n", chat_name)),
+    }
+
+    // Skip local echoes that the server has not confirmed
+    for msg in messages.iter().rev().filter(|m| m.send_state.is_none()) {
+        let sender = msg
+            .from
+            .as_ref()
+            .and_then(|f| f.user.as_ref())
+            .and_then(|u| u.display_name.as_deref())
+            .unwrap_or("Unknown");
+        let timestamp = crate::time::parse_teams_datetime(&msg.created_date_time)
+            .map(|dt| dt.format("%Y-%m-%d %H:%M").to_string())
+            .unwrap_or_else(|| msg.created_date_time.clone());
+        let content = msg
+            .body
+            .as_ref()
+            .and_then(|b| b.content.as_deref())
+            .map(crate::html::html_to_plain)
+            .unwrap_or_default();
+
+        match format {
+            ExportFormat::Markdown => {
+                out.push_str(&format!("**{}** ({}): {}\n", sender, timestamp, content))
+            }
+            ExportFormat::PlainText => {
+                out.push_str(&format!("{} ({}): {}\n", sender, timestamp, content))
+            }
+        }
+
+        for attachment in &msg.attachments {
+            let name = attachment.name.as_deref().unwrap_or("attachment");
+            let url = attachment
+                .content_url
+                .as_deref()
+                .or(attachment.thumbnail_url.as_deref());
+            let label = if attachment.is_image(image_extensions) {
+                "Image"
+            } else {
+                "Attachment"
+            };
+            match (format, url) {
+                (ExportFormat::Markdown, Some(url)) => {
+                    out.push_str(&format!("  - {}: [{}]({})\n", label, name, url))
+                }
+                (ExportFormat::PlainText, Some(url)) => {
+                    out.push_str(&format!("  - {}: {} <{}>\n", label, name, url))
+                }
+                (_, None) => out.push_str(&format!("  - {}: {}\n", label, name)),
+            }
+        }
+        out.push('\n');
+    }
+
+    out
+}
+
+/// Write the chat to a timestamped file in the Downloads directory and return its path
+pub fn export_chat(
+    chat_name: &str,
+    messages: &[Message],
+    format: ExportFormat,
+    image_extensions: &[String],
+) -> Result<PathBuf> {
+    let dir = dirs::download_dir()
+        .or_else(dirs::home_dir)
+        .context("Could not find a Downloads directory")?;
+    fs::create_dir_all(&dir)?;
+
+    // Keep the file name portable
+    let safe_name: String = chat_name
+        .chars()
+        .map(|c| if c.is_alphanumeric() { c } else { '_' })
+        .collect();
+    let file_name = format!(
+        "teams-tui-{}-{}.{}",
+        safe_name,
+        chrono::Local::now().format("%Y%m%d-%H%M%S"),
+        format.extension()
+    );
+    let path = dir.join(file_name);
+
+    let content = render_export(chat_name, messages, format, image_extensions);
+    fs::write(&path, content).with_context(|| format!("Failed to write {}", path.display()))?;
+    Ok(path)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::api::{MessageBody, MessageFrom, MessageUser};
+
+    fn message(id: &str, sender: &str, content: &str) -> Message {
+        Message {
+            id: id.to_string(),
+            message_type: Some("message".to_string()),
+            created_date_time: "2025-11-21T19:11:33Z".to_string(),
+            last_modified_date_time: None,
+            last_edited_date_time: None,
+            deleted_date_time: None,
+            policy_violation: None,
+            from: Some(MessageFrom {
+                user: Some(MessageUser {
+                    id: None,
+                    display_name: Some(sender.to_string()),
+                }),
+            }),
+            body: Some(MessageBody {
+                content: Some(content.to_string()),
+                content_type: Some("html".to_string()),
+            }),
+            attachments: Vec::new(),
+            reactions: Vec::new(),
+            mentioned: Vec::new(),
+            event_detail: None,
+            subject: None,
+            importance: None,
+            send_state: None,
+        }
+    }
+
+    #[test]
+    fn test_render_markdown_oldest_first() {
+        // Graph returns newest first
+        let messages = vec![
+            message("2", "Bob", "<p>second</p>"),
+            message("1", "Alice", "first"),
+        ];
+        let out = render_export("Team", &messages, ExportFormat::Markdown, &[]);
+        let alice = out.find("**Alice** (2025-11-21 19:11): first").unwrap();
+        let bob = out.find("**Bob** (2025-11-21 19:11): second").unwrap();
+        assert!(out.starts_with("# Team"));
+        assert!(alice < bob);
+    }
+}
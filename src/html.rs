@@ -0,0 +1,437 @@
+//! HTML cleanup for Teams message bodies
+//!
+//! Teams delivers message content as HTML. The TUI only needs readable plain
+//! text, so this module strips tags, resolves emoji and entities, and drops
+//! attachment placeholders. Code blocks and quotes are split out first so their
+//! layout survives. It also finds URLs in the cleaned text so they can be styled and
+//! opened.
+
+use std::ops::Range;
+
+/// Escape text typed by the user so it can be sent as an HTML message body
+pub fn escape_html(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+        .replace('\n', "<br>")
+}
+
+/// Convert a Teams HTML message body into trimmed plain text
+pub fn html_to_plain(content: &str) -> String {
+    html_to_plain_with(content, false)
+}
+
+/// `html_to_plain`, optionally writing `<emoji>` tags as ":shortcode:" text for
+/// terminals that draw emoji at a different width than they measure
+pub fn html_to_plain_with(content: &str, emoji_as_text: bool) -> String {
+    // Strip HTML tags and extract text content
+    let mut clean_content = content.to_string();
+
+    // Remove attachment tags (quoted messages) - they're just metadata
+    // Handle both self-closing <attachment ... /> and <attachment ...></attachment>
+    let mut attachment_removed = String::new();
+    let mut remaining = clean_content.as_str();
+
+    while let Some(attach_start) = remaining.find("<attachment") {
+        // Add text before the attachment tag
+        attachment_removed.push_str(&remaining[..attach_start]);
+
+        // Find the end of the opening tag
+        if let Some(tag_end) = remaining[attach_start..].find('>') {
+            // Check if it's self-closing (ends with />)
+            let tag_str = &remaining[attach_start..attach_start + tag_end];
+            if tag_str.ends_with('/') {
+                // Self-closing: <attachment ... />
+                remaining = &remaining[attach_start + tag_end + 1..];
+            } else {
+                // Has closing tag: <attachment ...></attachment>
+                remaining = &remaining[attach_start + tag_end + 1..];
+                // Skip past closing </attachment> tag
+                if let Some(close_start) = remaining.find("</attachment>") {
+                    remaining = &remaining[close_start + 13..]; // 13 = len("</attachment>")
+                }
+            }
+        } else {
+            // Malformed tag, skip the <attachment part
+            attachment_removed.push_str(&remaining[..attach_start + 11]);
+            remaining = &remaining[attach_start + 11..];
+        }
+    }
+
+    // Add remaining text
+    attachment_removed.push_str(remaining);
+    clean_content = attachment_removed;
+
+    // Extract emoji alt text: <emoji ... alt="😅" ...> -> 😅
+    // Process emoji tags by finding them and replacing with alt text
+    let mut emoji_processed = String::new();
+    remaining = clean_content.as_str();
+
+    while let Some(emoji_start) = remaining.find("<emoji") {
+        // Add text before the emoji tag
+        emoji_processed.push_str(&remaining[..emoji_start]);
+
+        // Find the end of the opening tag
+        if let Some(tag_end) = remaining[emoji_start..].find('>') {
+            let tag_str = &remaining[emoji_start..emoji_start + tag_end + 1];
+
+            match emoji_shortcode(tag_str).filter(|_| emoji_as_text) {
+                Some(shortcode) => emoji_processed.push_str(&format!(":{}:", shortcode)),
+                None => {
+                    if let Some(alt) = tag_attr(tag_str, "alt") {
+                        emoji_processed.push_str(alt);
+                    }
+                }
+            }
+
+            // Skip past the opening tag
+            remaining = &remaining[emoji_start + tag_end + 1..];
+
+            // Skip past closing </emoji> tag if present
+            if remaining.starts_with("</emoji") {
+                if let Some(close_end) = remaining.find('>') {
+                    remaining = &remaining[close_end + 1..];
+                }
+            }
+        } else {
+            // Malformed tag, skip the <emoji part
+            emoji_processed.push_str(&remaining[..emoji_start + 6]);
+            remaining = &remaining[emoji_start + 6..];
+        }
+    }
+
+    // Add remaining text
+    emoji_processed.push_str(remaining);
+    clean_content = emoji_processed;
+
+    // Handle HTML entities
+    clean_content = decode_entities(&clean_content);
+
+    // Convert block-level tags to newlines
+    clean_content = clean_content
+        .replace("</p>", "\n")
+        .replace("<p>", "")
+        .replace("</div>", "\n")
+        .replace("<div>", "")
+        .replace("</li>", "\n")
+        .replace("<li>", "")
+        .replace("<br>", "\n")
+        .replace("<br/>", "\n")
+        .replace("<br />", "\n")
+        .replace("</br>", "\n");
+
+    // Remove remaining HTML tags
+    let mut no_html = String::new();
+    let mut inside_tag = false;
+
+    for c in clean_content.chars() {
+        if c == '<' {
+            inside_tag = true;
+        } else if c == '>' {
+            inside_tag = false;
+        } else if !inside_tag {
+            no_html.push(c);
+        }
+    }
+
+    // Clean up whitespace: limit consecutive newlines to 2
+    let mut final_content = String::new();
+    let mut consecutive_newlines = 0;
+
+    for c in no_html.chars() {
+        if c == '\n' {
+            consecutive_newlines += 1;
+            if consecutive_newlines <= 2 {
+                final_content.push(c);
+            }
+        } else {
+            consecutive_newlines = 0;
+            final_content.push(c);
+        }
+    }
+
+    // Trim leading/trailing whitespace
+    final_content.trim().to_string()
+}
+
+/// Value of a double-quoted attribute in an opening tag
+fn tag_attr<'a>(tag: &'a str, name: &str) -> Option<&'a str> {
+    let needle = format!(" {}=\"", name);
+    let start = tag.find(&needle)? + needle.len();
+    let len = tag[start..].find('"')?;
+    Some(&tag[start..start + len])
+}
+
+/// Text name of a Teams `<emoji>` tag, e.g. "smile" or "grinning_face"
+///
+/// Prefers the `id`, minus any leading codepoint such as "1f600_", then the title.
+fn emoji_shortcode(tag: &str) -> Option<String> {
+    let id = tag_attr(tag, "id").map(|id| match id.split_once('_') {
+        Some((prefix, rest)) if prefix.chars().all(|c| c.is_ascii_hexdigit()) => rest,
+        _ => id,
+    });
+    let name = id
+        .or_else(|| tag_attr(tag, "title"))
+        .filter(|name| !name.is_empty())?;
+    Some(name.to_lowercase().replace(' ', "_"))
+}
+
+fn decode_entities(text: &str) -> String {
+    text.replace("&nbsp;", " ")
+        .replace("&amp;", "&")
+        .replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&quot;", "\"")
+        .replace("&#39;", "'")
+        .replace("&apos;", "'")
+        .replace("&#160;", " ")
+        .replace("&nbsp", " ")
+}
+
+/// A run of a message body: prose, a quote, or a code block whose layout must be kept
+#[derive(Debug, PartialEq)]
+pub enum BodyBlock {
+    /// Still HTML; clean it with `html_to_plain`
+    Text(String),
+    /// A `<blockquote>` (often a pasted email history); still HTML like `Text`
+    Quote(String),
+    /// Plain text with its indentation and blank lines intact
+    Code(String),
+}
+
+/// Split a message body into prose, `<blockquote>` quotes and `<pre>` / `<codeblock>`
+/// code blocks
+pub fn split_body_blocks(content: &str) -> Vec<BodyBlock> {
+    let mut blocks = Vec::new();
+    let mut rest = content;
+
+    loop {
+        let next = ["pre", "codeblock", "blockquote"]
+            .into_iter()
+            .filter_map(|tag| {
+                let start = find_open_tag(rest, tag)?;
+                let body_start = start + rest[start..].find('>')? + 1;
+                let (body_end, end) = find_close_tag(&rest[body_start..], tag)?;
+                Some((
+                    start,
+                    body_start,
+                    body_start + body_end,
+                    body_start + end,
+                    tag,
+                ))
+            })
+            .min();
+        let Some((start, body_start, body_end, end, tag)) = next else {
+            break;
+        };
+        if start > 0 {
+            blocks.push(BodyBlock::Text(rest[..start].to_string()));
+        }
+        let body = &rest[body_start..body_end];
+        blocks.push(if tag == "blockquote" {
+            BodyBlock::Quote(body.to_string())
+        } else {
+            BodyBlock::Code(code_to_plain(body))
+        });
+        rest = &rest[end..];
+    }
+
+    if !rest.is_empty() || blocks.is_empty() {
+        blocks.push(BodyBlock::Text(rest.to_string()));
+    }
+    blocks
+}
+
+/// Start and end of the `</tag>` closing an element whose body `text` is, skipping
+/// over nested elements of the same tag (quotes of quotes)
+fn find_close_tag(text: &str, tag: &str) -> Option<(usize, usize)> {
+    let close = format!("</{}>", tag);
+    let mut depth = 0;
+    let mut from = 0;
+    loop {
+        let close_at = from + text[from..].find(&close)?;
+        match find_open_tag(&text[from..], tag).map(|open| from + open) {
+            Some(open_at) if open_at < close_at => {
+                depth += 1;
+                from = open_at + 1;
+            }
+            _ if depth > 0 => {
+                depth -= 1;
+                from = close_at + close.len();
+            }
+            _ => return Some((close_at, close_at + close.len())),
+        }
+    }
+}
+
+/// Position of `<tag>` or `<tag ...>`, but not of a longer tag name such as `<prefix>`
+fn find_open_tag(text: &str, tag: &str) -> Option<usize> {
+    let open = format!("<{}", tag);
+    let mut from = 0;
+    while let Some(offset) = text[from..].find(&open) {
+        let start = from + offset;
+        match text[start + open.len()..].chars().next() {
+            Some('>') | Some(' ') | Some('\t') | Some('\n') => return Some(start),
+            _ => from = start + open.len(),
+        }
+    }
+    None
+}
+
+/// Plain text of a code block: line breaks and spacing are kept as sent
+fn code_to_plain(html: &str) -> String {
+    let with_breaks = html
+        .replace("<br>", "\n")
+        .replace("<br/>", "\n")
+        .replace("<br />", "\n")
+        .replace("</div>", "\n")
+        .replace("</p>", "\n");
+    let mut text = String::new();
+    let mut inside_tag = false;
+    for c in with_breaks.chars() {
+        match c {
+            '<' => inside_tag = true,
+            '>' => inside_tag = false,
+            _ if !inside_tag => text.push(c),
+            _ => {}
+        }
+    }
+    decode_entities(&text)
+        .replace('\t', "    ")
+        .trim_matches('\n')
+        .trim_end()
+        .to_string()
+}
+
+/// Byte ranges of the http(s) URLs in plain text
+///
+/// Trailing sentence punctuation is not part of a URL, and a closing bracket is
+/// only kept when the URL itself opened one (e.g. Wikipedia links).
+pub fn find_urls(text: &str) -> Vec<Range<usize>> {
+    let mut urls = Vec::new();
+    let mut pos = 0;
+
+    while pos < text.len() {
+        let rest = &text[pos..];
+        let Some(offset) = [rest.find("https://"), rest.find("http://")]
+            .into_iter()
+            .flatten()
+            .min()
+        else {
+            break;
+        };
+        let start = pos + offset;
+
+        // Must start a word: "xhttp://" is not a link
+        let at_boundary = text[..start]
+            .chars()
+            .next_back()
+            .is_none_or(|c| !c.is_alphanumeric());
+        let len = text[start..]
+            .find(|c: char| c.is_whitespace() || matches!(c, '<' | '>' | '"'))
+            .unwrap_or(text.len() - start);
+        let mut end = start + len;
+
+        while let Some(last) = text[start..end].chars().next_back() {
+            let unbalanced = |open: char| {
+                text[start..end].matches(open).count() < text[start..end].matches(last).count()
+            };
+            let trim = match last {
+                '.' | ',' | ';' | ':' | '!' | '?' | '\'' | '*' => true,
+                ')' => unbalanced('('),
+                ']' => unbalanced('['),
+                _ => false,
+            };
+            if !trim {
+                break;
+            }
+            end -= last.len_utf8();
+        }
+
+        let scheme_len = if text[start..].starts_with("https://") {
+            8
+        } else {
+            7
+        };
+        if at_boundary && end > start + scheme_len {
+            urls.push(start..end);
+        }
+        pos = (start + len).max(start + 1);
+    }
+
+    urls
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn urls(text: &str) -> Vec<&str> {
+        find_urls(text).into_iter().map(|r| &text[r]).collect()
+    }
+
+    #[test]
+    fn test_find_urls_trims_trailing_punctuation() {
+        assert_eq!(
+            urls("See https://example.com."),
+            vec!["https://example.com"]
+        );
+        assert_eq!(
+            urls("http://a.io/x, https://b.io/y?q=1!"),
+            vec!["http://a.io/x", "https://b.io/y?q=1"]
+        );
+        assert_eq!(
+            urls("(docs: https://example.com/page)"),
+            vec!["https://example.com/page"]
+        );
+        assert_eq!(
+            urls("https://en.wikipedia.org/wiki/Rust_(programming_language)."),
+            vec!["https://en.wikipedia.org/wiki/Rust_(programming_language)"]
+        );
+        assert!(urls("no links, just https:// and xhttps://a.b").is_empty());
+    }
+
+    #[test]
+    fn test_emoji_as_text() {
+        let body = r#"Nice <emoji id="1f600_grinningface" alt="😀" title="Grinning face"></emoji> and <emoji id="like" alt="👍"></emoji>"#;
+        assert_eq!(html_to_plain(body), "Nice 😀 and 👍");
+        assert_eq!(
+            html_to_plain_with(body, true),
+            "Nice :grinningface: and :like:"
+        );
+        // No name to show: keep the emoji
+        assert_eq!(
+            html_to_plain_with(r#"<emoji alt="🙂"></emoji>"#, true),
+            "🙂"
+        );
+    }
+
+    #[test]
+    fn test_split_body_blocks_keeps_indentation() {
+        let body = "<p>Try this:</p><codeblock class=\"Rust\"><code>fn main() {<br>&nbsp;&nbsp;&nbsp;&nbsp;let x = 1 &lt; 2;<br><br>}</code></codeblock><p>ok?</p>";
+        assert_eq!(
+            split_body_blocks(body),
+            vec![
+                BodyBlock::Text("<p>Try this:</p>".to_string()),
+                BodyBlock::Code("fn main() {\n    let x = 1 < 2;\n\n}".to_string()),
+                BodyBlock::Text("<p>ok?</p>".to_string()),
+            ]
+        );
+        assert_eq!(
+            split_body_blocks("<pre>a\n  b</pre>"),
+            vec![BodyBlock::Code("a\n  b".to_string())]
+        );
+        assert_eq!(
+            split_body_blocks("<prefix>x"),
+            vec![BodyBlock::Text("<prefix>x".to_string())]
+        );
+        assert_eq!(
+            split_body_blocks("<p>yes</p><blockquote>a<blockquote>b</blockquote>c</blockquote>"),
+            vec![
+                BodyBlock::Text("<p>yes</p>".to_string()),
+                BodyBlock::Quote("a<blockquote>b</blockquote>c".to_string()),
+            ]
+        );
+    }
+}
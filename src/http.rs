@@ -0,0 +1,119 @@
+//! Shared HTTP client
+//!
+//! Graph and sign-in requests all go through one client, set up at startup
+//! with the configured timeouts. Without them a request stuck on a flaky VPN
+//! never returns, and whatever waits on it shows "Loading…" forever.
+//!
+//! Image and file downloads use a second client without the overall limit, as
+//! a large file on a slow link can take longer than any API call should; they
+//! are cut off only when the connection goes quiet (see `read_body`).
+
+use crate::logging::log_warn;
+use std::sync::OnceLock;
+use std::time::Duration;
+
+const DEFAULT_TIMEOUT: Duration = Duration::from_secs(15);
+const DEFAULT_CONNECT_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// The client, and the overall timeout it was built with for error messages
+static CLIENT: OnceLock<(reqwest::Client, Duration)> = OnceLock::new();
+
+/// The download client, and how long a download may go without receiving data
+static DOWNLOAD_CLIENT: OnceLock<(reqwest::Client, Duration)> = OnceLock::new();
+
+/// Build the shared clients; call once at startup, before any request
+pub fn init(timeout: Duration, connect_timeout: Duration) {
+    let _ = CLIENT.set(build(timeout, connect_timeout));
+    let _ = DOWNLOAD_CLIENT.set(build_download(timeout, connect_timeout));
+}
+
+/// The shared client; cheap to clone, as clones share one connection pool
+pub fn client() -> reqwest::Client {
+    CLIENT
+        .get_or_init(|| build(DEFAULT_TIMEOUT, DEFAULT_CONNECT_TIMEOUT))
+        .0
+        .clone()
+}
+
+/// The client for image and file downloads, which has no overall timeout
+pub fn download_client() -> reqwest::Client {
+    DOWNLOAD_CLIENT
+        .get_or_init(|| build_download(DEFAULT_TIMEOUT, DEFAULT_CONNECT_TIMEOUT))
+        .0
+        .clone()
+}
+
+fn build_download(idle: Duration, connect_timeout: Duration) -> (reqwest::Client, Duration) {
+    let client = reqwest::Client::builder()
+        .connect_timeout(connect_timeout)
+        .build()
+        .unwrap_or_else(|e| {
+            log_warn!("Could not set HTTP timeouts ({}); downloads may hang", e);
+            reqwest::Client::new()
+        });
+    (client, idle)
+}
+
+/// Read a download's body, giving up once no data has arrived for the
+/// configured timeout; a slow but steady transfer is never cut off
+pub async fn read_body(mut response: reqwest::Response) -> anyhow::Result<Vec<u8>> {
+    let idle = DOWNLOAD_CLIENT
+        .get()
+        .map_or(DEFAULT_TIMEOUT, |(_, idle)| *idle);
+    let host = response
+        .url()
+        .host_str()
+        .unwrap_or("the server")
+        .to_string();
+    let mut body = Vec::new();
+    loop {
+        match tokio::time::timeout(idle, response.chunk()).await {
+            Ok(Ok(Some(chunk))) => body.extend_from_slice(&chunk),
+            Ok(Ok(None)) => return Ok(body),
+            Ok(Err(e)) => return Err(request_error(e)),
+            Err(_) => anyhow::bail!(
+                "{} stopped sending data for {}s (check your network or VPN)",
+                host,
+                idle.as_secs()
+            ),
+        }
+    }
+}
+
+fn build(timeout: Duration, connect_timeout: Duration) -> (reqwest::Client, Duration) {
+    let client = reqwest::Client::builder()
+        .timeout(timeout)
+        .connect_timeout(connect_timeout)
+        .build()
+        .unwrap_or_else(|e| {
+            log_warn!("Could not set HTTP timeouts ({}); requests may hang", e);
+            reqwest::Client::new()
+        });
+    (client, timeout)
+}
+
+/// Turn a failed request into an error that says what to check
+///
+/// Timeouts and refused connections name the host, so the status bar shows a
+/// network problem rather than a bare "error sending request".
+pub fn request_error(err: reqwest::Error) -> anyhow::Error {
+    let host = err
+        .url()
+        .and_then(|url| url.host_str())
+        .unwrap_or("the server")
+        .to_string();
+    if err.is_timeout() {
+        let timeout = CLIENT
+            .get()
+            .map_or(DEFAULT_TIMEOUT, |(_, timeout)| *timeout);
+        anyhow::anyhow!(
+            "{} did not respond within {}s (check your network or VPN)",
+            host,
+            timeout.as_secs()
+        )
+    } else if err.is_connect() {
+        anyhow::anyhow!("Could not connect to {} (check your network or VPN)", host)
+    } else {
+        err.into()
+    }
+}
@@ -8,16 +8,18 @@
 
 use anyhow::{Context, Result};
 use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
-use image::DynamicImage;
+use image::codecs::gif::GifDecoder;
+use image::{AnimationDecoder, DynamicImage, ImageFormat};
 use ratatui_image::picker::{Picker, ProtocolType};
 use ratatui_image::protocol::StatefulProtocol;
 use serde::Deserialize;
-use std::collections::HashMap;
-use std::io::{stdout, Write};
+use std::collections::{HashMap, VecDeque};
+use std::io::{stdout, Cursor, Write};
+use std::time::Duration;
 
 /// Image picker for creating image protocols
-/// This is initialized once at startup by querying the terminal for
-/// its capabilities and font size.
+/// This is initialized at startup by querying the terminal for its
+/// capabilities and font size, and again when the user asks to re-detect them.
 pub struct ImagePicker {
     picker: Picker,
 }
@@ -33,11 +35,10 @@ impl ImagePicker {
         Ok(Self { picker })
     }
 
-    /// Create a new ImagePicker with a fallback font size
+    /// Create a new ImagePicker with a fixed font size (pixels per cell)
     /// Use this if the terminal query fails
-    pub fn with_fallback_fontsize() -> Self {
-        // Default font size (8x12 pixels per character cell is common)
-        let picker = Picker::from_fontsize((8, 12));
+    pub fn with_fallback_fontsize(font_size: (u16, u16)) -> Self {
+        let picker = Picker::from_fontsize(font_size);
         Self { picker }
     }
 
@@ -46,6 +47,21 @@ impl ImagePicker {
         self.picker.protocol_type()
     }
 
+    /// Human-readable name of the detected protocol
+    pub fn protocol_name(&self) -> &'static str {
+        match self.picker.protocol_type() {
+            ProtocolType::Kitty => "Kitty",
+            ProtocolType::Sixel => "Sixel",
+            ProtocolType::Iterm2 => "iTerm2",
+            ProtocolType::Halfblocks => "Halfblocks (fallback)",
+        }
+    }
+
+    /// Size of one terminal cell in pixels
+    pub fn font_size(&self) -> (u16, u16) {
+        self.picker.font_size()
+    }
+
     /// Check if the terminal supports any graphics protocol (not just halfblocks)
     pub fn supports_graphics(&self) -> bool {
         matches!(
@@ -56,46 +72,100 @@ impl ImagePicker {
 
     /// Create a new resize protocol for an image
     /// This prepares the image for rendering with automatic resizing
-    pub fn new_resize_protocol(&mut self, image: DynamicImage) -> StatefulProtocol {
-        self.picker.new_resize_protocol(image)
+    ///
+    /// Malformed images can make the encoder panic; that is caught and returned
+    /// as an error so one bad attachment can't take the app down.
+    pub fn new_resize_protocol(&mut self, image: DynamicImage) -> Result<StatefulProtocol> {
+        if image.width() == 0 || image.height() == 0 {
+            anyhow::bail!("Image has no pixels");
+        }
+        // The panic hook stays quiet while encoding: its message would print over the TUI
+        EXPECTING_PANIC.with(|expecting| expecting.set(true));
+        let result = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+            self.picker.new_resize_protocol(image)
+        }));
+        EXPECTING_PANIC.with(|expecting| expecting.set(false));
+        result.map_err(|_| anyhow::anyhow!("Image could not be prepared for display"))
     }
 }
 
-/// Cache for loaded images
-/// This stores downloaded and decoded images to avoid re-downloading
-pub struct ImageCache {
-    /// Map from URL to decoded image
-    images: HashMap<String, DynamicImage>,
+thread_local! {
+    /// Set while this thread runs code whose panics are caught and reported
+    static EXPECTING_PANIC: std::cell::Cell<bool> = const { std::cell::Cell::new(false) };
+}
+
+/// Wrap the panic hook so caught encoder panics don't print; call once at startup
+///
+/// Swapping the hook around each encode instead would race with other threads
+/// and could silence, or lose, their panics.
+pub fn install_panic_hook() {
+    let default = std::panic::take_hook();
+    std::panic::set_hook(Box::new(move |info| {
+        if !EXPECTING_PANIC.with(std::cell::Cell::get) {
+            default(info);
+        }
+    }));
+}
+
+/// Cache of decoded images, or of the terminal protocols made from them
+///
+/// Bounded both by entry count and by total pixels, since a few large photos
+/// take more memory than many thumbnails. When either bound is exceeded the
+/// least recently used entries go first; reading an entry counts as a use.
+pub struct ImageCache<V = DynamicImage> {
+    /// Map from URL to the cached value and its pixel count
+    images: HashMap<String, (V, u64)>,
+    /// URLs from least to most recently used
+    order: VecDeque<String>,
     /// Maximum number of images to cache
     max_size: usize,
+    /// Maximum total pixels across all entries
+    max_pixels: u64,
+    pixels: u64,
 }
 
-impl ImageCache {
-    /// Create a new image cache with the given maximum size
-    pub fn new(max_size: usize) -> Self {
+impl<V> ImageCache<V> {
+    /// Create a new image cache holding at most `max_size` images and `max_pixels` pixels
+    pub fn new(max_size: usize, max_pixels: u64) -> Self {
         Self {
             images: HashMap::new(),
+            order: VecDeque::new(),
             max_size,
+            max_pixels,
+            pixels: 0,
         }
     }
 
-    /// Get an image from the cache
-    pub fn get(&self, url: &str) -> Option<&DynamicImage> {
-        self.images.get(url)
+    /// Get an image from the cache, marking it most recently used
+    pub fn get(&mut self, url: &str) -> Option<&V> {
+        self.touch(url);
+        self.images.get(url).map(|(value, _)| value)
     }
 
-    /// Insert an image into the cache
-    /// Note: When capacity is exceeded, an arbitrary entry is removed (not necessarily oldest)
-    /// since HashMap doesn't maintain insertion order.
-    pub fn insert(&mut self, url: String, image: DynamicImage) {
-        // Simple cache eviction: remove an arbitrary entry if over capacity
-        if self.images.len() >= self.max_size {
-            // Remove an arbitrary entry (HashMap iteration order is not guaranteed)
-            if let Some(key) = self.images.keys().next().cloned() {
-                self.images.remove(&key);
+    /// Get a cached value to display, marking it most recently used; stateful
+    /// protocols need mutable access
+    pub fn get_mut(&mut self, url: &str) -> Option<&mut V> {
+        self.touch(url);
+        self.images.get_mut(url).map(|(value, _)| value)
+    }
+
+    /// Insert a value covering `pixels` pixels, evicting the least recently used
+    /// entries until both bounds hold again
+    ///
+    /// The new entry itself is always kept, even if it alone is over the pixel bound.
+    pub fn insert_sized(&mut self, url: String, value: V, pixels: u64) {
+        self.remove(&url);
+        self.pixels += pixels;
+        self.images.insert(url.clone(), (value, pixels));
+        self.order.push_back(url);
+
+        while self.order.len() > 1
+            && (self.images.len() > self.max_size || self.pixels > self.max_pixels)
+        {
+            if let Some(oldest) = self.order.front().cloned() {
+                self.remove(&oldest);
             }
         }
-        self.images.insert(url, image);
     }
 
     /// Check if an image is in the cache
@@ -104,9 +174,34 @@ impl ImageCache {
     }
 
     /// Clear the cache
-    #[allow(dead_code)]
     pub fn clear(&mut self) {
         self.images.clear();
+        self.order.clear();
+        self.pixels = 0;
+    }
+
+    fn remove(&mut self, url: &str) {
+        if let Some((_, pixels)) = self.images.remove(url) {
+            self.pixels -= pixels;
+            self.order.retain(|key| key != url);
+        }
+    }
+
+    /// Move a cached URL to the most recently used end
+    fn touch(&mut self, url: &str) {
+        if let Some(position) = self.order.iter().position(|key| key == url) {
+            if let Some(key) = self.order.remove(position) {
+                self.order.push_back(key);
+            }
+        }
+    }
+}
+
+impl ImageCache<DynamicImage> {
+    /// Insert a decoded image, sized by its dimensions
+    pub fn insert(&mut self, url: String, image: DynamicImage) {
+        let pixels = u64::from(image.width()) * u64::from(image.height());
+        self.insert_sized(url, image, pixels);
     }
 }
 
@@ -116,6 +211,68 @@ pub fn load_image_from_bytes(bytes: &[u8]) -> Result<DynamicImage> {
     Ok(image)
 }
 
+/// Browsers treat very short GIF frame delays as 100ms; do the same
+const MIN_GIF_FRAME_DELAY: Duration = Duration::from_millis(20);
+const DEFAULT_GIF_FRAME_DELAY: Duration = Duration::from_millis(100);
+
+/// Check whether the bytes are a GIF (which may be animated)
+pub fn is_gif(bytes: &[u8]) -> bool {
+    matches!(image::guess_format(bytes), Ok(ImageFormat::Gif))
+}
+
+/// Decode every frame of a GIF along with how long each frame is shown
+pub fn decode_gif_frames(bytes: &[u8]) -> Result<Vec<(DynamicImage, Duration)>> {
+    let decoder = GifDecoder::new(Cursor::new(bytes)).context("Failed to read GIF")?;
+    let frames = decoder
+        .into_frames()
+        .collect_frames()
+        .context("Failed to decode GIF frames")?;
+
+    Ok(frames
+        .into_iter()
+        .map(|frame| {
+            let (numer, denom) = frame.delay().numer_denom_ms();
+            let delay = Duration::from_millis(u64::from(numer / denom.max(1)));
+            let delay = if delay < MIN_GIF_FRAME_DELAY {
+                DEFAULT_GIF_FRAME_DELAY
+            } else {
+                delay
+            };
+            (DynamicImage::ImageRgba8(frame.into_buffer()), delay)
+        })
+        .collect())
+}
+
+/// What the viewer shows of a downloaded image
+pub enum DecodedFrames {
+    Still(DynamicImage),
+    /// Frames of an animated GIF with how long each is shown
+    Animated(Vec<(DynamicImage, Duration)>),
+}
+
+/// Decode a downloaded image for the viewer; GIFs with more than one frame
+/// animate when `animate` is set, and show their first frame otherwise
+///
+/// Large photos and long GIFs take a while to decode, so call this off the
+/// event loop.
+pub fn decode_for_viewer(bytes: &[u8], animate: bool) -> Result<DecodedFrames> {
+    if animate && is_gif(bytes) {
+        if let Ok(frames) = decode_gif_frames(bytes) {
+            if frames.len() > 1 {
+                return Ok(DecodedFrames::Animated(frames));
+            }
+        }
+    }
+    load_image_from_bytes(bytes).map(DecodedFrames::Still)
+}
+
+/// A downloaded image, decoded, plus the link it was fetched from
+pub struct DecodedImage {
+    /// Decoding can fail after a successful download; the link is still useful then
+    pub frames: Result<DecodedFrames, String>,
+    pub download_url: Option<String>,
+}
+
 /// Response from the Graph API shares endpoint
 #[derive(Debug, Deserialize)]
 struct SharesResponse {
@@ -129,49 +286,70 @@ fn url_to_shares_endpoint(url: &str) -> String {
     // See: https://learn.microsoft.com/en-us/graph/api/shares-get
     let encoded = URL_SAFE_NO_PAD.encode(url);
     format!(
-        "https://graph.microsoft.com/v1.0/shares/u!{}/driveItem",
+        "{}/shares/u!{}/driveItem",
+        crate::cloud::graph_api_base(),
         encoded
     )
 }
 
+/// Whether an image lives in SharePoint/OneDrive and so needs the file scopes to download
+pub fn is_file_storage_url(url: &str) -> bool {
+    let url_lower = url.to_lowercase();
+    url_lower.contains("sharepoint.com") || url_lower.contains("onedrive")
+}
+
+/// Image bytes plus the link they were actually fetched from
+pub struct DownloadedImage {
+    pub bytes: Vec<u8>,
+    /// Pre-authenticated `@microsoft.graph.downloadUrl` for SharePoint/OneDrive
+    /// files; openable without a token until it expires
+    pub download_url: Option<String>,
+}
+
 /// Download an image from a URL using the provided access token
 ///
 /// Teams uses different URL patterns for images:
 /// - Graph API URLs: Direct access with Bearer token
+/// - Anything else: Fetched without a token
 /// - SharePoint/OneDrive URLs: Uses Graph API shares endpoint to get download URL
 /// - Hosted content: Inline images embedded in messages
 pub async fn download_image(
     client: &reqwest::Client,
     url: &str,
     access_token: &str,
-) -> Result<Vec<u8>> {
-    let url_lower = url.to_lowercase();
-
+) -> Result<DownloadedImage> {
     // For SharePoint/OneDrive URLs, use the Graph API shares endpoint
-    if url_lower.contains("sharepoint.com") || url_lower.contains("onedrive") {
+    if is_file_storage_url(url) {
         return download_sharepoint_image(client, url, access_token).await;
     }
 
-    // For other URLs (Graph API, etc.), try direct access with Bearer token
-    let response = client
-        .get(url)
-        .header("Authorization", format!("Bearer {}", access_token))
+    // Graph URLs get the Bearer token; anything else is fetched anonymously so
+    // the token never leaks to a host a message happened to link to
+    let graph = is_graph_url(url);
+    let mut request = client.get(url);
+    if graph {
+        request = request.header("Authorization", format!("Bearer {}", access_token));
+    }
+    let response = request
         .send()
         .await
+        .map_err(crate::http::request_error)
         .context("Failed to send image request")?;
 
     let status = response.status();
 
     if status.is_success() {
-        let bytes = response
-            .bytes()
+        let bytes = crate::http::read_body(response)
             .await
             .context("Failed to read image bytes")?;
-        return Ok(bytes.to_vec());
+        return Ok(DownloadedImage {
+            bytes,
+            download_url: None,
+        });
     }
 
     if status == reqwest::StatusCode::UNAUTHORIZED || status == reqwest::StatusCode::FORBIDDEN {
-        if url_lower.contains("graph.microsoft.com") {
+        if graph {
             anyhow::bail!(
                 "Graph API access denied ({}). Token may have expired - try deleting ~/.config/teams-tui/token.json and restart.",
                 status
@@ -187,12 +365,98 @@ pub async fn download_image(
     anyhow::bail!("Failed to download image: {}", status)
 }
 
+/// Download a shared file of any type, e.g. a PDF or spreadsheet
+///
+/// Goes through the same paths as images: the shares endpoint for SharePoint and
+/// OneDrive links, a Bearer request for Graph URLs, a plain request otherwise.
+pub async fn download_attachment(
+    client: &reqwest::Client,
+    url: &str,
+    access_token: &str,
+) -> Result<Vec<u8>> {
+    Ok(download_image(client, url, access_token).await?.bytes)
+}
+
+/// Write a downloaded attachment to a private download dir under its own name,
+/// so the app that opens it shows a sensible title and picks the right handler
+///
+/// An existing file is never overwritten; a clash gets a " (n)" suffix instead.
+pub fn save_attachment(name: &str, bytes: &[u8]) -> Result<std::path::PathBuf> {
+    let dir = crate::config::private_temp_dir("teams-tui-downloads")
+        .context("Failed to create download directory")?;
+    let file_name = sanitize_file_name(name);
+    let (stem, ext) = match file_name.rsplit_once('.') {
+        Some((stem, ext)) if !stem.is_empty() => (stem, format!(".{}", ext)),
+        _ => (file_name.as_str(), String::new()),
+    };
+
+    for n in 0..1000 {
+        let candidate = if n == 0 {
+            file_name.clone()
+        } else {
+            format!("{} ({}){}", stem, n, ext)
+        };
+        let path = dir.join(candidate);
+        match std::fs::OpenOptions::new()
+            .write(true)
+            .create_new(true)
+            .open(&path)
+        {
+            Ok(mut file) => {
+                file.write_all(bytes).context("Failed to save attachment")?;
+                return Ok(path);
+            }
+            Err(e) if e.kind() == std::io::ErrorKind::AlreadyExists => continue,
+            Err(e) => return Err(e).context("Failed to save attachment"),
+        }
+    }
+    anyhow::bail!("Too many downloads named {}", file_name)
+}
+
+/// Reduce a sender-chosen file name to a plain base name that can't climb out
+/// of the download directory or hide itself
+fn sanitize_file_name(name: &str) -> String {
+    let base = name.rsplit(['/', '\\']).next().unwrap_or_default();
+    let cleaned: String = base
+        .chars()
+        .map(|c| match c {
+            c if c.is_control() => '_',
+            '<' | '>' | ':' | '"' | '|' | '?' | '*' => '_',
+            c => c,
+        })
+        .collect();
+    let cleaned = cleaned
+        .trim()
+        .trim_start_matches('.')
+        .trim_end_matches(['.', ' ']);
+    if cleaned.is_empty() {
+        "attachment".to_string()
+    } else {
+        cleaned.to_string()
+    }
+}
+
+/// Whether a URL points at the Graph host, the only place the access token is sent
+fn is_graph_url(url: &str) -> bool {
+    let Ok(parsed) = reqwest::Url::parse(url) else {
+        return false;
+    };
+    let Some(host) = parsed.host_str() else {
+        return false;
+    };
+    let authority = match parsed.port() {
+        Some(port) => format!("{}:{}", host, port),
+        None => host.to_string(),
+    };
+    authority.eq_ignore_ascii_case(crate::cloud::graph_host())
+}
+
 /// Download an image from SharePoint/OneDrive using the Graph API shares endpoint
 async fn download_sharepoint_image(
     client: &reqwest::Client,
     sharepoint_url: &str,
     access_token: &str,
-) -> Result<Vec<u8>> {
+) -> Result<DownloadedImage> {
     // Step 1: Use the shares endpoint to get the driveItem with download URL
     let shares_url = url_to_shares_endpoint(sharepoint_url);
 
@@ -201,6 +465,7 @@ async fn download_sharepoint_image(
         .header("Authorization", format!("Bearer {}", access_token))
         .send()
         .await
+        .map_err(crate::http::request_error)
         .context("Failed to query Graph API shares endpoint")?;
 
     let status = response.status();
@@ -232,6 +497,7 @@ async fn download_sharepoint_image(
         .get(&download_url)
         .send()
         .await
+        .map_err(crate::http::request_error)
         .context("Failed to download file from SharePoint")?;
 
     if !file_response.status().is_success() {
@@ -241,25 +507,19 @@ async fn download_sharepoint_image(
         );
     }
 
-    let bytes = file_response
-        .bytes()
+    let bytes = crate::http::read_body(file_response)
         .await
         .context("Failed to read file bytes")?;
 
-    Ok(bytes.to_vec())
+    Ok(DownloadedImage {
+        bytes,
+        download_url: Some(download_url),
+    })
 }
 
 /// Print information about the detected image protocol
 pub fn print_protocol_info(picker: &ImagePicker) {
-    let protocol = picker.protocol_type();
-    let protocol_name = match protocol {
-        ProtocolType::Kitty => "Kitty",
-        ProtocolType::Sixel => "Sixel",
-        ProtocolType::Iterm2 => "iTerm2",
-        ProtocolType::Halfblocks => "Halfblocks (fallback)",
-    };
-
-    println!("Image protocol: {}", protocol_name);
+    println!("Image protocol: {}", picker.protocol_name());
     if picker.supports_graphics() {
         println!("✓ Full graphics support available");
     } else {
@@ -274,7 +534,7 @@ mod tests {
 
     #[test]
     fn test_image_cache_basic() {
-        let mut cache = ImageCache::new(2);
+        let mut cache = ImageCache::new(2, u64::MAX);
 
         // Create a simple 1x1 test image
         let img = DynamicImage::new_rgb8(1, 1);
@@ -287,16 +547,62 @@ mod tests {
 
     #[test]
     fn test_image_cache_eviction() {
-        let mut cache = ImageCache::new(2);
+        let mut cache = ImageCache::new(2, u64::MAX);
         let img = DynamicImage::new_rgb8(1, 1);
 
         cache.insert("img1".to_string(), img.clone());
         cache.insert("img2".to_string(), img.clone());
         assert_eq!(cache.images.len(), 2);
 
-        // This should trigger eviction
+        // This should evict the least recently used entry, img1
         cache.insert("img3".to_string(), img.clone());
         assert_eq!(cache.images.len(), 2);
+        assert!(!cache.contains("img1"));
+        assert!(cache.contains("img2") && cache.contains("img3"));
+    }
+
+    #[test]
+    fn test_image_cache_access_protects_from_eviction() {
+        let mut cache = ImageCache::new(2, u64::MAX);
+        let img = DynamicImage::new_rgb8(1, 1);
+
+        cache.insert("hot".to_string(), img.clone());
+        cache.insert("cold".to_string(), img.clone());
+        // Reading "hot" leaves "cold" as the least recently used
+        assert!(cache.get("hot").is_some());
+        cache.insert("new".to_string(), img.clone());
+        assert!(cache.contains("hot") && cache.contains("new"));
+        assert!(!cache.contains("cold"));
+
+        // Displaying through get_mut counts too
+        assert!(cache.get_mut("hot").is_some());
+        cache.insert("newer".to_string(), img.clone());
+        assert!(cache.contains("hot") && !cache.contains("new"));
+    }
+
+    #[test]
+    fn test_image_cache_evicts_by_pixels() {
+        // Room for 300 pixels: three 10x10 images
+        let mut cache = ImageCache::new(10, 300);
+        let small = DynamicImage::new_rgb8(10, 10);
+        for url in ["a", "b", "c", "d", "e"] {
+            cache.insert(url.to_string(), small.clone());
+        }
+
+        // Always the two least recently used, whatever order the HashMap keeps
+        assert!(!cache.contains("a") && !cache.contains("b"));
+        assert!(cache.contains("c") && cache.contains("d") && cache.contains("e"));
+
+        // Re-inserting counts as a use
+        cache.insert("c".to_string(), small.clone());
+        cache.insert("f".to_string(), small.clone());
+        assert!(!cache.contains("d") && cache.contains("c"));
+
+        // One large image pushes out everything older, but is kept itself
+        cache.insert("big".to_string(), DynamicImage::new_rgb8(20, 20));
+        assert!(cache.contains("big"));
+        assert_eq!(cache.images.len(), 1);
+        assert_eq!(cache.pixels, 400);
     }
 
     #[test]
@@ -306,4 +612,14 @@ mod tests {
         let result = load_image_from_bytes(png_bytes);
         assert!(result.is_ok());
     }
+
+    #[test]
+    fn test_sanitize_file_name() {
+        assert_eq!(sanitize_file_name("report.pdf"), "report.pdf");
+        assert_eq!(sanitize_file_name("../../.bashrc"), "bashrc");
+        assert_eq!(sanitize_file_name("..\\evil\\run.bat"), "run.bat");
+        assert_eq!(sanitize_file_name("a\nb:c.txt"), "a_b_c.txt");
+        assert_eq!(sanitize_file_name(".."), "attachment");
+        assert_eq!(sanitize_file_name(""), "attachment");
+    }
 }
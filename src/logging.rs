@@ -0,0 +1,185 @@
+//! Diagnostic log file
+//!
+//! While the TUI is running the terminal is in raw mode, so `eprintln!` output
+//! is lost. Errors worth diagnosing are appended to
+//! `~/.config/teams-tui/teams-tui.log` instead. With `--verbose` the lines
+//! logged during the session are also printed to stderr after the TUI exits.
+
+use crate::config::get_app_dir;
+use anyhow::Result;
+use std::fmt;
+use std::fs::{self, File, OpenOptions};
+use std::io::Write;
+use std::path::PathBuf;
+use std::sync::Mutex;
+
+const LOG_FILE_NAME: &str = "teams-tui.log";
+
+/// Start a fresh file once the log grows past this size, keeping one old copy
+const MAX_LOG_BYTES: u64 = 1024 * 1024;
+
+#[derive(Debug, Clone, Copy)]
+pub enum Level {
+    Error,
+    Warn,
+    Info,
+}
+
+impl fmt::Display for Level {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Level::Error => "ERROR",
+            Level::Warn => "WARN",
+            Level::Info => "INFO",
+        })
+    }
+}
+
+struct Logger {
+    file: Option<File>,
+    /// Session lines kept for `--verbose`
+    mirror: Option<Vec<String>>,
+}
+
+static LOGGER: Mutex<Logger> = Mutex::new(Logger {
+    file: None,
+    mirror: None,
+});
+
+/// Open the log file; logging stays a no-op if this fails
+pub fn init(verbose: bool) -> Result<PathBuf> {
+    let path = get_app_dir()?.join(LOG_FILE_NAME);
+    if fs::metadata(&path).is_ok_and(|m| m.len() > MAX_LOG_BYTES) {
+        let _ = fs::rename(&path, path.with_extension("log.old"));
+    }
+    let file = OpenOptions::new().create(true).append(true).open(&path)?;
+
+    if let Ok(mut logger) = LOGGER.lock() {
+        logger.file = Some(file);
+        if verbose {
+            logger.mirror = Some(Vec::new());
+        }
+    }
+    Ok(path)
+}
+
+/// Append one line to the log; use the `log_*!` macros instead of calling this
+pub fn write(level: Level, args: fmt::Arguments) {
+    let Ok(mut logger) = LOGGER.lock() else {
+        return;
+    };
+    let line = format!(
+        "{} {:<5} {}",
+        chrono::Local::now().format("%Y-%m-%d %H:%M:%S"),
+        level,
+        redact(&args.to_string())
+    );
+    if let Some(file) = logger.file.as_mut() {
+        let _ = writeln!(file, "{}", line);
+    }
+    if let Some(mirror) = logger.mirror.as_mut() {
+        mirror.push(line);
+    }
+}
+
+/// Mask credentials before text reaches the log, stderr or the status bar
+///
+/// Catches JWTs, the word after "Bearer", and values of `*token` fields in JSON
+/// or form bodies (e.g. `"refresh_token":"..."`), which Graph and the sign-in
+/// endpoints can echo back in error responses.
+pub fn redact(text: &str) -> String {
+    let is_token_char = |c: char| c.is_ascii_alphanumeric() || "-_.~+/".contains(c);
+    // Words are made of ASCII token characters only, so byte slicing is safe
+    let ends_with = |word: &str, suffix: &str| {
+        word.len() >= suffix.len() && word[word.len() - suffix.len()..].eq_ignore_ascii_case(suffix)
+    };
+    let mut out = String::with_capacity(text.len());
+    let mut rest = text;
+    let mut previous = "";
+    while let Some(start) = rest.find(is_token_char) {
+        let end = rest[start..]
+            .find(|c: char| !is_token_char(c))
+            .map_or(rest.len(), |len| start + len);
+        let word = &rest[start..end];
+        // Only the previous word and what separates it from this one matter
+        let gap = &rest[..start];
+
+        let is_jwt = word.starts_with("eyJ") && word.matches('.').count() >= 2;
+        let after_bearer = gap.chars().all(char::is_whitespace) && ends_with(previous, "bearer");
+        let after_token_key = !gap.is_empty()
+            && gap.chars().all(|c| "\"' :=".contains(c))
+            && ends_with(previous, "token");
+
+        out.push_str(gap);
+        if is_jwt || after_bearer || after_token_key {
+            out.push_str("[REDACTED]");
+        } else {
+            out.push_str(word);
+        }
+        previous = word;
+        rest = &rest[end..];
+    }
+    out.push_str(rest);
+    out
+}
+
+/// Print the session's log lines to stderr (only with `--verbose`)
+pub fn dump_to_stderr() {
+    let Ok(mut logger) = LOGGER.lock() else {
+        return;
+    };
+    if let Some(lines) = logger.mirror.take() {
+        for line in lines {
+            eprintln!("{}", line);
+        }
+    }
+}
+
+macro_rules! log_error {
+    ($($arg:tt)*) => {
+        $crate::logging::write($crate::logging::Level::Error, format_args!($($arg)*))
+    };
+}
+
+macro_rules! log_warn {
+    ($($arg:tt)*) => {
+        $crate::logging::write($crate::logging::Level::Warn, format_args!($($arg)*))
+    };
+}
+
+macro_rules! log_info {
+    ($($arg:tt)*) => {
+        $crate::logging::write($crate::logging::Level::Info, format_args!($($arg)*))
+    };
+}
+
+pub(crate) use {log_error, log_info, log_warn};
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_redact_masks_tokens() {
+        assert_eq!(
+            redact("Authorization: Bearer abc.def-123"),
+            "Authorization: Bearer [REDACTED]"
+        );
+        assert_eq!(
+            redact(r#"{"token_type":"Bearer","refresh_token":"0.AXYZ-abc_1"}"#),
+            r#"{"token_type":"Bearer","refresh_token":"[REDACTED]"}"#
+        );
+        assert_eq!(
+            redact("grant_type=refresh_token&refresh_token=0.AB"),
+            "grant_type=refresh_token&refresh_token=[REDACTED]"
+        );
+        assert_eq!(
+            redact("bad jwt eyJhbGci.eyJzdWIi.c2ln here"),
+            "bad jwt [REDACTED] here"
+        );
+        assert_eq!(
+            redact("Failed to get chats: 403 Forbidden - Insufficient privileges"),
+            "Failed to get chats: 403 Forbidden - Insufficient privileges"
+        );
+    }
+}
@@ -1,88 +1,114 @@
 mod api;
 mod app;
 mod auth;
+mod cli;
+mod clipboard;
+mod cloud;
 pub mod config;
+mod export;
+mod html;
+mod http;
 pub mod image_display;
+mod logging;
+mod setup;
+mod spinner;
+mod state;
+mod time;
 mod ui;
 
 use crate::app::{ActivePane, App};
+use crate::logging::{log_error, log_info, log_warn};
+use crate::spinner::Spinner;
 use anyhow::Result;
+use clap::Parser;
 use crossterm::{
     event::{
-        self, DisableMouseCapture, EnableMouseCapture, Event, KeyCode, KeyEventKind, MouseButton,
-        MouseEventKind,
+        self, DisableMouseCapture, EnableMouseCapture, Event, KeyCode, KeyEventKind, KeyModifiers,
+        MouseButton, MouseEventKind,
     },
     execute,
     terminal::{disable_raw_mode, enable_raw_mode, EnterAlternateScreen, LeaveAlternateScreen},
 };
 use ratatui::{backend::CrosstermBackend, Terminal};
 use std::io;
+use tokio_util::sync::CancellationToken;
+use tokio_util::task::TaskTracker;
 
 #[tokio::main]
 async fn main() -> Result<()> {
+    // Parse arguments first; --help/--version print and exit here
+    let cli = cli::Cli::parse();
+
+    // Logging is best effort: the app still runs if the log file can't be opened
+    if let Err(e) = logging::init(cli.verbose) {
+        eprintln!("⚠ Could not open log file: {}", e);
+    }
+    log_info!("teams-tui {} starting", env!("CARGO_PKG_VERSION"));
+    image_display::install_panic_hook();
+
     // Load .env and authenticate first (before setting up terminal)
     dotenv::dotenv().ok();
     println!("TeamsTUI");
     println!("================================\n");
 
+    // Nothing configured yet: walk through the app registration before signing in
+    if setup::needed() {
+        setup::run()?;
+    }
+
+    let startup_config = config::load_config();
+    http::init(
+        startup_config.http_timeout(),
+        startup_config.connect_timeout(),
+    );
+    cloud::init(cloud::Endpoints::resolve(
+        cli.cloud,
+        startup_config.graph_base_url.as_deref(),
+        startup_config.login_base_url.as_deref(),
+    ));
+
     // Try silent first
+    let spinner = Spinner::start("Checking saved sign-in...");
     let access_token = match auth::get_valid_token_silent().await {
         Ok(token) => {
-            println!("✓ Authentication successful!\n");
+            spinner.finish("✓ Authentication successful!\n");
             token
         }
         Err(_) => {
-            // Interactive device code flow: start, show the message, then poll
-            match auth::start_device_flow().await {
-                Ok(device_code_response) => {
-                    println!("{}\n", device_code_response.message);
-                    println!("Waiting for authentication...\n");
-                    match auth::poll_for_token(
-                        &device_code_response.device_code,
-                        device_code_response.interval,
-                    )
-                    .await
-                    {
-                        Ok(token_resp) => {
-                            println!("✓ Authentication successful!\n");
-                            token_resp.access_token
-                        }
-                        Err(e) => {
-                            eprintln!("✗ Authentication failed: {}", e);
-                            return Err(e);
-                        }
-                    }
-                }
-                Err(e) => {
-                    eprintln!("✗ Failed to start device flow: {}", e);
-                    return Err(e);
-                }
-            }
+            drop(spinner);
+            device_code_sign_in().await?
         }
     };
 
     // Fetch current user profile
-    println!("Fetching user profile...");
-    let current_user = match api::get_me(&access_token).await {
+    let graph = api::Graph::for_session();
+    let spinner = Spinner::start("Fetching user profile...");
+    let current_user = match api::get_me(&graph, &access_token).await {
         Ok(user) => {
-            println!("✓ Logged in as: {}\n", user.display_name);
+            spinner.finish(&format!("✓ Logged in as: {}\n", user.display_name));
             Some(user)
         }
         Err(e) => {
-            eprintln!("⚠ Failed to fetch user profile: {}", e);
+            spinner.finish(&format!("⚠ Failed to fetch user profile: {}", e));
             None
         }
     };
 
-    // Fetch chats
-    println!("Fetching chats...");
-    let (chats, _) = match api::get_chats(&access_token).await {
+    // Fetch chats; looking up members is one request per chat, so show how far along it is
+    let spinner = Spinner::start("Fetching chats...");
+    let chat_list_options = api::ChatListOptions::from(&startup_config);
+    let chats_result =
+        api::get_chats_with_progress(&graph, &access_token, chat_list_options, |done, total| {
+            spinner.set_message(format!("Loading chat members ({}/{})...", done + 1, total));
+        })
+        .await;
+    let (chats, _) = match chats_result {
         Ok(result) => {
-            println!("✓ Loaded {} chats\n", result.0.len());
+            spinner.finish(&format!("✓ Loaded {} chats\n", result.0.len()));
             result
         }
         Err(e) => {
-            eprintln!("✗ Failed to fetch chats: {}", e);
+            spinner.finish(&format!("✗ Failed to fetch chats: {}", e));
             return Err(e);
         }
     };
@@ -96,13 +122,33 @@ async fn main() -> Result<()> {
 
     // Create app state
     let mut app = App::new();
+    app.debug = cli.debug;
     app.set_chats(chats);
+    app.select_startup_chat();
+    app.can_view_files = auth::can_read_files(&access_token);
+    if !app.can_view_files {
+        app.status = FILE_SCOPES_MISSING.to_string();
+    }
     if let Some(user) = current_user {
+        app.current_user_id = Some(user.id);
         app.set_current_user(user.display_name);
     }
 
     // Run app
-    let res = run_app(&mut terminal, &mut app).await;
+    let tasks = TaskTracker::new();
+    let shutdown = CancellationToken::new();
+    let res = run_app(&mut terminal, &mut app, &graph, &tasks, &shutdown).await;
+
+    // Stop background work before the terminal is restored, so nothing it logs or
+    // prints lands on the user's shell
+    shutdown.cancel();
+    tasks.close();
+    if tokio::time::timeout(SHUTDOWN_TIMEOUT, tasks.wait())
+        .await
+        .is_err()
+    {
+        log_warn!("{} background tasks still running at exit", tasks.len());
+    }
 
     // Restore terminal
     disable_raw_mode()?;
@@ -114,135 +160,630 @@ async fn main() -> Result<()> {
     terminal.show_cursor()?;
 
     if let Err(err) = res {
+        log_error!("Exited with error: {:?}", err);
         println!("{:?}", err);
     }
+    logging::dump_to_stderr();
 
     Ok(())
 }
 
+/// Interactive device code flow: start, show the message, then poll
+///
+/// Runs in the plain terminal, both at startup and from the 'A' key (see `reauthenticate`).
+async fn device_code_sign_in() -> Result<String> {
+    let spinner = Spinner::start("Starting sign-in...");
+    let device_code_response = match auth::start_device_flow().await {
+        Ok(response) => response,
+        Err(e) => {
+            spinner.finish(&format!("✗ Failed to start device flow: {}", e));
+            return Err(e);
+        }
+    };
+    spinner.finish(&format!("{}\n", device_code_response.message));
+
+    let spinner = Spinner::start("Waiting for authentication in your browser...");
+    match auth::poll_for_token(
+        &device_code_response.device_code,
+        device_code_response.interval,
+        device_code_response.expires_in,
+    )
+    .await
+    {
+        Ok(token_resp) => {
+            spinner.finish("✓ Authentication successful!\n");
+            Ok(token_resp.access_token)
+        }
+        Err(e) => {
+            spinner.finish(&format!("✗ Authentication failed: {}", e));
+            Err(e)
+        }
+    }
+}
+
+/// Sign in again from inside the TUI once the refresh token has expired
+///
+/// Leaves the alternate screen and raw mode so the device code can be read and
+/// copied, runs the flow, then restores the TUI whatever the outcome.
+async fn reauthenticate(terminal: &mut Terminal<CrosstermBackend<io::Stdout>>) -> Result<()> {
+    let result = async {
+        let _suspended = SuspendedTui::leave()?;
+
+        println!("\nTeamsTUI - sign in again");
+        println!("================================\n");
+        let result = device_code_sign_in().await;
+        if result.is_err() {
+            // Leave the error readable for a moment before the TUI covers it
+            tokio::time::sleep(std::time::Duration::from_secs(3)).await;
+        }
+        result
+    }
+    .await;
+
+    // The screen was left as the sign-in flow drew it
+    terminal.clear()?;
+    result.map(|_| ())
+}
+
+/// The TUI stepped aside for plain terminal output; dropping it comes back, so an
+/// error or panic halfway through never leaves the terminal in cooked mode
+struct SuspendedTui;
+
+impl SuspendedTui {
+    fn leave() -> Result<Self> {
+        // Created first, so even a half-finished leave is undone
+        let suspended = SuspendedTui;
+        disable_raw_mode()?;
+        execute!(
+            io::stdout(),
+            LeaveAlternateScreen,
+            DisableMouseCapture,
+            crossterm::cursor::Show
+        )?;
+        Ok(suspended)
+    }
+}
+
+impl Drop for SuspendedTui {
+    fn drop(&mut self) {
+        let restored = enable_raw_mode()
+            .and_then(|()| execute!(io::stdout(), EnterAlternateScreen, EnableMouseCapture));
+        if let Err(e) = restored {
+            log_warn!("Could not restore the terminal after signing in: {}", e);
+        }
+    }
+}
+
 async fn run_app(
     terminal: &mut Terminal<CrosstermBackend<io::Stdout>>,
     app: &mut App,
+    graph: &api::Graph,
+    tasks: &TaskTracker,
+    shutdown: &CancellationToken,
 ) -> Result<()> {
-    // Create a channel for receiving loaded messages
-    let (tx, mut rx) = tokio::sync::mpsc::unbounded_channel::<(usize, Vec<api::Message>)>();
+    // Create a channel for receiving loaded messages, keyed by chat id so results for a chat
+    // that has since moved in the list are never applied to another one
+    // (Err = the load gave up after retrying)
+    let (tx, mut rx) = tokio::sync::mpsc::unbounded_channel::<(
+        String,
+        Result<api::MessagePage, api::MessagesError>,
+    )>();
 
     // Create a channel for receiving chat updates
     let (tx_chats, mut rx_chats) =
         tokio::sync::mpsc::unbounded_channel::<(Vec<api::Chat>, Option<String>)>();
 
-    // Create a channel for receiving loaded images (Ok = success with bytes, Err = failure)
-    let (tx_image, mut rx_image) =
-        tokio::sync::mpsc::unbounded_channel::<(String, Result<Vec<u8>, String>)>();
+    // Create a channel for receiving loaded images (Ok = downloaded and decoded, Err = failure)
+    let (tx_image, mut rx_image) = tokio::sync::mpsc::unbounded_channel::<(
+        String,
+        Result<image_display::DecodedImage, String>,
+    )>();
+
+    // Create a channel for gallery thumbnails, decoded and shrunk off the event loop
+    let (tx_thumb, mut rx_thumb) =
+        tokio::sync::mpsc::unbounded_channel::<(String, Result<image::DynamicImage, String>)>();
+
+    // Create a channel for attachments downloaded to open, keyed by file name
+    let (tx_file, mut rx_file) =
+        tokio::sync::mpsc::unbounded_channel::<(String, Result<std::path::PathBuf, String>)>();
+
+    // Create a channel for receiving older pages fetched while scrolling back, keyed by chat id
+    let (tx_older, mut rx_older) =
+        tokio::sync::mpsc::unbounded_channel::<(String, Result<api::MessagePage, String>)>();
+
+    // Create a channel for receiving send outcomes, keyed by the local echo id
+    let (tx_send_result, mut rx_send_result) =
+        tokio::sync::mpsc::unbounded_channel::<(String, Result<(), String>)>();
+
+    // Create a channel for mark-as-read outcomes: Ok(false) means unsupported
+    let (tx_read, mut rx_read) =
+        tokio::sync::mpsc::unbounded_channel::<(String, Result<bool, String>)>();
+
+    // Create a channel for reaction outcomes, with the change to undo if it failed
+    let (tx_react, mut rx_react) =
+        tokio::sync::mpsc::unbounded_channel::<(app::ReactionChange, Result<(), String>)>();
+
+    // Create a channel for background refresh health: Err carries the failure
+    let (tx_refresh, mut rx_refresh) = tokio::sync::mpsc::unbounded_channel::<Result<(), String>>();
+
+    // Create a channel for whether the background tasks found the sign-in valid or expired
+    let (tx_auth, mut rx_auth) = tokio::sync::mpsc::unbounded_channel::<bool>();
+
+    // Shared HTTP client for image and file downloads
+    let http_client = std::sync::Arc::new(http::download_client());
 
-    // Shared HTTP client for image downloads
-    let http_client = std::sync::Arc::new(reqwest::Client::new());
+    // Wake-ups for the refresh tasks below (F5 / Ctrl+R). A wake-up sent while a refresh
+    // is in flight is held until it finishes, so repeated presses never stack requests.
+    let refresh_chats_now = std::sync::Arc::new(tokio::sync::Notify::new());
+    let refresh_messages_now = std::sync::Arc::new(tokio::sync::Notify::new());
+
+    // Raised by the polling tasks when they deliver, so an idle loop wakes promptly
+    let wake = std::sync::Arc::new(std::sync::atomic::AtomicBool::new(false));
+
+    // Messages fetched per request
+    let page_size = app.config.page_size;
 
     // Spawn background task to refresh chats
+    let chat_list_options = api::ChatListOptions::from(&app.config);
     let tx_chats_clone = tx_chats.clone();
-    tokio::spawn(async move {
-        let mut interval = tokio::time::interval(std::time::Duration::from_secs(3));
-        interval.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Skip);
+    let tx_refresh_clone = tx_refresh.clone();
+    let tx_auth_clone = tx_auth.clone();
+    let wake_clone = wake.clone();
+    let refresh_now = refresh_chats_now.clone();
+    let graph_clone = graph.clone();
+    // Poll intervals can change when the config is reloaded (F6)
+    let (tx_list_interval, mut rx_list_interval) =
+        tokio::sync::watch::channel(app.config.list_poll_interval());
+    spawn_task(tasks, shutdown, async move {
+        let mut interval = poll_interval(*rx_list_interval.borrow_and_update());
+        // Only log when the error changes, not on every tick
+        let mut last_error: Option<String> = None;
+        let mut chat_sync = api::ChatSync::new(graph_clone, chat_list_options);
         loop {
-            interval.tick().await;
-            if let Ok(token) = auth::get_valid_token_silent().await {
-                if let Ok(result) = api::get_chats(&token).await {
+            tokio::select! {
+                _ = interval.tick() => {}
+                _ = refresh_now.notified() => interval.reset(),
+                Ok(()) = rx_list_interval.changed() => {
+                    interval = poll_interval(*rx_list_interval.borrow_and_update());
+                    interval.reset();
+                    continue;
+                }
+            }
+            let token = auth::get_valid_token_silent().await;
+            if let Some(valid) = sign_in_valid(&token) {
+                let _ = tx_auth_clone.send(valid);
+            }
+            let result = match token {
+                Ok(token) => chat_sync.refresh(&token).await,
+                Err(e) => Err(e.context("Auth error")),
+            };
+            match result {
+                Ok(result) => {
+                    if last_error.take().is_some() {
+                        log_info!("Chat refresh recovered");
+                    }
                     let _ = tx_chats_clone.send(result);
+                    let _ = tx_refresh_clone.send(Ok(()));
+                    wake_clone.store(true, std::sync::atomic::Ordering::Relaxed);
+                }
+                Err(e) => {
+                    let error = format!("{:#}", e);
+                    let _ = tx_refresh_clone.send(Err(error.clone()));
+                    if last_error.as_ref() != Some(&error) {
+                        log_error!("Chat refresh failed: {}", error);
+                        last_error = Some(error);
+                    }
+                }
+            }
+        }
+    });
+
+    // Spawn background task to poll the open chat, which follows the selection
+    let (tx_active, mut rx_active) =
+        tokio::sync::watch::channel(app.get_selected_chat().map(|c| c.id.clone()));
+    let tx_clone = tx.clone();
+    let refresh_now = refresh_messages_now.clone();
+    let tx_auth_clone = tx_auth.clone();
+    let wake_clone = wake.clone();
+    let graph_clone = graph.clone();
+    let (tx_active_interval, mut rx_active_interval) =
+        tokio::sync::watch::channel(app.config.active_poll_interval());
+    spawn_task(tasks, shutdown, async move {
+        let mut interval = poll_interval(*rx_active_interval.borrow_and_update());
+        let mut last_error: Option<String> = None;
+        loop {
+            tokio::select! {
+                _ = interval.tick() => {}
+                _ = refresh_now.notified() => interval.reset(),
+                Ok(()) = rx_active_interval.changed() => {
+                    interval = poll_interval(*rx_active_interval.borrow_and_update());
+                    interval.reset();
+                    continue;
+                }
+                changed = rx_active.changed() => {
+                    // A newly opened chat is loaded by the switch itself; poll it a full
+                    // interval later
+                    if changed.is_err() {
+                        break;
+                    }
+                    interval.reset();
+                    continue;
+                }
+            }
+            let Some(chat_id) = rx_active.borrow_and_update().clone() else {
+                continue;
+            };
+            let token = auth::get_valid_token_silent().await;
+            if let Some(valid) = sign_in_valid(&token) {
+                let _ = tx_auth_clone.send(valid);
+            }
+            let result = match token {
+                Ok(token) => api::get_messages(&graph_clone, &token, &chat_id, page_size).await,
+                Err(e) => Err(e.context("Auth error")),
+            };
+            match result {
+                Ok(page) => {
+                    last_error = None;
+                    if tx_clone.send((chat_id, Ok(page))).is_err() {
+                        break;
+                    }
+                    wake_clone.store(true, std::sync::atomic::Ordering::Relaxed);
+                }
+                Err(e) if e.is::<api::ChatUnavailable>() => {
+                    let _ = tx_clone.send((chat_id, Err(api::MessagesError::Unavailable)));
+                }
+                Err(e) => {
+                    let error = format!("{:#}", e);
+                    if last_error.as_ref() != Some(&error) {
+                        log_warn!("Message refresh failed: {}", error);
+                        last_error = Some(error);
+                    }
                 }
             }
         }
     });
 
-    // Helper function to spawn image download task
-    let spawn_image_download =
-        |url: String,
-         tx_img: tokio::sync::mpsc::UnboundedSender<(String, Result<Vec<u8>, String>)>,
-         client: std::sync::Arc<reqwest::Client>| {
-            tokio::spawn(async move {
-                let result = async {
+    // Helper function to spawn image download task; a failed thumbnail is retried from
+    // the original, and the result is still reported under the thumbnail's URL. The
+    // image is decoded here too (all GIF frames when `animate`), so a big one doesn't
+    // freeze the UI.
+    let spawn_image_download = |image: &app::ViewableImage,
+                                tx_img: tokio::sync::mpsc::UnboundedSender<(
+        String,
+        Result<image_display::DecodedImage, String>,
+    )>,
+                                client: std::sync::Arc<reqwest::Client>,
+                                animate: bool| {
+        let url = image.source_url().to_string();
+        let fallback = image.fallback_url().map(str::to_string);
+        spawn_task(tasks, shutdown, async move {
+            let result = match fetch_image(&client, &url, fallback.as_deref()).await {
+                Ok(image_display::DownloadedImage {
+                    bytes,
+                    download_url,
+                }) => tokio::task::spawn_blocking(move || {
+                    image_display::decode_for_viewer(&bytes, animate)
+                        .map_err(|e| format!("{:#}", e))
+                })
+                .await
+                .map(|frames| image_display::DecodedImage {
+                    frames,
+                    download_url,
+                })
+                .map_err(|e| e.to_string()),
+                Err(e) => Err(e),
+            };
+            let _ = tx_img.send((url, result));
+        });
+    };
+
+    // Helper function to spawn a gallery thumbnail download; decoding and shrinking
+    // a full-size photo takes long enough to stall input, so it happens here too
+    let spawn_thumbnail_download = |image: &app::ViewableImage| {
+        let url = image.source_url().to_string();
+        let fallback = image.fallback_url().map(str::to_string);
+        let client = http_client.clone();
+        let tx_thumb = tx_thumb.clone();
+        spawn_task(tasks, shutdown, async move {
+            let result = match fetch_image(&client, &url, fallback.as_deref()).await {
+                Ok(image_display::DownloadedImage { bytes, .. }) => {
+                    tokio::task::spawn_blocking(move || {
+                        image_display::load_image_from_bytes(&bytes)
+                            .map(|image| {
+                                image.thumbnail(app::THUMBNAIL_PIXELS, app::THUMBNAIL_PIXELS)
+                            })
+                            .map_err(|e| format!("{:#}", e))
+                    })
+                    .await
+                    .unwrap_or_else(|e| Err(e.to_string()))
+                }
+                Err(e) => Err(e),
+            };
+            let _ = tx_thumb.send((url, result));
+        });
+    };
+
+    // Download a shared file to the temp dir, to be opened in its default app
+    let spawn_attachment_open = |app: &mut App, entry: app::AttachmentEntry| {
+        let Some(url) = entry.url else {
+            app.status = format!("No link for {}", entry.name);
+            return;
+        };
+        app.status = format!("Downloading {}…", entry.name);
+        let name = entry.name;
+        let tx_file = tx_file.clone();
+        let client = http_client.clone();
+        spawn_task(tasks, shutdown, async move {
+            let result = async {
+                let token = auth::get_valid_token_silent()
+                    .await
+                    .map_err(|e| format!("Auth error: {}", e))?;
+                let bytes = image_display::download_attachment(&client, &url, &token)
+                    .await
+                    .map_err(|e| format!("{:#}", e))?;
+                image_display::save_attachment(&name, &bytes).map_err(|e| format!("{:#}", e))
+            }
+            .await;
+            if let Err(ref e) = result {
+                log_error!("Attachment download failed for {}: {}", url, e);
+            }
+            let _ = tx_file.send((name, result));
+        });
+    };
+
+    // Helper function to spawn a message send task
+    let spawn_send_message =
+        |chat_id: String,
+         local_id: String,
+         content: String,
+         reply_to: Option<api::ReplyTo>,
+         importance: api::Importance,
+         retry_of: Option<app::OutgoingMessage>,
+         sender: Option<String>,
+         tx: tokio::sync::mpsc::UnboundedSender<(
+            String,
+            Result<api::MessagePage, api::MessagesError>,
+        )>,
+         tx_chats: tokio::sync::mpsc::UnboundedSender<(Vec<api::Chat>, Option<String>)>,
+         tx_send_result: tokio::sync::mpsc::UnboundedSender<(String, Result<(), String>)>| {
+            let graph = graph.clone();
+            spawn_task(tasks, shutdown, async move {
+                let result: Result<String, String> = async {
                     let token = auth::get_valid_token_silent()
                         .await
                         .map_err(|e| format!("Auth error: {}", e))?;
-                    let bytes = image_display::download_image(&client, &url, &token)
+                    // A retried send may have gone through after all; don't post it twice
+                    if let Some(original) = retry_of {
+                        let (text, sent_at) = original.sent_text();
+                        let delivered = api::find_sent_message(
+                            &graph,
+                            &token,
+                            &chat_id,
+                            sender.as_deref(),
+                            &text,
+                            sent_at,
+                        )
                         .await
-                        .map_err(|e| format!("Download error: {}", e))?;
-                    Ok(bytes)
+                        .unwrap_or(false);
+                        if delivered {
+                            log_info!("Skipping retry of {}: already delivered", local_id);
+                            return Ok(token);
+                        }
+                    }
+                    api::send_message(
+                        &graph,
+                        &token,
+                        &chat_id,
+                        &content,
+                        reply_to.as_ref(),
+                        importance,
+                    )
+                    .await
+                    .map_err(|e| e.to_string())?;
+                    Ok(token)
                 }
                 .await;
-                let _ = tx_img.send((url, result));
+
+                match result {
+                    Ok(token) => {
+                        let _ = tx_send_result.send((local_id, Ok(())));
+                        // Reload messages
+                        if let Ok(page) =
+                            api::get_messages(&graph, &token, &chat_id, page_size).await
+                        {
+                            let _ = tx.send((chat_id, Ok(page)));
+                        }
+                        // Refresh chat list to update last message preview
+                        if let Ok(chats) = api::get_chats(&graph, &token, chat_list_options).await {
+                            let _ = tx_chats.send(chats);
+                        }
+                    }
+                    Err(e) => {
+                        let _ = tx_send_result.send((local_id, Err(e)));
+                    }
+                }
             });
         };
 
-    // Load messages for the first chat if available
-    if let Some(chat) = app.get_selected_chat() {
-        let chat_id = chat.id.clone();
-        let chat_index = app.selected_index;
-        let tx_clone = tx.clone();
-
-        app.set_loading_messages(true);
-        tokio::spawn(async move {
-            if let Ok(token) = auth::get_valid_token_silent().await {
-                if let Ok(messages) = api::get_messages(&token, &chat_id).await {
-                    let _ = tx_clone.send((chat_index, messages));
-                }
+    // Send the compose buffer to the selected chat
+    let send_input = |app: &mut App| {
+        // Send message logic
+        if let Some(chat) = app.get_selected_chat() {
+            let chat_id = chat.id.clone();
+            // Checked before taking the text, so a refused send leaves it in the compose box
+            if app.is_duplicate_send(&chat_id, &app.input_buffer) {
+                app.status = "Not sent again: that message is already on its way".to_string();
+            } else {
+                let message = app.take_input();
+                app.input_mode = false;
+                let reply_to = app.reply_to.take();
+                let importance = std::mem::take(&mut app.compose_importance);
+                let local_id =
+                    app.add_outgoing_message(&chat_id, &message, reply_to.clone(), importance);
+
+                app.scroll_to_newest();
+                spawn_send_message(
+                    chat_id,
+                    local_id,
+                    message,
+                    reply_to,
+                    importance,
+                    None,
+                    app.current_user_name.clone(),
+                    tx.clone(),
+                    tx_chats.clone(),
+                    tx_send_result.clone(),
+                );
             }
+        }
+    };
+
+    // Helper function to spawn the load of a newly selected chat, retrying transient failures
+    let spawn_message_load = |chat_id: String,
+                              tx: tokio::sync::mpsc::UnboundedSender<(
+        String,
+        Result<api::MessagePage, api::MessagesError>,
+    )>| {
+        let graph = graph.clone();
+        spawn_task(tasks, shutdown, async move {
+            let result = load_messages_with_retry(&graph, &chat_id, page_size).await;
+            let _ = tx.send((chat_id, result));
         });
+    };
+
+    // Reset the messages pane for the newly selected chat and start loading it
+    let open_selected_chat = |app: &mut App| {
+        if let Some(chat_id) = app.get_selected_chat().map(|c| c.id.clone()) {
+            app.prepare_chat_switch();
+            spawn_message_load(chat_id, tx.clone());
+        }
+    };
+
+    // Load messages for the first chat if available, restoring its saved draft
+    if let Some(chat) = app.get_selected_chat() {
+        let chat_id = chat.id.clone();
+        app.prepare_chat_switch();
+        spawn_message_load(chat_id, tx.clone());
     }
 
     use std::process::Command;
     loop {
+        // Point the active chat poll at whatever is selected now
+        let selected_chat_id = app.get_selected_chat().map(|c| c.id.clone());
+        tx_active.send_if_modified(|active| {
+            if *active == selected_chat_id {
+                return false;
+            }
+            *active = selected_chat_id;
+            true
+        });
+
         // Check for chat updates
         while let Ok((chats, _)) = rx_chats.try_recv() {
-            // Preserve selection
-            let current_chat_id = app.get_selected_chat().map(|c| c.id.clone());
-
-            app.set_chats(chats);
-
-            if let Some(id) = current_chat_id {
-                if let Some(index) = app.chats.iter().position(|c| c.id == id) {
-                    app.selected_index = index;
+            // The selection follows its chat; if the chat disappeared, open whichever took its place
+            if app.set_chats(chats) {
+                open_selected_chat(app);
+            }
+        }
+        if std::mem::take(&mut app.notify_pending) {
+            // The terminal decides whether the bell beeps, flashes or raises a notification
+            use std::io::Write;
+            let mut stdout = io::stdout();
+            let _ = stdout.write_all(b"\x07").and_then(|()| stdout.flush());
+        }
 
-                    // Always refresh messages for the current chat to ensure we get new ones
-                    let tx_clone = tx.clone();
-                    let chat_id = id.clone();
-                    let chat_index = index;
+        // Check for loaded messages (non-blocking)
+        while let Ok((chat_id, result)) = rx.try_recv() {
+            // Only update if we're still on the same chat
+            if app.get_selected_chat().is_some_and(|c| c.id == chat_id) {
+                match result {
+                    Ok(page) => app.apply_messages_page(page),
+                    Err(api::MessagesError::Unavailable) => app.mark_chat_unavailable(&chat_id),
+                    Err(api::MessagesError::Failed(e)) => app.set_messages_error(e),
+                }
+            }
+        }
 
-                    tokio::spawn(async move {
-                        if let Ok(token) = auth::get_valid_token_silent().await {
-                            if let Ok(messages) = api::get_messages(&token, &chat_id).await {
-                                let _ = tx_clone.send((chat_index, messages));
-                            }
-                        }
-                    });
-                } else {
-                    // Chat disappeared or moved, keep index clamped
-                    if app.selected_index >= app.chats.len() {
-                        app.selected_index = app.chats.len().saturating_sub(1);
+        // Check for older pages of history
+        while let Ok((chat_id, result)) = rx_older.try_recv() {
+            if app.get_selected_chat().is_some_and(|c| c.id == chat_id) {
+                match result {
+                    Ok(page) => app.append_older_page(page),
+                    Err(e) => {
+                        log_error!("Loading older messages failed: {}", e);
+                        app.loading_older = false;
+                        app.messages_dirty = true;
+                        app.status = "Could not load older messages".to_string();
                     }
                 }
             }
         }
 
-        // Check for loaded messages (non-blocking)
-        while let Ok((chat_index, messages)) = rx.try_recv() {
-            // Only update if we're still on the same chat
-            if chat_index == app.selected_index {
-                // Check if messages actually changed to avoid unnecessary snaps/renders
-                let should_update = if app.messages.len() != messages.len() {
-                    true
-                } else {
-                    // Check last message ID
-                    match (app.messages.last(), messages.last()) {
-                        (Some(curr), Some(new)) => curr.id != new.id,
-                        (None, None) => false,
-                        _ => true,
-                    }
-                };
+        // Check for send outcomes
+        while let Ok((local_id, result)) = rx_send_result.try_recv() {
+            match result {
+                Ok(()) => app.set_outgoing_state(&local_id, api::SendState::Sent),
+                Err(e) => {
+                    log_error!("Send failed: {}", e);
+                    app.set_outgoing_state(&local_id, api::SendState::Failed);
+                    app.status = format!("Message not sent: {} (S to retry)", e);
+                }
+            }
+        }
+
+        // Open attachments once downloaded
+        while let Ok((name, result)) = rx_file.try_recv() {
+            app.status = match result {
+                Ok(path) => match open::that_detached(&path) {
+                    Ok(()) => format!("Opened {}", name),
+                    Err(e) => format!("Saved {} but could not open it: {}", path.display(), e),
+                },
+                Err(e) => format!("Could not download {}: {}", name, e),
+            };
+        }
+
+        // Check for reaction outcomes; a refused change is undone locally
+        while let Ok((change, result)) = rx_react.try_recv() {
+            if let Err(e) = result {
+                log_error!("Reaction failed: {}", e);
+                app.revert_reaction(&change);
+                app.status = format!("Could not react: {}", e);
+            }
+        }
+
+        // Track whether the background refresh is reaching the server
+        while let Ok(result) = rx_refresh.try_recv() {
+            app.record_refresh(result.is_ok());
+        }
 
-                if should_update {
-                    app.set_messages(messages);
-                    app.snap_to_bottom = true;
+        // Go offline (read-only) while no token can be had, and back once one can
+        while let Ok(ok) = rx_auth.try_recv() {
+            app.record_auth(ok);
+        }
+
+        // Check for mark-as-read outcomes
+        while let Ok((chat_id, result)) = rx_read.try_recv() {
+            match result {
+                Ok(true) => {
+                    app.mark_chat_read_locally(&chat_id);
+                    app.status = "Marked as read".to_string();
+                }
+                Ok(false) => {
+                    app.status = "Marking as read isn't available for this chat".to_string();
                 }
+                Err(e) => {
+                    log_error!("Mark as read failed: {}", e);
+                    app.status = format!("Could not mark as read: {}", e);
+                }
+            }
+        }
+
+        // Check for gallery thumbnails
+        while let Ok((url, result)) = rx_thumb.try_recv() {
+            app.take_gallery_request(&url);
+            match result {
+                Ok(thumbnail) => app.set_gallery_thumbnail(&url, thumbnail),
+                Err(e) => log_warn!("Could not load gallery thumbnail: {}", e),
             }
         }
 
@@ -250,15 +791,24 @@ async fn run_app(
         while let Ok((url, result)) = rx_image.try_recv() {
             // Only process if we're still viewing this image
             if let Some(ref viewing) = app.viewing_image {
-                if viewing.url == url {
+                if viewing.source_url() == url {
                     match result {
-                        Ok(bytes) => {
-                            // Try to decode and create protocol
-                            match image::load_from_memory(&bytes) {
-                                Ok(dyn_img) => {
-                                    if let Some(ref mut picker) = app.image_picker {
-                                        let protocol = picker.new_resize_protocol(dyn_img);
-                                        app.set_image_protocol(protocol);
+                        Ok(image_display::DecodedImage {
+                            frames,
+                            download_url,
+                        }) => {
+                            app.set_image_download_url(download_url);
+                            match frames {
+                                // Animated GIFs play only where real graphics are supported
+                                Ok(image_display::DecodedFrames::Animated(frames)) => {
+                                    let (w, h) = (frames[0].0.width(), frames[0].0.height());
+                                    app.set_image_dimensions(w, h);
+                                    app.start_animation(frames);
+                                }
+                                Ok(image_display::DecodedFrames::Still(dyn_img)) => {
+                                    app.set_image_dimensions(dyn_img.width(), dyn_img.height());
+                                    if app.image_picker.is_some() {
+                                        app.show_viewer_image(dyn_img);
                                     } else {
                                         app.set_image_error(
                                             "Image display not supported in this terminal"
@@ -266,9 +816,7 @@ async fn run_app(
                                         );
                                     }
                                 }
-                                Err(e) => {
-                                    app.set_image_error(format!("Failed to decode image: {}", e));
-                                }
+                                Err(e) => app.set_image_error(e),
                             }
                         }
                         Err(error_msg) => {
@@ -279,18 +827,47 @@ async fn run_app(
             }
         }
 
+        app.tick_animation();
+        app.release_idle_images();
+
         terminal.draw(|f| ui::draw(f, app))?;
 
-        // Use poll with timeout to allow checking for messages
-        if event::poll(std::time::Duration::from_millis(100))? {
+        // Use poll with timeout to allow checking for messages, waking early for GIF frames;
+        // left alone for a while, redraw less often
+        let frame = if app.is_idle() { IDLE_FRAME } else { FRAME };
+        let poll_timeout = app
+            .time_to_next_frame()
+            .map_or(frame, |next| next.min(frame));
+        if wait_for_event(poll_timeout, &wake)? {
             let previous_index = app.selected_index;
 
-            match event::read()? {
+            let event = event::read()?;
+            app.last_input = std::time::Instant::now();
+            app.note_activity();
+            match event {
                 Event::Key(key) => {
                     // Only handle key press events, ignore release and repeat
                     if key.kind != KeyEventKind::Press {
                         continue;
                     }
+                    // "gg" is two presses; any other key in between cancels it
+                    let after_g = std::mem::take(&mut app.pending_g);
+
+                    // Ctrl+C quits from anywhere, asking first if there is an unsent draft;
+                    // a second Ctrl+C at that prompt answers yes
+                    if key.code == KeyCode::Char('c')
+                        && key.modifiers.contains(KeyModifiers::CONTROL)
+                    {
+                        if app.input_buffer.is_empty() || app.confirm_quit {
+                            app.save_ui_state();
+                            return Ok(());
+                        }
+                        if app.is_viewing_image() {
+                            app.stop_viewing_image();
+                        }
+                        app.confirm_quit = true;
+                        continue;
+                    }
 
                     // Handle image viewing mode first
                     if app.is_viewing_image() {
@@ -298,45 +875,77 @@ async fn run_app(
                             KeyCode::Esc | KeyCode::Char('q') => {
                                 app.stop_viewing_image();
                             }
+                            KeyCode::Char('p') | KeyCode::Char(' ') => {
+                                app.toggle_animation_pause();
+                            }
+                            KeyCode::Char('z') => {
+                                app.toggle_zoom();
+                            }
+                            KeyCode::Char('f') => {
+                                if let Some(img) = app.view_full_resolution() {
+                                    spawn_image_download(
+                                        &img,
+                                        tx_image.clone(),
+                                        http_client.clone(),
+                                        app.supports_graphics(),
+                                    );
+                                }
+                            }
+                            // Arrow keys pan while showing the image at actual size
+                            KeyCode::Left if !app.zoom_fit => app.pan_image(-1, 0),
+                            KeyCode::Right if !app.zoom_fit => app.pan_image(1, 0),
+                            KeyCode::Up if !app.zoom_fit => app.pan_image(0, -1),
+                            KeyCode::Down if !app.zoom_fit => app.pan_image(0, 1),
+                            KeyCode::Char('c') => {
+                                if let Some(link) = app.viewing_image_link().map(str::to_string) {
+                                    app.status = match clipboard::copy_text(&link) {
+                                        Ok(()) => "Copied image link to clipboard".to_string(),
+                                        Err(e) => format!("Could not copy link: {}", e),
+                                    };
+                                }
+                            }
                             KeyCode::Left | KeyCode::Char('h') => {
                                 app.previous_image();
                                 // Load the new image
                                 if let Some(img) = app.get_current_viewable_image().cloned() {
-                                    let url = img.url.clone();
-                                    app.start_viewing_image(img);
                                     spawn_image_download(
-                                        url,
+                                        &img,
                                         tx_image.clone(),
                                         http_client.clone(),
+                                        app.supports_graphics(),
                                     );
+                                    app.start_viewing_image(img);
                                 }
                             }
                             KeyCode::Right | KeyCode::Char('l') => {
                                 app.next_image();
                                 // Load the new image
                                 if let Some(img) = app.get_current_viewable_image().cloned() {
-                                    let url = img.url.clone();
-                                    app.start_viewing_image(img);
                                     spawn_image_download(
-                                        url,
+                                        &img,
                                         tx_image.clone(),
                                         http_client.clone(),
+                                        app.supports_graphics(),
                                     );
+                                    app.start_viewing_image(img);
                                 }
                             }
                             KeyCode::Char('o') => {
                                 // View externally: download image and open with default viewer
                                 if let Some(img) = app.get_current_viewable_image() {
-                                    let url = img.url.clone();
+                                    let url = img.source_url().to_string();
                                     if let Ok(token) = auth::get_valid_token_silent().await {
+                                        // Awaited right here, so keep the overall limit of the API client
                                         match image_display::download_image(
-                                            &http_client,
+                                            &http::client(),
                                             &url,
                                             &token,
                                         )
                                         .await
                                         {
-                                            Ok(bytes) => {
+                                            Ok(image_display::DownloadedImage {
+                                                bytes, ..
+                                            }) => {
                                                 // Save to temp file
                                                 let ext = if url.ends_with(".png") {
                                                     "png"
@@ -407,9 +1016,271 @@ async fn run_app(
                         continue;
                     }
 
+                    // Handle send confirmation for large group chats
+                    if app.confirm_send.is_some() {
+                        match key.code {
+                            KeyCode::Char('y') | KeyCode::Char('Y') | KeyCode::Enter => {
+                                app.confirm_send = None;
+                                send_input(app);
+                            }
+                            KeyCode::Char('n') | KeyCode::Char('N') | KeyCode::Esc => {
+                                // Back to the draft, unchanged
+                                app.confirm_send = None;
+                            }
+                            _ => {}
+                        }
+                        continue;
+                    }
+
+                    // Handle quit confirmation
+                    if app.confirm_quit {
+                        match key.code {
+                            KeyCode::Char('y') | KeyCode::Char('Y') => {
+                                app.save_ui_state();
+                                return Ok(());
+                            }
+                            KeyCode::Char('n') | KeyCode::Char('N') | KeyCode::Esc => {
+                                app.confirm_quit = false;
+                            }
+                            _ => {}
+                        }
+                        continue;
+                    }
+
+                    // Thumbnail gallery takes all keys until closed
+                    if app.gallery_mode {
+                        let columns = app.gallery_columns as isize;
+                        match key.code {
+                            KeyCode::Esc | KeyCode::Char('P') | KeyCode::Char('q') => {
+                                app.gallery_mode = false;
+                            }
+                            KeyCode::Left | KeyCode::Char('h') => app.move_gallery_selection(-1),
+                            KeyCode::Right | KeyCode::Char('l') => app.move_gallery_selection(1),
+                            KeyCode::Up | KeyCode::Char('k') => {
+                                app.move_gallery_selection(-columns)
+                            }
+                            KeyCode::Down | KeyCode::Char('j') => {
+                                app.move_gallery_selection(columns)
+                            }
+                            KeyCode::Enter => {
+                                // Full size in the viewer; closing it returns to the grid
+                                if let Some(img) = app.get_current_viewable_image().cloned() {
+                                    if !app.can_view_image(&img.url) {
+                                        app.status = FILE_SCOPES_MISSING.to_string();
+                                        continue;
+                                    }
+                                    spawn_image_download(
+                                        &img,
+                                        tx_image.clone(),
+                                        http_client.clone(),
+                                        app.supports_graphics(),
+                                    );
+                                    app.start_viewing_image(img);
+                                }
+                            }
+                            _ => {}
+                        }
+                        continue;
+                    }
+
+                    if let Some(row) = app.attachments_drawer {
+                        match key.code {
+                            KeyCode::Esc | KeyCode::Char('a') | KeyCode::Char('q') => {
+                                app.attachments_drawer = None;
+                            }
+                            KeyCode::Up | KeyCode::Char('k') => app.move_drawer_selection(-1),
+                            KeyCode::Down | KeyCode::Char('j') => app.move_drawer_selection(1),
+                            KeyCode::PageUp => app.move_drawer_selection(-10),
+                            KeyCode::PageDown => app.move_drawer_selection(10),
+                            KeyCode::Enter => {
+                                let Some(entry) = app.chat_attachments().into_iter().nth(row)
+                                else {
+                                    continue;
+                                };
+                                if !entry.is_image {
+                                    spawn_attachment_open(app, entry);
+                                    continue;
+                                }
+                                let Some(url) = entry.url else {
+                                    app.status = format!("No link for {}", entry.name);
+                                    continue;
+                                };
+                                if !app.can_view_image(&url) {
+                                    app.status = FILE_SCOPES_MISSING.to_string();
+                                } else if let Some(img) = app.select_image_by_url(&url) {
+                                    // Closing the viewer returns to the drawer
+                                    spawn_image_download(
+                                        &img,
+                                        tx_image.clone(),
+                                        http_client.clone(),
+                                        app.supports_graphics(),
+                                    );
+                                    app.start_viewing_image(img);
+                                }
+                            }
+                            _ => {}
+                        }
+                        continue;
+                    }
+
+                    if app.notif_overlay.is_some() {
+                        match key.code {
+                            KeyCode::Esc | KeyCode::Char('b') | KeyCode::Char('q') => {
+                                app.notif_overlay = None;
+                            }
+                            KeyCode::Up | KeyCode::Char('k') => app.move_notif_selection(-1),
+                            KeyCode::Down | KeyCode::Char('j') => app.move_notif_selection(1),
+                            KeyCode::Enter => app.apply_notif_selection(),
+                            _ => {}
+                        }
+                        continue;
+                    }
+
+                    if app.reaction_picker.is_some() {
+                        match key.code {
+                            KeyCode::Esc | KeyCode::Char('r') | KeyCode::Char('q') => {
+                                app.reaction_picker = None;
+                            }
+                            KeyCode::Left | KeyCode::Char('h') => app.move_reaction_selection(-1),
+                            KeyCode::Right | KeyCode::Char('l') => app.move_reaction_selection(1),
+                            KeyCode::Enter => {
+                                if let Some(change) = app.apply_reaction_selection() {
+                                    let tx_react = tx_react.clone();
+                                    let graph = graph.clone();
+                                    spawn_task(tasks, shutdown, async move {
+                                        let result = async {
+                                            let token = auth::get_valid_token_silent()
+                                                .await
+                                                .map_err(|e| format!("Auth error: {}", e))?;
+                                            let (chat_id, message_id, kind) = (
+                                                &change.chat_id,
+                                                &change.message_id,
+                                                change.reaction_type,
+                                            );
+                                            if change.remove {
+                                                api::unset_reaction(
+                                                    &graph, &token, chat_id, message_id, kind,
+                                                )
+                                                .await
+                                            } else {
+                                                api::set_reaction(
+                                                    &graph, &token, chat_id, message_id, kind,
+                                                )
+                                                .await
+                                            }
+                                            .map_err(|e| e.to_string())
+                                        }
+                                        .await;
+                                        let _ = tx_react.send((change, result));
+                                    });
+                                }
+                            }
+                            _ => {}
+                        }
+                        continue;
+                    }
+
+                    if app.raw_html_overlay.is_some() {
+                        match key.code {
+                            KeyCode::Esc | KeyCode::Char('H') | KeyCode::Char('q') => {
+                                app.raw_html_overlay = None;
+                            }
+                            KeyCode::Up | KeyCode::Char('k') => app.scroll_raw_html(-1),
+                            KeyCode::Down | KeyCode::Char('j') => app.scroll_raw_html(1),
+                            KeyCode::PageUp => app.scroll_raw_html(-10),
+                            KeyCode::PageDown => app.scroll_raw_html(10),
+                            _ => {}
+                        }
+                        continue;
+                    }
+
+                    // Member list overlay takes all keys until closed
+                    if app.members_overlay.is_some() {
+                        match key.code {
+                            KeyCode::Esc | KeyCode::Char('M') | KeyCode::Char('q') => {
+                                app.members_overlay = None;
+                            }
+                            KeyCode::Up | KeyCode::Char('k') => app.scroll_members(-1),
+                            KeyCode::Down | KeyCode::Char('j') => app.scroll_members(1),
+                            KeyCode::PageUp => app.scroll_members(-10),
+                            KeyCode::PageDown => app.scroll_members(10),
+                            _ => {}
+                        }
+                        continue;
+                    }
+
+                    // Handle command prompt
+                    if app.command_mode {
+                        match key.code {
+                            KeyCode::Esc => {
+                                app.command_mode = false;
+                                app.command_buffer.clear();
+                            }
+                            KeyCode::Enter => {
+                                app.command_mode = false;
+                                let command = std::mem::take(&mut app.command_buffer);
+                                run_command(app, &command);
+                            }
+                            KeyCode::Backspace => {
+                                app::pop_grapheme(&mut app.command_buffer);
+                            }
+                            KeyCode::Char(c) => app.command_buffer.push(c),
+                            _ => {}
+                        }
+                        continue;
+                    }
+
+                    // Handle search prompt
+                    if app.search_mode {
+                        match key.code {
+                            KeyCode::Esc => app.clear_search(),
+                            KeyCode::Enter => app.submit_search(),
+                            KeyCode::Backspace => {
+                                app::pop_grapheme(&mut app.search_query);
+                                app.messages_dirty = true;
+                            }
+                            KeyCode::Char(c) => {
+                                app.search_query.push(c);
+                                app.messages_dirty = true;
+                            }
+                            _ => {}
+                        }
+                        continue;
+                    }
+
+                    // F5 / Ctrl+R refresh now, even while composing
+                    let refresh_key = key.code == KeyCode::F(5)
+                        || (key.code == KeyCode::Char('r')
+                            && key.modifiers.contains(KeyModifiers::CONTROL));
+
                     // Normal key handling
                     match key.code {
-                        KeyCode::Char('q') if !app.input_mode => return Ok(()),
+                        _ if refresh_key => {
+                            refresh_chats_now.notify_one();
+                            refresh_messages_now.notify_one();
+                            app.status = "Refreshing…".to_string();
+                        }
+                        KeyCode::F(6) => {
+                            app.reload_config();
+                            tx_list_interval.send_if_modified(|interval| {
+                                let changed = *interval != app.config.list_poll_interval();
+                                *interval = app.config.list_poll_interval();
+                                changed
+                            });
+                            tx_active_interval.send_if_modified(|interval| {
+                                let changed = *interval != app.config.active_poll_interval();
+                                *interval = app.config.active_poll_interval();
+                                changed
+                            });
+                        }
+                        KeyCode::Char('q') if !app.input_mode => {
+                            if app.input_buffer.is_empty() {
+                                app.save_ui_state();
+                                return Ok(());
+                            }
+                            // Don't lose an unsent draft without asking
+                            app.confirm_quit = true;
+                        }
                         KeyCode::Tab if !app.input_mode => {
                             // Toggle focused pane
                             app.focused_pane = match app.focused_pane {
@@ -438,73 +1309,268 @@ async fn run_app(
                                 crate::app::FocusedPane::ChatList => app.previous_chat(),
                                 crate::app::FocusedPane::Messages => {
                                     // Scroll messages up
-                                    app.snap_to_bottom = false;
-                                    app.scroll_offset = app.scroll_offset.saturating_sub(1);
+                                    app.scroll_messages_up(1);
                                 }
                             }
                         }
                         KeyCode::Char('v') if !app.input_mode => {
                             // View image - open image viewer if images are available
                             if let Some(img) = app.get_current_viewable_image().cloned() {
-                                let url = img.url.clone();
+                                if !app.can_view_image(&img.url) {
+                                    app.status = FILE_SCOPES_MISSING.to_string();
+                                    continue;
+                                }
+                                spawn_image_download(
+                                    &img,
+                                    tx_image.clone(),
+                                    http_client.clone(),
+                                    app.supports_graphics(),
+                                );
                                 app.start_viewing_image(img);
-                                spawn_image_download(url, tx_image.clone(), http_client.clone());
                             }
                         }
-                        KeyCode::Char('i') if !app.input_mode => {
-                            app.input_mode = true;
-                            app.input_buffer.clear();
+                        KeyCode::Char('P') if !app.input_mode => {
+                            for img in app.open_gallery() {
+                                spawn_thumbnail_download(&img);
+                            }
                         }
-                        KeyCode::Esc if app.input_mode => {
-                            app.input_mode = false;
-                            app.input_buffer.clear();
+                        KeyCode::Char('b') if !app.input_mode => app.open_notif_overlay(),
+                        KeyCode::Char('r') if !app.input_mode => app.open_reaction_picker(),
+                        KeyCode::Char('a') if !app.input_mode => app.open_attachments_drawer(),
+                        KeyCode::Char('I') if !app.input_mode => {
+                            app.redetect_image_picker();
+                            app.messages_dirty = true;
                         }
-                        KeyCode::Enter if app.input_mode => {
-                            if !app.input_buffer.is_empty() {
-                                let message = app.input_buffer.clone();
-                                app.input_buffer.clear();
-                                app.input_mode = false;
-
-                                // Send message logic
+                        KeyCode::Enter if !app.input_mode => {
+                            // Open the focused message's file, or pick one of several;
+                            // messages without files expand or collapse their quotes
+                            match app.focused_file_rows().as_slice() {
+                                [] => app.toggle_quote_expansion(),
+                                [row] => {
+                                    if let Some(entry) =
+                                        app.chat_attachments().into_iter().nth(*row)
+                                    {
+                                        spawn_attachment_open(app, entry);
+                                    }
+                                }
+                                [first, ..] => app.attachments_drawer = Some(*first),
+                            }
+                        }
+                        KeyCode::Char('x') if !app.input_mode => {
+                            // A new row index is picked up by the selection check below
+                            let selection_moved = app.toggle_mute_selected();
+                            if selection_moved && app.selected_index == previous_index {
+                                open_selected_chat(app);
+                            }
+                        }
+                        KeyCode::Char('X') if !app.input_mode => {
+                            // A new row index is picked up by the selection check below
+                            let selection_moved = app.toggle_show_muted();
+                            if selection_moved && app.selected_index == previous_index {
+                                open_selected_chat(app);
+                            }
+                        }
+                        KeyCode::Char(':') if !app.input_mode => {
+                            app.command_mode = true;
+                            app.command_buffer.clear();
+                        }
+                        KeyCode::Char('<') if !app.input_mode => {
+                            app.set_split_ratio(app.split_ratio.saturating_sub(5));
+                        }
+                        KeyCode::Char('>') if !app.input_mode => {
+                            app.set_split_ratio(app.split_ratio + 5);
+                        }
+                        KeyCode::Char('D') if !app.input_mode => {
+                            app.config.density = app.config.density.toggled();
+                            app.messages_dirty = true;
+                            app.status = format!("Layout density: {:?}", app.config.density);
+                        }
+                        KeyCode::Char('F') if !app.input_mode => {
+                            app.start_search();
+                        }
+                        KeyCode::Char('n') if !app.input_mode => app.next_match(),
+                        KeyCode::Char('N') if !app.input_mode => app.previous_match(),
+                        KeyCode::Esc if !app.input_mode => {
+                            app.clear_search();
+                            app.clear_message_focus();
+                        }
+                        KeyCode::Char('A') if !app.input_mode => {
+                            // Only interrupt the session when the saved sign-in is really gone
+                            if auth::get_valid_token_silent().await.is_ok() {
+                                app.status = "Already signed in".to_string();
+                            } else {
+                                app.status = match reauthenticate(terminal).await {
+                                    Ok(()) => {
+                                        app.record_auth(true);
+                                        "Signed in again".to_string()
+                                    }
+                                    Err(e) => {
+                                        log_error!("Re-authentication failed: {:#}", e);
+                                        format!("Sign-in failed: {} (A to try again)", e)
+                                    }
+                                };
+                                // Everything that failed meanwhile is worth retrying now
                                 if let Some(chat) = app.get_selected_chat() {
                                     let chat_id = chat.id.clone();
-                                    let chat_index = app.selected_index;
-                                    let tx = tx.clone();
-                                    let tx_chats = tx_chats.clone();
-
-                                    app.snap_to_bottom = true;
-                                    tokio::spawn(async move {
-                                        if let Ok(token) = auth::get_valid_token_silent().await {
-                                            if api::send_message(&token, &chat_id, &message)
-                                                .await
-                                                .is_ok()
-                                            {
-                                                // Reload messages
-                                                if let Ok(messages) =
-                                                    api::get_messages(&token, &chat_id).await
-                                                {
-                                                    let _ = tx.send((chat_index, messages));
-                                                }
-                                                // Refresh chat list to update last message preview
-                                                if let Ok(chats) = api::get_chats(&token).await {
-                                                    let _ = tx_chats.send(chats);
-                                                }
-                                            }
-                                        }
-                                    });
+                                    app.set_loading_messages(true);
+                                    spawn_message_load(chat_id, tx.clone());
+                                }
+                            }
+                        }
+                        KeyCode::Char('H') if !app.input_mode && app.debug => app.open_raw_html(),
+                        KeyCode::Char('M')
+                            if !app.input_mode && app.get_selected_chat().is_some() =>
+                        {
+                            app.members_overlay = Some(0);
+                        }
+                        KeyCode::Char('m') if !app.input_mode => {
+                            // Mark the selected chat as read on the server
+                            if let Some(chat) = app.get_selected_chat() {
+                                let chat_id = chat.id.clone();
+                                let user_id = app.current_user_id.clone();
+                                let tx_read = tx_read.clone();
+                                let graph = graph.clone();
+                                app.status = "Marking as read...".to_string();
+                                spawn_task(tasks, shutdown, async move {
+                                    let result = async {
+                                        let token = auth::get_valid_token_silent()
+                                            .await
+                                            .map_err(|e| format!("Auth error: {}", e))?;
+                                        // Without a user or tenant id the call can't be made
+                                        let (Some(user_id), Some(tenant_id)) =
+                                            (user_id, auth::tenant_id_from_token(&token))
+                                        else {
+                                            return Ok(false);
+                                        };
+                                        api::mark_chat_read(
+                                            &graph, &token, &chat_id, &user_id, &tenant_id,
+                                        )
+                                        .await
+                                        .map_err(|e| e.to_string())
+                                    }
+                                    .await;
+                                    let _ = tx_read.send((chat_id, result));
+                                });
+                            }
+                        }
+                        KeyCode::Char('p') if !app.input_mode => app.toggle_pin_focused(),
+                        KeyCode::Char('L') if !app.input_mode => {
+                            match app.focused_message_permalink() {
+                                Some(link) => {
+                                    app.status = match clipboard::copy_text(&link) {
+                                        Ok(()) => "Copied message link to clipboard".to_string(),
+                                        // Still show it, so it can be selected by hand
+                                        Err(e) => format!("{}: {}", e, link),
+                                    };
                                 }
+                                None if app.focused_message_id.is_none() => {
+                                    app.status = "Move to a message with J/K first".to_string();
+                                }
+                                None => app.status = "That message isn't sent yet".to_string(),
+                            }
+                        }
+                        KeyCode::Char('J') if !app.input_mode => app.move_message_focus(1),
+                        KeyCode::Char('K') if !app.input_mode => app.move_message_focus(-1),
+                        KeyCode::Char('S') if !app.input_mode => {
+                            // Retry the most recent failed send in this chat
+                            if let Some(failed) = app.last_failed_outgoing() {
+                                let local_id = failed.message.id.clone();
+                                app.set_outgoing_state(&local_id, api::SendState::Sending);
+                                app.snap_to_bottom = true;
+                                spawn_send_message(
+                                    failed.chat_id.clone(),
+                                    local_id,
+                                    failed.content(),
+                                    failed.reply_to.clone(),
+                                    failed.message.importance(),
+                                    Some(failed),
+                                    app.current_user_name.clone(),
+                                    tx.clone(),
+                                    tx_chats.clone(),
+                                    tx_send_result.clone(),
+                                );
                             }
                         }
-                        KeyCode::Backspace if app.input_mode => {
-                            app.input_buffer.pop();
+                        KeyCode::Char('R') if !app.input_mode && app.messages_error.is_some() => {
+                            // Retry a chat whose messages failed to load
+                            if let Some(chat) = app.get_selected_chat() {
+                                let chat_id = chat.id.clone();
+                                app.set_loading_messages(true);
+                                spawn_message_load(chat_id, tx.clone());
+                            }
+                        }
+                        KeyCode::Char('R') if !app.input_mode => app.start_reply(),
+                        KeyCode::Char('o') if !app.input_mode => {
+                            // Open the first link in the message under the cursor
+                            let links = app.focused_message_links();
+                            match links.first() {
+                                Some(url) => {
+                                    open_link(app, url);
+                                    if links.len() > 1 {
+                                        app.status.push_str(&format!(
+                                            " (first of {} links; click to open others)",
+                                            links.len()
+                                        ));
+                                    }
+                                }
+                                None => {
+                                    app.status = "No link in the selected message (J/K to select)"
+                                        .to_string()
+                                }
+                            }
+                        }
+                        KeyCode::Char('i') if !app.input_mode && !app.online => {
+                            app.status = "Offline — press A to re-authenticate".to_string();
+                        }
+                        KeyCode::Char('i') if !app.input_mode => {
+                            // Resume any draft left in the compose buffer
+                            app.input_mode = true;
+                        }
+                        KeyCode::Esc if app.input_mode && app.reply_to.is_some() => {
+                            // First ESC drops the reply context, keeping the text
+                            app.reply_to = None;
                         }
-                        KeyCode::Char(c) if app.input_mode => {
-                            app.input_buffer.push(c);
+                        KeyCode::Esc if app.input_mode => {
+                            // Keep the text as a draft for this chat
+                            app.input_mode = false;
                         }
+                        KeyCode::Enter if app.input_mode && app.compose_too_long() => {
+                            app.status = format!(
+                                "Message too long to send: Teams accepts up to {} KB",
+                                app::MAX_MESSAGE_BYTES / 1024
+                            );
+                        }
+                        KeyCode::Enter if app.input_mode && !app.online => {
+                            app.status =
+                                "Offline — the draft is kept; press Esc, then A to re-authenticate"
+                                    .to_string();
+                        }
+                        KeyCode::Enter if app.input_mode && !app.input_buffer.is_empty() => {
+                            // Big group chats get a chance to back out first
+                            match app.send_needs_confirmation() {
+                                Some(recipients) => app.confirm_send = Some(recipients),
+                                None => send_input(app),
+                            }
+                        }
+                        KeyCode::Backspace if app.input_mode => app.delete_before_cursor(),
+                        KeyCode::Delete if app.input_mode => app.delete_at_cursor(),
+                        KeyCode::Left if app.input_mode => app.move_cursor(-1),
+                        KeyCode::Right if app.input_mode => app.move_cursor(1),
+                        KeyCode::Home if app.input_mode => app.cursor_pos = 0,
+                        KeyCode::End if app.input_mode => app.cursor_pos = app.input_buffer.len(),
+                        KeyCode::Char('u')
+                            if app.input_mode && key.modifiers.contains(KeyModifiers::CONTROL) =>
+                        {
+                            app.compose_importance = app.compose_importance.next();
+                        }
+                        KeyCode::Char(c) if app.input_mode => app.insert_at_cursor(c),
                         KeyCode::PageUp => {
-                            app.snap_to_bottom = false;
-                            app.scroll_offset = app.scroll_offset.saturating_sub(10);
+                            app.scroll_messages_up(10);
                         }
+                        KeyCode::End | KeyCode::Char('G') => app.scroll_to_newest(),
+                        KeyCode::Home => app.scroll_to_oldest(),
+                        KeyCode::Char('g') if after_g => app.scroll_to_oldest(),
+                        KeyCode::Char('g') => app.pending_g = true,
                         KeyCode::PageDown => {
                             app.scroll_offset = app.scroll_offset.saturating_add(10);
                             if app.scroll_offset >= app.max_scroll {
@@ -531,20 +1597,74 @@ async fn run_app(
 
                     match mouse_event.kind {
                         MouseEventKind::Down(MouseButton::Left) => {
-                            if in_chat_list {
+                            if app.is_on_split_divider(x, y) {
+                                // Start resizing the chat list/messages split
+                                app.dragging_split = true;
+                            } else if let Some(id) = app.pinned_at(x, y).map(str::to_string) {
+                                app.active_pane = ActivePane::Messages;
+                                app.focused_pane = crate::app::FocusedPane::Messages;
+                                app.jump_to_message(&id);
+                            } else if in_chat_list {
                                 app.active_pane = ActivePane::ChatList;
                                 app.focused_pane = crate::app::FocusedPane::ChatList;
 
-                                // Calculate which chat was clicked (accounting for border)
+                                // Calculate which chat was clicked (accounting for border
+                                // and how far the list is scrolled)
                                 let inner_y = y.saturating_sub(app.chat_list_area.y + 1);
-                                let clicked_index = inner_y as usize;
+                                let inner_height = app.chat_list_area.height.saturating_sub(2);
+                                let clicked_index = app.chat_list_state.offset() + inner_y as usize;
 
-                                if clicked_index < app.chats.len() {
+                                let on_row = y > app.chat_list_area.y && inner_y < inner_height;
+                                if on_row && clicked_index < app.chats.len() {
                                     app.selected_index = clicked_index;
                                 }
                             } else if in_messages {
                                 app.active_pane = ActivePane::Messages;
                                 app.focused_pane = crate::app::FocusedPane::Messages;
+
+                                // Start a new text selection at the click position
+                                let position = app.messages_position(x, y);
+                                app.selection = Some((position, position));
+                            }
+                            if !in_messages || app.dragging_split {
+                                app.selection = None;
+                            }
+                        }
+                        MouseEventKind::Drag(MouseButton::Left) => {
+                            if app.dragging_split {
+                                app.drag_split_to(x);
+                            } else {
+                                // Extend the selection, clamped to the messages text area
+                                let position = app.messages_position(x, y);
+                                if let Some((_, ref mut cursor)) = app.selection {
+                                    *cursor = position;
+                                }
+                            }
+                        }
+                        MouseEventKind::Up(MouseButton::Left) => {
+                            if app.dragging_split {
+                                app.dragging_split = false;
+                            } else if let Some(text) = app.selected_text() {
+                                // Copy the selection on release
+                                match clipboard::copy_text(&text) {
+                                    Ok(()) => {
+                                        app.status = format!(
+                                            "Copied {} characters to clipboard",
+                                            text.chars().count()
+                                        );
+                                    }
+                                    Err(e) => {
+                                        app.status = format!("Copy failed: {}", e);
+                                    }
+                                }
+                            } else {
+                                // A plain click on a link opens it
+                                let clicked = app.selection.take().map(|(anchor, _)| anchor);
+                                if let Some(url) =
+                                    clicked.and_then(|pos| app.link_at(pos)).map(str::to_string)
+                                {
+                                    open_link(app, &url);
+                                }
                             }
                         }
                         MouseEventKind::ScrollUp => {
@@ -559,8 +1679,8 @@ async fn run_app(
                                 app.active_pane = ActivePane::Messages;
                                 app.focused_pane = crate::app::FocusedPane::Messages;
                                 // Scroll messages up
-                                app.snap_to_bottom = false;
-                                app.scroll_offset = app.scroll_offset.saturating_sub(3);
+                                let step = app.wheel_step(true);
+                                app.scroll_messages_up(step);
                             }
                         }
                         MouseEventKind::ScrollDown => {
@@ -576,7 +1696,8 @@ async fn run_app(
                                 app.active_pane = ActivePane::Messages;
                                 app.focused_pane = crate::app::FocusedPane::Messages;
                                 // Scroll messages down
-                                app.scroll_offset = app.scroll_offset.saturating_add(3);
+                                let step = app.wheel_step(false);
+                                app.scroll_offset = app.scroll_offset.saturating_add(step);
                                 if app.scroll_offset >= app.max_scroll {
                                     app.snap_to_bottom = true;
                                 }
@@ -588,26 +1709,207 @@ async fn run_app(
                 _ => {}
             }
 
+            // Scrolling past the top fetches the next older page, one request at a time
+            if let Some(next_link) = app.take_older_request() {
+                if let Some(chat) = app.get_selected_chat() {
+                    let chat_id = chat.id.clone();
+                    let tx_older = tx_older.clone();
+                    spawn_task(tasks, shutdown, async move {
+                        let result = async {
+                            let token = auth::get_valid_token_silent()
+                                .await
+                                .map_err(|e| format!("Auth error: {}", e))?;
+                            api::get_older_messages(&token, &next_link)
+                                .await
+                                .map_err(|e| e.to_string())
+                        }
+                        .await;
+                        let _ = tx_older.send((chat_id, result));
+                    });
+                }
+            }
+
             // If selection changed, spawn a background task to load messages
             if previous_index != app.selected_index {
                 if let Some(chat) = app.get_selected_chat() {
                     let chat_id = chat.id.clone();
-                    let chat_index = app.selected_index;
-                    let tx_clone = tx.clone();
+                    app.prepare_chat_switch();
+                    spawn_message_load(chat_id, tx.clone());
+                }
+            }
+        }
+    }
+}
+
+/// Open a link in the default browser, reporting the outcome in the status bar
+fn open_link(app: &mut App, url: &str) {
+    app.status = match open::that_detached(url) {
+        Ok(()) => format!("Opened {}", url),
+        Err(e) => format!("Could not open {}: {}", url, e),
+    };
+}
 
-                    app.set_loading_messages(true);
-                    app.set_messages(Vec::new()); // Clear old messages immediately
-                    app.snap_to_bottom = true; // Snap to bottom for new chat
+/// Shown when the token lacks the scopes needed for images stored in SharePoint/OneDrive
+const FILE_SCOPES_MISSING: &str = "Shared images can't be shown: your organisation hasn't granted \
+     Files.Read.All or Sites.Read.All (pasted images still work)";
 
-                    tokio::spawn(async move {
-                        if let Ok(token) = auth::get_valid_token_silent().await {
-                            if let Ok(messages) = api::get_messages(&token, &chat_id).await {
-                                let _ = tx_clone.send((chat_index, messages));
-                            }
-                        }
-                    });
-                }
+/// Longest the event loop waits between redraws, and while idle
+const FRAME: std::time::Duration = std::time::Duration::from_millis(100);
+const IDLE_FRAME: std::time::Duration = std::time::Duration::from_millis(500);
+
+/// Wait up to `timeout` for a terminal event, returning early without one when
+/// a polling task raises `wake`
+fn wait_for_event(
+    timeout: std::time::Duration,
+    wake: &std::sync::atomic::AtomicBool,
+) -> io::Result<bool> {
+    let deadline = std::time::Instant::now() + timeout;
+    loop {
+        let left = deadline.saturating_duration_since(std::time::Instant::now());
+        if event::poll(left.min(FRAME))? {
+            return Ok(true);
+        }
+        if left <= FRAME || wake.swap(false, std::sync::atomic::Ordering::Relaxed) {
+            return Ok(false);
+        }
+    }
+}
+
+/// How long quitting waits for background tasks to wind down
+const SHUTDOWN_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(1);
+
+/// Spawn a background task that is dropped at its next await once `shutdown` is cancelled
+fn spawn_task<F>(tasks: &TaskTracker, shutdown: &CancellationToken, task: F)
+where
+    F: std::future::Future<Output = ()> + Send + 'static,
+{
+    let shutdown = shutdown.clone();
+    tasks.spawn(async move {
+        shutdown.run_until_cancelled(task).await;
+    });
+}
+
+/// Ticker for a polling task: ticks right away, then every `period`, skipping
+/// ticks missed while a poll was still running
+fn poll_interval(period: std::time::Duration) -> tokio::time::Interval {
+    let mut interval = tokio::time::interval(period);
+    interval.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Skip);
+    interval
+}
+
+/// Download an image, retrying a failed thumbnail from its original
+async fn fetch_image(
+    client: &reqwest::Client,
+    url: &str,
+    fallback: Option<&str>,
+) -> Result<image_display::DownloadedImage, String> {
+    let result = async {
+        let token = auth::get_valid_token_silent()
+            .await
+            .map_err(|e| format!("Auth error: {}", e))?;
+        let mut image = image_display::download_image(client, url, &token).await;
+        if let (Err(e), Some(fallback)) = (&image, fallback) {
+            log_warn!("Thumbnail download failed, trying the original: {:#}", e);
+            image = image_display::download_image(client, fallback, &token).await;
+        }
+        image.map_err(|e| format!("Download error: {:#}", e))
+    }
+    .await;
+    if let Err(ref e) = result {
+        log_error!("Image download failed for {}: {}", url, e);
+    }
+    result
+}
+
+/// Whether a token lookup shows the sign-in still works; `None` when it failed for
+/// a reason that may pass on its own, like a network error
+fn sign_in_valid(token: &Result<String>) -> Option<bool> {
+    match token {
+        Ok(_) => Some(true),
+        Err(e) if e.is::<auth::SignInExpired>() => Some(false),
+        Err(_) => None,
+    }
+}
+
+/// How many times to try loading a newly selected chat before giving up
+const MESSAGE_LOAD_ATTEMPTS: u32 = 3;
+
+/// Load a chat's messages, retrying with a doubling backoff so an expired token
+/// or transient network error doesn't leave the pane stuck on "Loading"
+async fn load_messages_with_retry(
+    graph: &api::Graph,
+    chat_id: &str,
+    page_size: usize,
+) -> Result<api::MessagePage, api::MessagesError> {
+    let mut delay = std::time::Duration::from_millis(500);
+    let mut attempt = 1;
+    loop {
+        let result = async {
+            let token = auth::get_valid_token_silent()
+                .await
+                .map_err(|e| e.context("Auth error"))?;
+            api::get_messages(graph, &token, chat_id, page_size).await
+        }
+        .await;
+
+        match result {
+            Ok(page) => return Ok(page),
+            Err(e) if e.is::<api::ChatUnavailable>() => {
+                log_warn!("Chat {} is no longer available", chat_id);
+                return Err(api::MessagesError::Unavailable);
             }
+            Err(e) if attempt >= MESSAGE_LOAD_ATTEMPTS => {
+                let e = format!("{:#}", e);
+                log_error!("Loading messages failed after {} attempts: {}", attempt, e);
+                return Err(api::MessagesError::Failed(e));
+            }
+            Err(e) => {
+                log_warn!("Loading messages failed (attempt {}): {:#}", attempt, e);
+                tokio::time::sleep(delay).await;
+                delay *= 2;
+                attempt += 1;
+            }
+        }
+    }
+}
+
+/// Run a command entered at the ':' prompt
+fn run_command(app: &mut App, command: &str) {
+    let mut parts = command.split_whitespace();
+    match parts.next() {
+        Some("export") => {
+            let format = match parts.next() {
+                Some("txt") | Some("text") => export::ExportFormat::PlainText,
+                _ => export::ExportFormat::Markdown,
+            };
+            if app.messages.is_empty() {
+                app.status = "No messages loaded - nothing to export".to_string();
+                return;
+            }
+            let chat_name = app
+                .get_selected_chat()
+                .and_then(|c| c.cached_display_name.clone())
+                .unwrap_or_else(|| "chat".to_string());
+            app.status = match export::export_chat(
+                &chat_name,
+                &app.messages,
+                format,
+                &app.config.image_extensions,
+            ) {
+                Ok(path) => format!("Exported chat to {}", path.display()),
+                Err(e) => format!("Export failed: {}", e),
+            };
+        }
+        Some("chatid") => {
+            // For default_chat_id in config.json
+            app.status = match app.get_selected_chat() {
+                Some(chat) => format!("Chat id: {}", chat.id),
+                None => "No chat selected".to_string(),
+            };
+        }
+        Some(other) => {
+            app.status = format!("Unknown command: {}", other);
         }
+        None => {}
     }
 }
@@ -0,0 +1,154 @@
+//! First-run setup wizard
+//!
+//! Runs before sign-in when there is no config.json and no `CLIENT_ID` /
+//! `TENANT_ID` in the environment, asking for the app registration and cloud
+//! and writing the answers to config.json.
+
+use crate::cloud::Cloud;
+use anyhow::Result;
+use std::io::{self, BufRead, IsTerminal, Write};
+
+/// Public client used when the user skips entering their own app registration
+pub const DEFAULT_CLIENT_ID: &str = "d3590ed6-52b3-4102-aeff-aad2292ab01c";
+const DEFAULT_TENANT: &str = "common";
+
+/// Whether the wizard should run: nothing configured and someone at the terminal
+pub fn needed() -> bool {
+    let env_set = |name: &str| std::env::var(name).is_ok_and(|v| !v.trim().is_empty());
+    !crate::config::config_exists()
+        && !env_set("CLIENT_ID")
+        && !env_set("TENANT_ID")
+        && io::stdin().is_terminal()
+}
+
+/// Ask for the client id, tenant and cloud, then save them to config.json
+///
+/// Skipping, or closing stdin, saves the defaults so the wizard doesn't come back.
+pub fn run() -> Result<()> {
+    println!("Welcome! No configuration was found, so let's set one up.");
+    println!("See AZURE_SETUP.md for how to register an app and find these values.\n");
+
+    let mut input = io::stdin().lock();
+    let answers = if prompt(&mut input, "Set up now? [Y/n, n uses the defaults]")?
+        .is_some_and(|a| a.eq_ignore_ascii_case("n") || a.eq_ignore_ascii_case("no"))
+    {
+        Answers::default()
+    } else {
+        ask(&mut input)?
+    };
+
+    // The commercial hosts are the built-in default, so only other clouds are written out
+    let endpoints = (answers.cloud != Cloud::Commercial).then(|| {
+        (
+            answers.cloud.graph_base_url(),
+            answers.cloud.login_base_url(),
+        )
+    });
+    let path = crate::config::save_setup(&answers.client_id, &answers.tenant_id, endpoints)?;
+    if answers.client_id == DEFAULT_CLIENT_ID {
+        println!("Using the default public client; set client_id in config.json to use your own.");
+    }
+    println!("✓ Saved {}\n", path.display());
+    Ok(())
+}
+
+struct Answers {
+    client_id: String,
+    tenant_id: String,
+    cloud: Cloud,
+}
+
+impl Default for Answers {
+    fn default() -> Self {
+        Self {
+            client_id: DEFAULT_CLIENT_ID.to_string(),
+            tenant_id: DEFAULT_TENANT.to_string(),
+            cloud: Cloud::Commercial,
+        }
+    }
+}
+
+/// Prompt until each answer is valid; a blank answer takes the default
+fn ask(input: &mut impl BufRead) -> Result<Answers> {
+    let mut answers = Answers::default();
+
+    loop {
+        match prompt(input, "Client ID [blank for the default public client]")? {
+            None => break,
+            Some(id) if is_guid(&id) => {
+                answers.client_id = id;
+                break;
+            }
+            Some(_) => println!("  That isn't a client ID; expected a GUID like 00000000-0000-0000-0000-000000000000"),
+        }
+    }
+
+    loop {
+        match prompt(input, "Tenant ID or domain [blank for common]")? {
+            None => break,
+            Some(tenant) if !tenant.contains(char::is_whitespace) => {
+                answers.tenant_id = tenant;
+                break;
+            }
+            Some(_) => println!("  The tenant can't contain spaces"),
+        }
+    }
+
+    loop {
+        match prompt(
+            input,
+            "Cloud: commercial, gcchigh, dod or china [blank for commercial]",
+        )? {
+            None => break,
+            Some(cloud) => match cloud.parse::<Cloud>() {
+                Ok(cloud) => {
+                    answers.cloud = cloud;
+                    break;
+                }
+                Err(e) => println!("  {}", e),
+            },
+        }
+    }
+
+    Ok(answers)
+}
+
+/// Print a prompt and read one trimmed line; `None` for a blank line or end of input
+fn prompt(input: &mut impl BufRead, question: &str) -> Result<Option<String>> {
+    print!("{}: ", question);
+    io::stdout().flush()?;
+    let mut line = String::new();
+    input.read_line(&mut line)?;
+    let line = line.trim();
+    Ok((!line.is_empty()).then(|| line.to_string()))
+}
+
+/// Azure AD client ids are GUIDs in 8-4-4-4-12 hex form
+fn is_guid(value: &str) -> bool {
+    let groups: Vec<&str> = value.split('-').collect();
+    groups.len() == 5
+        && groups
+            .iter()
+            .zip([8, 4, 4, 4, 12])
+            .all(|(group, len)| group.len() == len && group.chars().all(|c| c.is_ascii_hexdigit()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_ask_defaults_and_retries() {
+        let mut input = "not-a-guid\n\nexample.onmicrosoft.com\nmars\ngcchigh\n".as_bytes();
+        let answers = ask(&mut input).unwrap();
+        assert_eq!(answers.client_id, DEFAULT_CLIENT_ID);
+        assert_eq!(answers.tenant_id, "example.onmicrosoft.com");
+        assert_eq!(answers.cloud, Cloud::GccHigh);
+
+        let mut input = "D3590ED6-52B3-4102-AEFF-AAD2292AB01C\n".as_bytes();
+        let answers = ask(&mut input).unwrap();
+        assert_eq!(answers.client_id, "D3590ED6-52B3-4102-AEFF-AAD2292AB01C");
+        assert_eq!(answers.tenant_id, DEFAULT_TENANT);
+        assert_eq!(answers.cloud, Cloud::Commercial);
+    }
+}
@@ -0,0 +1,76 @@
+//! Single-line progress spinner for the startup steps before the TUI starts
+
+use std::io::{self, IsTerminal, Write};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+const FRAMES: [&str; 10] = ["⠋", "⠙", "⠹", "⠸", "⠼", "⠴", "⠦", "⠧", "⠇", "⠏"];
+const FRAME_INTERVAL: Duration = Duration::from_millis(80);
+
+/// Redraws "<frame> <message>" in place until finished. When stdout is not a
+/// terminal it just prints the message once.
+pub struct Spinner {
+    message: Arc<Mutex<String>>,
+    task: Option<tokio::task::JoinHandle<()>>,
+}
+
+impl Spinner {
+    pub fn start(message: &str) -> Self {
+        let message = Arc::new(Mutex::new(message.to_string()));
+
+        if !io::stdout().is_terminal() {
+            println!("{}", message.lock().map(|m| m.clone()).unwrap_or_default());
+            return Self {
+                message,
+                task: None,
+            };
+        }
+
+        let shared = Arc::clone(&message);
+        let task = tokio::spawn(async move {
+            let mut interval = tokio::time::interval(FRAME_INTERVAL);
+            for frame in FRAMES.iter().cycle() {
+                interval.tick().await;
+                let text = shared.lock().map(|m| m.clone()).unwrap_or_default();
+                let mut stdout = io::stdout();
+                // \x1b[2K clears the line so a shorter message leaves no leftovers
+                let _ = write!(stdout, "\r\x1b[2K{} {}", frame, text);
+                let _ = stdout.flush();
+            }
+        });
+
+        Self {
+            message,
+            task: Some(task),
+        }
+    }
+
+    /// Change the text shown next to the spinner
+    pub fn set_message(&self, message: impl Into<String>) {
+        if let Ok(mut current) = self.message.lock() {
+            *current = message.into();
+        }
+    }
+
+    /// Stop spinning and replace the line with a final result
+    pub fn finish(mut self, line: &str) {
+        self.clear();
+        println!("{}", line);
+    }
+
+    /// Stop spinning and erase the line, e.g. before printing something else
+    pub fn clear(&mut self) {
+        if let Some(task) = self.task.take() {
+            task.abort();
+            let mut stdout = io::stdout();
+            let _ = write!(stdout, "\r\x1b[2K");
+            let _ = stdout.flush();
+        }
+    }
+}
+
+impl Drop for Spinner {
+    fn drop(&mut self) {
+        self.clear();
+    }
+}
@@ -0,0 +1,49 @@
+//! UI state persisted between sessions
+//!
+//! Unlike `config.json`, which the user edits, `ui_state.json` is written by
+//! the app itself (drafts, layout and similar session leftovers).
+
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fs;
+use std::path::PathBuf;
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct UiState {
+    /// Unsent compose text keyed by chat id
+    pub drafts: HashMap<String, String>,
+    /// Chat list width as a percentage of the window
+    pub split_ratio: Option<u16>,
+    /// Ids of chats hidden from the chat list (shown dimmed at the bottom on request)
+    pub muted_chats: Vec<String>,
+    /// Ids of messages pinned above the messages pane, keyed by chat id, in pin order
+    pub pinned_messages: HashMap<String, Vec<String>>,
+    /// Chat open when the app last quit
+    pub last_chat_id: Option<String>,
+}
+
+fn get_state_path() -> Result<PathBuf> {
+    Ok(crate::config::get_app_dir()?.join("ui_state.json"))
+}
+
+impl UiState {
+    /// Load the saved state, starting fresh if it is missing or unreadable
+    pub fn load() -> Self {
+        get_state_path()
+            .ok()
+            .and_then(|path| fs::read_to_string(path).ok())
+            .and_then(|json| serde_json::from_str(&json).ok())
+            .unwrap_or_default()
+    }
+
+    pub fn save(&self) -> Result<()> {
+        let path = get_state_path()?;
+        // Write atomically: write to temp then rename
+        let tmp_path = path.with_extension("tmp");
+        fs::write(&tmp_path, serde_json::to_string_pretty(self)?)?;
+        fs::rename(tmp_path, path)?;
+        Ok(())
+    }
+}
@@ -0,0 +1,124 @@
+//! Timestamp display
+//!
+//! Graph timestamps are UTC. They are shown in the system's local zone unless
+//! `timezone` in config.json names an IANA zone (e.g. "Europe/London").
+
+use chrono::{DateTime, FixedOffset, Local, NaiveDateTime};
+use chrono_tz::Tz;
+
+/// Zone used for every timestamp shown in the UI
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum DisplayZone {
+    #[default]
+    Local,
+    Named(Tz),
+}
+
+impl DisplayZone {
+    /// Zone for the configured name; unknown names fall back to local time
+    pub fn from_config(name: Option<&str>) -> Self {
+        match name.map(str::parse::<Tz>) {
+            Some(Ok(tz)) => DisplayZone::Named(tz),
+            Some(Err(_)) => {
+                crate::logging::log_warn!(
+                    "Unknown timezone {:?} in config, using local time",
+                    name.unwrap_or_default()
+                );
+                DisplayZone::Local
+            }
+            None => DisplayZone::Local,
+        }
+    }
+
+    /// Format a timestamp in this zone with a chrono format string
+    pub fn format<Z: chrono::TimeZone>(&self, dt: &DateTime<Z>, fmt: &str) -> String {
+        match self {
+            DisplayZone::Local => dt.with_timezone(&Local).format(fmt).to_string(),
+            DisplayZone::Named(tz) => dt.with_timezone(tz).format(fmt).to_string(),
+        }
+    }
+}
+
+/// Parse a timestamp as sent by Graph
+///
+/// Strict RFC 3339 first; then the looser shapes seen in the wild: more than nine
+/// fractional digits, "+0000" offsets, and no offset at all (taken as UTC, which
+/// Graph always uses).
+pub fn parse_teams_datetime(value: &str) -> Option<DateTime<FixedOffset>> {
+    let value = value.trim();
+    if let Ok(dt) = DateTime::parse_from_rfc3339(value) {
+        return Some(dt);
+    }
+    let value = trim_fraction(value);
+    if let Ok(dt) = DateTime::parse_from_rfc3339(&value) {
+        return Some(dt);
+    }
+    if let Ok(dt) = DateTime::parse_from_str(&value, "%Y-%m-%dT%H:%M:%S%.f%z") {
+        return Some(dt);
+    }
+    let naive = value.trim_end_matches(['Z', 'z']).replacen(' ', "T", 1);
+    NaiveDateTime::parse_from_str(&naive, "%Y-%m-%dT%H:%M:%S%.f")
+        .ok()
+        .map(|dt| dt.and_utc().fixed_offset())
+}
+
+/// Cut fractional seconds to the nine digits chrono can parse
+fn trim_fraction(value: &str) -> String {
+    let Some(dot) = value.find('.') else {
+        return value.to_string();
+    };
+    let digits = value[dot + 1..]
+        .bytes()
+        .take_while(u8::is_ascii_digit)
+        .count();
+    if digits <= 9 {
+        return value.to_string();
+    }
+    format!("{}{}", &value[..dot + 10], &value[dot + 1 + digits..])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_teams_datetime_fallbacks() {
+        let expected = parse_teams_datetime("2024-05-01T10:00:00Z").unwrap();
+        for value in [
+            "2024-05-01T10:00:00.0000000Z",
+            "2024-05-01T10:00:00.123456789012Z",
+            "2024-05-01T10:00:00+0000",
+            "2024-05-01T10:00:00",
+            "2024-05-01 10:00:00.5",
+            " 2024-05-01T12:00:00+02:00 ",
+        ] {
+            let parsed = parse_teams_datetime(value).unwrap_or_else(|| panic!("{}", value));
+            assert_eq!(parsed.timestamp(), expected.timestamp(), "{}", value);
+        }
+        assert_eq!(parse_teams_datetime("yesterday"), None);
+        assert_eq!(parse_teams_datetime(""), None);
+    }
+
+    #[test]
+    fn test_utc_timestamp_renders_in_configured_zone() {
+        let dt = parse_teams_datetime("2025-11-21T19:11:33.123Z").unwrap();
+
+        let tokyo = DisplayZone::from_config(Some("Asia/Tokyo"));
+        assert_eq!(tokyo.format(&dt, "%b %d %H:%M"), "Nov 22 04:11");
+
+        let kolkata = DisplayZone::from_config(Some("Asia/Kolkata"));
+        assert_eq!(kolkata.format(&dt, "%Y-%m-%d %H:%M"), "2025-11-22 00:41");
+
+        let utc = DisplayZone::from_config(Some("UTC"));
+        assert_eq!(utc.format(&dt, "%H:%M"), "19:11");
+    }
+
+    #[test]
+    fn test_unknown_timezone_falls_back_to_local() {
+        assert_eq!(
+            DisplayZone::from_config(Some("Mars/Olympus_Mons")),
+            DisplayZone::Local
+        );
+        assert_eq!(DisplayZone::from_config(None), DisplayZone::Local);
+    }
+}
@@ -1,4 +1,8 @@
-use crate::app::{App, FocusedPane};
+use crate::api::{Importance, SendState, REACTIONS};
+use crate::app::{App, FocusedPane, MessageLink};
+use crate::config::{Density, NotifPref};
+use crate::html::BodyBlock;
+use crate::time::DisplayZone;
 use ratatui::{
     layout::{Constraint, Direction, Layout, Rect},
     style::{Color, Modifier, Style},
@@ -7,7 +11,19 @@ use ratatui::{
     Frame,
 };
 use ratatui_image::StatefulImage;
-use unicode_width::UnicodeWidthStr;
+use std::collections::HashMap;
+use unicode_segmentation::UnicodeSegmentation;
+use unicode_width::{UnicodeWidthChar, UnicodeWidthStr};
+
+/// Narrowest space worth showing a last message preview in, beside the chat name
+const MIN_PREVIEW_WIDTH: usize = 8;
+
+/// Appended to the last line of a message its sender edited
+const EDITED_SUFFIX: &str = " (edited)";
+/// Marker beside messages that @-mention me
+const MENTION_COLOR: Color = Color::Yellow;
+/// Most pinned messages listed above the messages pane
+const MAX_PINNED_ROWS: usize = 2;
 
 pub fn draw(f: &mut Frame, app: &mut App) {
     let main_chunks = Layout::default()
@@ -27,15 +43,15 @@ pub fn draw(f: &mut Frame, app: &mut App) {
         .direction(Direction::Horizontal)
         .constraints(
             [
-                Constraint::Percentage(30), // Chat list
-                Constraint::Percentage(70), // Messages
+                Constraint::Percentage(app.split_ratio),       // Chat list
+                Constraint::Percentage(100 - app.split_ratio), // Messages
             ]
             .as_ref(),
         )
         .split(main_chunks[0]);
 
-    // Split messages area vertically if in input mode
-    let messages_chunks = if app.input_mode {
+    // Split messages area vertically if a prompt is open
+    let messages_chunks = if app.input_mode || app.search_mode || app.command_mode {
         Layout::default()
             .direction(Direction::Vertical)
             .constraints(
@@ -50,32 +66,84 @@ pub fn draw(f: &mut Frame, app: &mut App) {
         std::rc::Rc::from(vec![content_chunks[1]].into_boxed_slice())
     };
 
+    // Pinned messages get a bar of their own above the messages
+    let pinned_rows = app.pinned_messages().0.len().min(MAX_PINNED_ROWS) as u16;
+    let (pinned_area, messages_pane) = if pinned_rows > 0 {
+        let chunks = Layout::default()
+            .direction(Direction::Vertical)
+            .constraints([Constraint::Length(pinned_rows + 2), Constraint::Min(3)])
+            .split(messages_chunks[0]);
+        (chunks[0], chunks[1])
+    } else {
+        (Rect::default(), messages_chunks[0])
+    };
+
     // Store pane areas for mouse click detection
+    app.content_area = main_chunks[0];
     app.chat_list_area = content_chunks[0];
-    app.messages_area = messages_chunks[0];
+    app.messages_area = messages_pane;
+    app.pinned_area = pinned_area;
 
     // Chat list
+    let now = chrono::Utc::now();
+    let row_width = content_chunks[0].width.saturating_sub(2) as usize; // Account for borders
     let items: Vec<ListItem> = app
         .chats
         .iter()
         .enumerate()
         .map(|(i, chat)| {
             let display_name = chat.cached_display_name.as_deref().unwrap_or("Unknown");
+            let muted = app.is_muted(&chat.id);
+            let unread = chat.is_unread() && !muted;
+            let marker = if unread { "● " } else { "  " };
+            let chat_type = format!("[{}] ", chat.chat_type);
+            let activity = chat
+                .last_updated
+                .as_deref()
+                .and_then(crate::time::parse_teams_datetime)
+                .map(|ts| relative_time(ts.with_timezone(&chrono::Utc), now, app.display_zone))
+                .unwrap_or_default();
+
+            // Truncate the name so the activity time stays right-aligned
+            let name_width = row_width
+                .saturating_sub(marker.width() + chat_type.width())
+                .saturating_sub(activity.width() + 1);
+            let name = truncate_to_width(display_name, name_width);
+            // The last message fills whatever room the name leaves
+            let preview_width = name_width.saturating_sub(name.width());
+            let preview = match chat.last_message_preview.as_deref() {
+                Some(text) if preview_width >= MIN_PREVIEW_WIDTH => {
+                    truncate_to_width(&format!("  {}", text), preview_width)
+                }
+                _ => String::new(),
+            };
+            let padding = row_width.saturating_sub(
+                marker.width()
+                    + chat_type.width()
+                    + name.width()
+                    + preview.width()
+                    + activity.width(),
+            );
 
             let style = if i == app.selected_index {
                 Style::default()
                     .fg(Color::Yellow)
                     .add_modifier(Modifier::BOLD)
+            } else if muted {
+                Style::default().fg(Color::DarkGray)
+            } else if unread {
+                Style::default().add_modifier(Modifier::BOLD)
             } else {
                 Style::default()
             };
 
             let content = Line::from(vec![
-                Span::styled(
-                    format!("[{}] ", chat.chat_type),
-                    Style::default().fg(Color::Cyan),
-                ),
-                Span::styled(display_name, style),
+                Span::styled(marker, Style::default().fg(Color::LightBlue)),
+                Span::styled(chat_type, Style::default().fg(Color::Cyan)),
+                Span::styled(name, style),
+                Span::styled(preview, Style::default().fg(Color::DarkGray)),
+                Span::raw(" ".repeat(padding)),
+                Span::styled(activity, Style::default().fg(Color::DarkGray)),
             ]);
 
             ListItem::new(content)
@@ -88,10 +156,17 @@ pub fn draw(f: &mut Frame, app: &mut App) {
         Style::default().fg(Color::White)
     };
 
+    let chat_list_title = match app.hidden_muted_count() {
+        0 => "Teams Chats (Tab to switch, ↑/↓ to navigate, q to quit)".to_string(),
+        hidden => format!(
+            "Teams Chats (Tab to switch, ↑/↓ to navigate, q to quit) · {} muted, X to show",
+            hidden
+        ),
+    };
     let list = List::new(items)
         .block(
             Block::default()
-                .title("Teams Chats (Tab to switch, ↑/↓ to navigate, q to quit)")
+                .title(chat_list_title)
                 .borders(Borders::ALL)
                 .border_style(chat_list_border_style),
         )
@@ -101,353 +176,56 @@ pub fn draw(f: &mut Frame, app: &mut App) {
                 .add_modifier(Modifier::BOLD),
         );
 
-    f.render_widget(list, content_chunks[0]);
+    let selected = (!app.chats.is_empty()).then_some(app.selected_index);
+    app.chat_list_state.select(selected);
+    f.render_stateful_widget(list, content_chunks[0], &mut app.chat_list_state);
 
     // Messages panel
-    let messages_content = if app.loading_messages || app.messages.is_empty() {
-        vec![Line::from("Loading messages...")]
-    } else {
-        // Reserve an extra column as a safety padding so text never touches the vertical border
-        // This prevents terminal selections (e.g. Ctrl+click) from accidentally including the '|' border
-        let width = messages_chunks[0].width.saturating_sub(3) as usize; // Account for borders + 1 pad
-        let max_line_width = (width as f32 * 0.9) as usize; // Max 90% width for messages
-
-        let mut lines = Vec::new();
-        let mut last_sender: Option<String> = None;
-        let mut last_message_time: Option<chrono::DateTime<chrono::FixedOffset>> = None;
-
-        // Take 100 newest messages
-        for msg in app.messages.iter().take(100).rev() {
-            let sender_name = msg
-                .from
-                .as_ref()
-                .and_then(|f| f.user.as_ref())
-                .and_then(|u| u.display_name.as_ref())
-                .map(|s| s.as_str())
-                .unwrap_or("Unknown");
-
-            let current_time = chrono::DateTime::parse_from_rfc3339(&msg.created_date_time).ok();
-
-            let is_me = app
-                .current_user_name
-                .as_ref()
-                .is_some_and(|me| sender_name == me);
-            let same_sender = last_sender.as_deref() == Some(sender_name);
-
-            let significant_time_gap =
-                if let (Some(curr), Some(last)) = (current_time, last_message_time) {
-                    let curr_hour = curr.format("%Y-%m-%d %H").to_string();
-                    let last_hour = last.format("%Y-%m-%d %H").to_string();
-                    curr_hour != last_hour
-                } else {
-                    false
-                };
-
-            let show_header = !same_sender || significant_time_gap;
-
-            last_sender = Some(sender_name.to_string());
-            last_message_time = current_time;
-
-            // Format date: 2025-11-21T19:11:33 -> Nov-21 19:11
-            let date_str = if let Some(dt) = current_time {
-                dt.format("%b %d %H:%M").to_string()
-            } else {
-                msg.created_date_time.clone()
-            };
-
-            let content = msg
-                .body
-                .as_ref()
-                .and_then(|b| b.content.as_ref())
-                .map(|c| c.as_str())
-                .unwrap_or("");
-
-            // Strip HTML tags and extract text content
-            let mut clean_content = content.to_string();
-
-            // Remove attachment tags (quoted messages) - they're just metadata
-            // Handle both self-closing <attachment ... /> and <attachment ...></attachment>
-            let mut attachment_removed = String::new();
-            let mut remaining = clean_content.as_str();
-
-            while let Some(attach_start) = remaining.find("<attachment") {
-                // Add text before the attachment tag
-                attachment_removed.push_str(&remaining[..attach_start]);
-
-                // Find the end of the opening tag
-                if let Some(tag_end) = remaining[attach_start..].find('>') {
-                    // Check if it's self-closing (ends with />)
-                    let tag_str = &remaining[attach_start..attach_start + tag_end];
-                    if tag_str.ends_with('/') {
-                        // Self-closing: <attachment ... />
-                        remaining = &remaining[attach_start + tag_end + 1..];
-                    } else {
-                        // Has closing tag: <attachment ...></attachment>
-                        remaining = &remaining[attach_start + tag_end + 1..];
-                        // Skip past closing </attachment> tag
-                        if let Some(close_start) = remaining.find("</attachment>") {
-                            remaining = &remaining[close_start + 13..]; // 13 = len("</attachment>")
-                        }
-                    }
-                } else {
-                    // Malformed tag, skip the <attachment part
-                    attachment_removed.push_str(&remaining[..attach_start + 11]);
-                    remaining = &remaining[attach_start + 11..];
-                }
-            }
-
-            // Add remaining text
-            attachment_removed.push_str(remaining);
-            clean_content = attachment_removed;
-
-            // Extract emoji alt text: <emoji ... alt="😅" ...> -> 😅
-            // Process emoji tags by finding them and replacing with alt text
-            let mut emoji_processed = String::new();
-            remaining = clean_content.as_str();
-
-            while let Some(emoji_start) = remaining.find("<emoji") {
-                // Add text before the emoji tag
-                emoji_processed.push_str(&remaining[..emoji_start]);
-
-                // Find the end of the opening tag
-                if let Some(tag_end) = remaining[emoji_start..].find('>') {
-                    let tag_str = &remaining[emoji_start..emoji_start + tag_end + 1];
-
-                    // Extract alt attribute value
-                    if let Some(alt_start) = tag_str.find("alt=\"") {
-                        let alt_value_start = alt_start + 5;
-                        if let Some(alt_end) = tag_str[alt_value_start..].find('"') {
-                            let emoji = &tag_str[alt_value_start..alt_value_start + alt_end];
-                            emoji_processed.push_str(emoji);
-                        }
-                    }
-
-                    // Skip past the opening tag
-                    remaining = &remaining[emoji_start + tag_end + 1..];
-
-                    // Skip past closing </emoji> tag if present
-                    if remaining.starts_with("</emoji") {
-                        if let Some(close_end) = remaining.find('>') {
-                            remaining = &remaining[close_end + 1..];
-                        }
-                    }
-                } else {
-                    // Malformed tag, skip the <emoji part
-                    emoji_processed.push_str(&remaining[..emoji_start + 6]);
-                    remaining = &remaining[emoji_start + 6..];
-                }
-            }
-
-            // Add remaining text
-            emoji_processed.push_str(remaining);
-            clean_content = emoji_processed;
-
-            // Handle HTML entities
-            clean_content = clean_content
-                .replace("&nbsp;", " ")
-                .replace("&amp;", "&")
-                .replace("&lt;", "<")
-                .replace("&gt;", ">")
-                .replace("&quot;", "\"")
-                .replace("&#39;", "'")
-                .replace("&apos;", "'")
-                .replace("&#160;", " ")
-                .replace("&nbsp", " ");
-
-            // Convert block-level tags to newlines
-            clean_content = clean_content
-                .replace("</p>", "\n")
-                .replace("<p>", "")
-                .replace("</div>", "\n")
-                .replace("<div>", "")
-                .replace("</li>", "\n")
-                .replace("<li>", "")
-                .replace("<br>", "\n")
-                .replace("<br/>", "\n")
-                .replace("<br />", "\n")
-                .replace("</br>", "\n");
-
-            // Remove remaining HTML tags
-            let mut no_html = String::new();
-            let mut inside_tag = false;
-
-            for c in clean_content.chars() {
-                if c == '<' {
-                    inside_tag = true;
-                } else if c == '>' {
-                    inside_tag = false;
-                } else if !inside_tag {
-                    no_html.push(c);
-                }
-            }
-
-            // Clean up whitespace: limit consecutive newlines to 2
-            let mut final_content = String::new();
-            let mut consecutive_newlines = 0;
-
-            for c in no_html.chars() {
-                if c == '\n' {
-                    consecutive_newlines += 1;
-                    if consecutive_newlines <= 2 {
-                        final_content.push(c);
-                    }
-                } else {
-                    consecutive_newlines = 0;
-                    final_content.push(c);
-                }
-            }
-
-            // Trim leading/trailing whitespace
-            let final_content = final_content.trim();
-
-            // Wrap text manually, preserving newlines
-            let mut wrapped_lines = Vec::new();
-
-            if final_content.is_empty() {
-                // Empty content - still show one empty line so message appears
-                wrapped_lines.push(String::new());
-            } else {
-                for line in final_content.lines() {
-                    let mut current_line = String::new();
-
-                    for word in line.split_whitespace() {
-                        if current_line.len() + word.len() + 1 > max_line_width {
-                            wrapped_lines.push(current_line);
-                            current_line = String::from(word);
-                        } else {
-                            if !current_line.is_empty() {
-                                current_line.push(' ');
-                            }
-                            current_line.push_str(word);
-                        }
-                    }
-                    if !current_line.is_empty() {
-                        wrapped_lines.push(current_line);
-                    }
-                }
-
-                // Ensure at least one line exists
-                if wrapped_lines.is_empty() {
-                    wrapped_lines.push(String::new());
-                }
-            }
-
-            // Header (if different sender or significant time gap)
-            if show_header {
-                // Add extra spacing before new group (unless it's the first message)
-                if !lines.is_empty() {
-                    lines.push(Line::from(""));
-                }
-
-                let header = if is_me {
-                    format!("{} {}", date_str, "Me")
-                } else {
-                    format!("{} {}", sender_name, date_str)
-                };
-
-                if is_me {
-                    // Right aligned header
-                    let padding = width.saturating_sub(header.len());
-                    let pad_str = " ".repeat(padding);
-                    lines.push(Line::from(vec![
-                        Span::raw(pad_str),
-                        Span::styled(
-                            header,
-                            Style::default()
-                                .fg(Color::Green)
-                                .add_modifier(Modifier::BOLD),
-                        ),
-                    ]));
-                } else {
-                    // Left aligned header
-                    lines.push(Line::from(vec![Span::styled(
-                        header,
-                        Style::default()
-                            .fg(Color::Cyan)
-                            .add_modifier(Modifier::BOLD),
-                    )]));
-                }
-            }
-
-            // Message body
-            if is_me {
-                // Right aligned body
-                for line in wrapped_lines {
-                    let padding = width.saturating_sub(line.len());
-                    let pad_str = " ".repeat(padding);
-                    lines.push(Line::from(vec![Span::raw(pad_str), Span::raw(line)]));
-                }
-            } else {
-                // Left aligned body
-                for line in wrapped_lines {
-                    lines.push(Line::from(line));
-                }
-            }
-
-            // Show image attachment indicators
-            let image_attachments: Vec<_> =
-                msg.attachments.iter().filter(|a| a.is_image()).collect();
-
-            if !image_attachments.is_empty() {
-                for attachment in image_attachments {
-                    let name = attachment.name.as_deref().unwrap_or("image");
-                    let indicator = format!("📷 [Image: {}]", name);
-
-                    if is_me {
-                        // Right aligned image indicator - use unicode width for proper alignment
-                        let display_width = indicator.width();
-                        let padding = width.saturating_sub(display_width);
-                        let pad_str = " ".repeat(padding);
-                        lines.push(Line::from(vec![
-                            Span::raw(pad_str),
-                            Span::styled(indicator, Style::default().fg(Color::Magenta)),
-                        ]));
-                    } else {
-                        // Left aligned image indicator
-                        lines.push(Line::from(vec![Span::styled(
-                            indicator,
-                            Style::default().fg(Color::Magenta),
-                        )]));
-                    }
-                }
-            }
-
-            // Show non-image attachment indicators
-            let other_attachments: Vec<_> = msg
-                .attachments
-                .iter()
-                .filter(|a| !a.is_image() && a.name.is_some())
-                .collect();
-
-            for attachment in other_attachments {
-                if let Some(name) = &attachment.name {
-                    let indicator = format!("📎 [Attachment: {}]", name);
-
-                    if is_me {
-                        // Use unicode width for proper alignment
-                        let display_width = indicator.width();
-                        let padding = width.saturating_sub(display_width);
-                        let pad_str = " ".repeat(padding);
-                        lines.push(Line::from(vec![
-                            Span::raw(pad_str),
-                            Span::styled(indicator, Style::default().fg(Color::DarkGray)),
-                        ]));
-                    } else {
-                        lines.push(Line::from(vec![Span::styled(
-                            indicator,
-                            Style::default().fg(Color::DarkGray),
-                        )]));
-                    }
-                }
-            }
-        }
-
-        lines
-    };
+    // Reserve an extra column as a safety padding so text never touches the vertical border
+    // This prevents terminal selections (e.g. Ctrl+click) from accidentally including the '|' border
+    let width = messages_pane.width.saturating_sub(3) as usize; // Account for borders + 1 pad
+    if app.messages_dirty || app.message_cache_width != width {
+        let (lines, starts) = build_message_lines(app, width);
+        app.message_cache = lines;
+        app.message_starts = starts;
+        app.message_cache_width = width;
+        app.messages_dirty = false;
+
+        // Keep the plain text of each line around so mouse selections can be copied
+        app.message_lines = app
+            .message_cache
+            .iter()
+            .map(|line| {
+                line.spans
+                    .iter()
+                    .map(|span| span.content.as_ref())
+                    .collect::<String>()
+            })
+            .collect();
+        app.message_links = app
+            .message_lines
+            .iter()
+            .enumerate()
+            .flat_map(|(line, text)| {
+                crate::html::find_urls(text)
+                    .into_iter()
+                    .map(move |url| MessageLink {
+                        line,
+                        start_col: text[..url.start].width() as u16,
+                        end_col: text[..url.end].width() as u16,
+                        url: text[url].to_string(),
+                    })
+            })
+            .collect();
+    }
+    // Line at which the message requested via `pending_scroll_to` starts
+    let scroll_target = app
+        .pending_scroll_to
+        .and_then(|index| app.message_starts.get(&index).copied());
 
     // Calculate scroll
-    let total_lines = messages_content.len() as u16;
-    let viewport_height = messages_chunks[0].height.saturating_sub(2); // Borders
+    let total_lines = app.message_cache.len() as u16;
+    let viewport_height = messages_pane.height.saturating_sub(2); // Borders
 
     // Calculate max scroll: if we have more lines than viewport, scroll to show bottom
     // The newest messages are at the bottom of the content (after .rev(), they're last in lines vector)
@@ -479,10 +257,14 @@ pub fn draw(f: &mut Frame, app: &mut App) {
         }
         // Update max_scroll to allow scrolling to this position
         app.max_scroll = std::cmp::max(app.max_scroll, app.scroll_offset);
+    } else if let Some(target) = scroll_target {
+        // Jump to a specific message (e.g. a search match)
+        app.scroll_offset = std::cmp::min(target, app.max_scroll);
     } else {
         // Clamp scroll offset to valid range, but allow the extra margin
         app.scroll_offset = std::cmp::min(app.scroll_offset, app.max_scroll);
     }
+    app.pending_scroll_to = None;
 
     let messages_border_style = if app.focused_pane == FocusedPane::Messages {
         Style::default().fg(Color::Green)
@@ -490,13 +272,13 @@ pub fn draw(f: &mut Frame, app: &mut App) {
         Style::default().fg(Color::White)
     };
 
-    let messages_widget = Paragraph::new(messages_content)
+    let messages_widget = Paragraph::new(app.message_cache.clone())
         .block(
             Block::default()
                 .title(if app.input_mode {
-                    "Messages (ESC to cancel)"
+                    "Messages (ESC to keep draft)"
                 } else {
-                    "Messages (Tab to switch, ↑/↓ to scroll, i to compose)"
+                    "Messages (Tab to switch, ↑/↓ to scroll, i to compose, F to search)"
                 })
                 .borders(Borders::ALL)
                 .border_style(messages_border_style),
@@ -504,14 +286,46 @@ pub fn draw(f: &mut Frame, app: &mut App) {
         .wrap(ratatui::widgets::Wrap { trim: false })
         .scroll((app.scroll_offset, 0));
 
-    f.render_widget(messages_widget, messages_chunks[0]);
+    f.render_widget(messages_widget, messages_pane);
+    render_selection(f, app, messages_pane);
+    render_pinned_bar(f, app, pinned_area);
 
     // Render input field if in input mode
     if app.input_mode {
+        let counter_style = if app.compose_too_long() {
+            Style::default().fg(Color::Red).add_modifier(Modifier::BOLD)
+        } else {
+            Style::default().fg(Color::DarkGray)
+        };
+        let counter = if app.compose_too_long() {
+            format!(
+                " {} chars, over the {} KB limit ",
+                app.input_buffer.chars().count(),
+                crate::app::MAX_MESSAGE_BYTES / 1024
+            )
+        } else {
+            format!(" {} chars ", app.input_buffer.chars().count())
+        };
+        let title = match app.reply_to {
+            Some(ref reply) => truncate_to_width(
+                &format!(
+                    "Replying to {}: {} (ESC to cancel reply)",
+                    reply.sender_name, reply.preview
+                ),
+                (messages_chunks[1].width as usize).saturating_sub(2 + counter.width()),
+            ),
+            None => "Type your message (Enter to send, Ctrl+U importance, ESC to keep as draft)"
+                .to_string(),
+        };
+        let title = match app.compose_importance.label() {
+            Some(label) => format!("[{}] {}", label, title),
+            None => title,
+        };
         let input_widget = Paragraph::new(app.input_buffer.as_str())
             .block(
                 Block::default()
-                    .title("Type your message (Enter to send, ESC to cancel)")
+                    .title(title)
+                    .title(Line::from(Span::styled(counter, counter_style)).right_aligned())
                     .borders(Borders::ALL)
                     .border_style(Style::default().fg(Color::Green)),
             )
@@ -521,7 +335,43 @@ pub fn draw(f: &mut Frame, app: &mut App) {
 
         // Set cursor position
         f.set_cursor_position((
-            messages_chunks[1].x + app.input_buffer.len() as u16 + 1,
+            messages_chunks[1].x
+                + 1
+                + prompt_cursor_column(&app.input_buffer[..app.cursor_pos], messages_chunks[1]),
+            messages_chunks[1].y + 1,
+        ));
+    } else if app.command_mode {
+        let command_widget = Paragraph::new(format!(":{}", app.command_buffer))
+            .block(
+                Block::default()
+                    .title("Command (e.g. export, export txt - Enter to run, ESC to cancel)")
+                    .borders(Borders::ALL)
+                    .border_style(Style::default().fg(Color::Cyan)),
+            )
+            .style(Style::default().fg(Color::White));
+
+        f.render_widget(command_widget, messages_chunks[1]);
+
+        f.set_cursor_position((
+            messages_chunks[1].x
+                + 1
+                + prompt_cursor_column(&format!(":{}", app.command_buffer), messages_chunks[1]),
+            messages_chunks[1].y + 1,
+        ));
+    } else if app.search_mode {
+        let search_widget = Paragraph::new(app.search_query.as_str())
+            .block(
+                Block::default()
+                    .title("Search messages (Enter to find, ESC to cancel)")
+                    .borders(Borders::ALL)
+                    .border_style(Style::default().fg(Color::Yellow)),
+            )
+            .style(Style::default().fg(Color::White));
+
+        f.render_widget(search_widget, messages_chunks[1]);
+
+        f.set_cursor_position((
+            messages_chunks[1].x + 1 + prompt_cursor_column(&app.search_query, messages_chunks[1]),
             messages_chunks[1].y + 1,
         ));
     }
@@ -539,47 +389,1506 @@ pub fn draw(f: &mut Frame, app: &mut App) {
         (&app.status).into()
     };
 
-    let status = Paragraph::new(status_text.as_ref())
-        .block(Block::default().title("Status").borders(Borders::ALL))
+    // Stale data warning goes first so it isn't pushed off a narrow bar
+    let mut status_spans = Vec::new();
+    if !app.online {
+        status_spans.push(Span::styled(
+            "⛔ Offline (read-only) ",
+            Style::default().fg(Color::Red).add_modifier(Modifier::BOLD),
+        ));
+    } else if app.is_reconnecting() {
+        status_spans.push(Span::styled(
+            "⚠ Reconnecting… ",
+            Style::default()
+                .fg(Color::Yellow)
+                .add_modifier(Modifier::BOLD),
+        ));
+    }
+    status_spans.push(Span::raw(status_text.as_ref()));
+
+    // Right side of the border: connection dot, unread count and the clock
+    let (dot_color, connection) = if !app.online {
+        (Color::Red, "offline")
+    } else if app.is_reconnecting() {
+        (Color::Yellow, "reconnecting")
+    } else {
+        (Color::Green, "live")
+    };
+    let unread = app
+        .chats
+        .iter()
+        .filter(|c| c.is_unread() && !app.is_muted(&c.id))
+        .count();
+    let mut summary = vec![
+        Span::raw(" "),
+        Span::styled("●", Style::default().fg(dot_color)),
+        Span::styled(
+            format!(" {} ", connection),
+            Style::default().fg(Color::DarkGray),
+        ),
+    ];
+    if unread > 0 {
+        summary.push(Span::styled(
+            format!("· {} unread ", unread),
+            Style::default().fg(Color::LightBlue),
+        ));
+    }
+    summary.push(Span::styled(
+        format!("· {} ", app.display_zone.format(&now, "%H:%M")),
+        Style::default().fg(Color::White),
+    ));
+
+    let status = Paragraph::new(Line::from(status_spans))
+        .block(
+            Block::default()
+                .title("Status")
+                .title(Line::from(summary).right_aligned())
+                .title_bottom(
+                    Line::styled(
+                        format!(" {} ", mode_hint(app)),
+                        Style::default().fg(Color::DarkGray),
+                    )
+                    .right_aligned(),
+                )
+                .borders(Borders::ALL),
+        )
         .style(Style::default().fg(Color::Green));
 
     f.render_widget(status, main_chunks[1]);
 
+    if app.gallery_mode {
+        render_gallery(f, app);
+    }
+
+    if let Some(row) = app.attachments_drawer {
+        render_attachments_drawer(f, app, row);
+    }
+
     // Image viewer overlay
     if app.is_viewing_image() {
         render_image_viewer(f, app);
     }
-}
 
-/// Render image viewer as a centered popup overlay
-fn render_image_viewer(f: &mut Frame, app: &mut App) {
-    let area = f.area();
-    // Create a centered popup that takes 80% of the screen
-    let popup_width = (area.width as f32 * 0.8) as u16;
-    let popup_height = (area.height as f32 * 0.8) as u16;
-    let popup_x = (area.width - popup_width) / 2;
-    let popup_y = (area.height - popup_height) / 2;
+    if app.members_overlay.is_some() {
+        render_members_overlay(f, app);
+    }
 
-    let popup_area = Rect::new(popup_x, popup_y, popup_width, popup_height);
+    if app.raw_html_overlay.is_some() {
+        render_raw_html_overlay(f, app);
+    }
 
-    // Clear the popup area first
-    f.render_widget(Clear, popup_area);
+    if let Some(row) = app.notif_overlay {
+        render_notif_overlay(f, app, row);
+    }
 
-    // Get image name for title
-    let title = if let Some(ref img) = app.viewing_image {
-        let nav_hint = if app.viewable_images.len() > 1 {
-            format!(
-                " ({}/{}) - ←/→ to navigate, ESC to close, 'o' to open externally",
-                app.selected_image_index + 1,
-                app.viewable_images.len()
-            )
-        } else {
-            " - ESC to close, 'o' to open externally".to_string()
-        };
-        format!("Image: {}{}", img.name, nav_hint)
-    } else {
-        "Image Viewer - ESC to close, 'o' to open externally".to_string()
-    };
+    if let Some(index) = app.reaction_picker {
+        render_reaction_picker(f, app, index);
+    }
+
+    if let Some(recipients) = app.confirm_send {
+        render_send_confirmation(f, recipients);
+    }
+
+    if app.confirm_quit {
+        render_quit_confirmation(f);
+    }
+}
+
+/// Pinned messages, newest pin first; a click on one jumps to it
+fn render_pinned_bar(f: &mut Frame, app: &mut App, area: Rect) {
+    if area.height == 0 {
+        app.pinned_rows.clear();
+        return;
+    }
+    let inner_width = area.width.saturating_sub(2) as usize;
+    let (pinned, not_loaded) = app.pinned_messages();
+    let more = pinned.len().saturating_sub(MAX_PINNED_ROWS) + not_loaded;
+    let mut ids = Vec::new();
+    let lines: Vec<Line> = pinned
+        .iter()
+        .take(MAX_PINNED_ROWS)
+        .map(|message| {
+            ids.push(message.id.clone());
+            let sender = message
+                .from
+                .as_ref()
+                .and_then(|f| f.user.as_ref())
+                .and_then(|u| u.display_name.as_deref())
+                .unwrap_or("Unknown");
+            let text = crate::api::message_preview(message, None).unwrap_or_default();
+            let sender = truncate_to_width(&format!("{}: ", sender), inner_width);
+            let text = truncate_to_width(&text, inner_width.saturating_sub(sender.width()));
+            Line::from(vec![
+                Span::styled(sender, Style::default().add_modifier(Modifier::BOLD)),
+                Span::raw(text),
+            ])
+        })
+        .collect();
+    app.pinned_rows = ids;
+
+    let title = if more > 0 {
+        format!("📌 Pinned (+{} more; p unpins)", more)
+    } else {
+        "📌 Pinned (click to jump, p unpins)".to_string()
+    };
+    let bar = Paragraph::new(lines).block(
+        Block::default()
+            .title(truncate_to_width(&title, inner_width))
+            .borders(Borders::ALL)
+            .border_style(Style::default().fg(Color::Yellow)),
+    );
+    f.render_widget(bar, area);
+}
+
+/// The few keys that matter most in the current mode, for the status bar
+fn mode_hint(app: &App) -> &'static str {
+    if app.command_mode {
+        "Enter run · Esc cancel"
+    } else if app.search_mode {
+        "Enter find · Esc cancel"
+    } else if app.input_mode {
+        "Enter send · Esc leave (draft kept) · Ctrl+U importance"
+    } else if !app.online {
+        "A sign in again · q quit"
+    } else if !app.search_query.is_empty() {
+        "n/N next/previous match · Esc clear"
+    } else if app.focused_pane == FocusedPane::Messages {
+        "J/K move · r react · R reply · p pin · L copy link · Enter open file · i compose"
+    } else {
+        "j/k chats · i compose · m mark read · x mute · F search · : command · q quit"
+    }
+}
+
+/// Render the raw HTML of a message, hard-wrapped so long tags stay visible
+fn render_raw_html_overlay(f: &mut Frame, app: &mut App) {
+    let Some((content, offset)) = app.raw_html_overlay.as_mut() else {
+        return;
+    };
+    let area = f.area();
+    let popup_width = (area.width * 4 / 5).max(40).min(area.width);
+    let popup_height = (area.height * 4 / 5).max(8).min(area.height);
+    let popup_area = Rect::new(
+        (area.width - popup_width) / 2,
+        (area.height - popup_height) / 2,
+        popup_width,
+        popup_height,
+    );
+
+    let lines = hard_wrap(content, popup_width.saturating_sub(2) as usize);
+    let visible = popup_height.saturating_sub(2) as usize;
+    *offset = (*offset).min(lines.len().saturating_sub(visible));
+
+    let text: Vec<Line> = lines
+        .iter()
+        .skip(*offset)
+        .take(visible)
+        .map(|line| Line::from(line.as_str()))
+        .collect();
+    let title = format!(
+        "Raw HTML - {} bytes (↑/↓ to scroll, ESC to close)",
+        content.len()
+    );
+
+    f.render_widget(Clear, popup_area);
+    f.render_widget(
+        Paragraph::new(text).block(
+            Block::default()
+                .title(title)
+                .borders(Borders::ALL)
+                .border_style(Style::default().fg(Color::Magenta)),
+        ),
+        popup_area,
+    );
+}
+
+/// Break text into lines of at most `width` columns, mid-word if need be
+fn hard_wrap(text: &str, width: usize) -> Vec<String> {
+    let width = width.max(1);
+    let mut lines = Vec::new();
+    for line in text.lines() {
+        let mut current = String::new();
+        let mut current_width = 0;
+        for c in line.chars() {
+            let char_width = c.width().unwrap_or(0);
+            if current_width + char_width > width {
+                lines.push(std::mem::take(&mut current));
+                current_width = 0;
+            }
+            current.push(c);
+            current_width += char_width;
+        }
+        lines.push(current);
+    }
+    lines
+}
+
+/// Render the notification preference picker for the selected chat
+fn render_notif_overlay(f: &mut Frame, app: &App, row: usize) {
+    let area = f.area();
+    let popup_width = 44.min(area.width);
+    let popup_height = (NotifPref::ALL.len() as u16 + 2).min(area.height);
+    let popup_area = Rect::new(
+        (area.width - popup_width) / 2,
+        (area.height - popup_height) / 2,
+        popup_width,
+        popup_height,
+    );
+
+    let current = app.get_selected_chat().map(|c| app.notif_pref(&c.id));
+    let lines: Vec<Line> = NotifPref::ALL
+        .iter()
+        .enumerate()
+        .map(|(index, pref)| {
+            let marker = if Some(*pref) == current { "● " } else { "  " };
+            let style = if index == row {
+                Style::default().fg(Color::Black).bg(Color::Cyan)
+            } else {
+                Style::default()
+            };
+            Line::from(Span::styled(format!("{}{}", marker, pref.label()), style))
+        })
+        .collect();
+
+    f.render_widget(Clear, popup_area);
+    let list = Paragraph::new(lines).block(
+        Block::default()
+            .title("Notify me for (Enter to set, ESC to cancel)")
+            .borders(Borders::ALL)
+            .border_style(Style::default().fg(Color::Cyan)),
+    );
+    f.render_widget(list, popup_area);
+}
+
+/// Render the reaction picker just below the top of the focused message
+fn render_reaction_picker(f: &mut Frame, app: &App, selected: usize) {
+    let area = app.messages_area;
+    let message = app
+        .focused_message_id
+        .as_ref()
+        .and_then(|id| app.messages.iter().find(|m| &m.id == id));
+    let message_start = app
+        .focused_message_id
+        .as_ref()
+        .and_then(|id| app.messages.iter().position(|m| &m.id == id))
+        .and_then(|index| app.message_starts.get(&index).copied());
+
+    let spans: Vec<Span> = REACTIONS
+        .iter()
+        .enumerate()
+        .map(|(index, (kind, emoji))| {
+            let mine =
+                message.is_some_and(|m| m.has_reaction(kind, app.current_user_id.as_deref()));
+            let style = if index == selected {
+                Style::default().fg(Color::Black).bg(Color::Cyan)
+            } else if mine {
+                Style::default().bg(Color::DarkGray)
+            } else {
+                Style::default()
+            };
+            Span::styled(format!(" {} ", emoji), style)
+        })
+        .collect();
+    let line = Line::from(spans);
+    let title = format!(" {} ", REACTIONS[selected].0);
+
+    let popup_width = (line.width() as u16 + 2)
+        .max(title.width() as u16 + 2)
+        .min(area.width);
+    let popup_height = 3.min(area.height);
+    // One row under the message's header when it is on screen, else the bottom of the pane
+    let bottom = area.y + area.height.saturating_sub(popup_height);
+    let y = message_start
+        .filter(|start| *start >= app.scroll_offset)
+        .map(|start| area.y + 2 + (start - app.scroll_offset))
+        .map_or(bottom, |y| y.min(bottom));
+    let popup_area = Rect::new(
+        area.x + 2.min(area.width - popup_width),
+        y,
+        popup_width,
+        popup_height,
+    );
+
+    f.render_widget(Clear, popup_area);
+    let picker = Paragraph::new(line).block(
+        Block::default()
+            .title(title)
+            .borders(Borders::ALL)
+            .border_style(Style::default().fg(Color::Cyan)),
+    );
+    f.render_widget(picker, popup_area);
+}
+
+/// Render the selected chat's full member list as a scrollable centered popup
+fn render_members_overlay(f: &mut Frame, app: &mut App) {
+    let members = app.selected_chat_members();
+    let count = members.len();
+    let area = f.area();
+    let popup_width = (area.width * 3 / 5).max(40).min(area.width);
+    let popup_height = (area.height * 7 / 10).max(8).min(area.height);
+    let popup_area = Rect::new(
+        (area.width - popup_width) / 2,
+        (area.height - popup_height) / 2,
+        popup_width,
+        popup_height,
+    );
+
+    // Clamp the scroll so the last member stays on the bottom row
+    let visible = popup_height.saturating_sub(2) as usize;
+    let max_scroll = members.len().saturating_sub(visible);
+    let offset = app.members_overlay.unwrap_or(0).min(max_scroll);
+    app.members_overlay = Some(offset);
+
+    let inner_width = popup_width.saturating_sub(2) as usize;
+    let lines: Vec<Line> = members
+        .into_iter()
+        .skip(offset)
+        .take(visible)
+        .map(|(name, email)| {
+            let mut spans = vec![Span::styled(
+                truncate_to_width(&name, inner_width),
+                Style::default().fg(Color::Cyan),
+            )];
+            if let Some(email) = email {
+                let room = inner_width.saturating_sub(name.width() + 1);
+                if room > 3 {
+                    spans.push(Span::raw(" "));
+                    spans.push(Span::styled(
+                        truncate_to_width(&email, room),
+                        Style::default().fg(Color::DarkGray),
+                    ));
+                }
+            }
+            Line::from(spans)
+        })
+        .collect();
+
+    let title = if max_scroll > 0 {
+        format!(
+            "Members ({}) - {}-{} (↑/↓ to scroll, ESC to close)",
+            count,
+            offset + 1,
+            (offset + visible).min(count)
+        )
+    } else {
+        format!("Members ({}) - ESC to close", count)
+    };
+
+    f.render_widget(Clear, popup_area);
+    let list = Paragraph::new(lines).block(
+        Block::default()
+            .title(truncate_to_width(&title, inner_width))
+            .borders(Borders::ALL)
+            .border_style(Style::default().fg(Color::Cyan)),
+    );
+    f.render_widget(list, popup_area);
+}
+
+/// Render the chat's attachments as a list over the messages pane
+fn render_attachments_drawer(f: &mut Frame, app: &App, selected: usize) {
+    let entries = app.chat_attachments();
+    let area = app.messages_area;
+    let inner_width = area.width.saturating_sub(2) as usize;
+    let visible = area.height.saturating_sub(2) as usize;
+    // Keep the selection on screen
+    let offset = selected.saturating_sub(visible.saturating_sub(1));
+
+    let lines: Vec<Line> = entries
+        .iter()
+        .enumerate()
+        .skip(offset)
+        .take(visible)
+        .map(|(index, entry)| {
+            let icon = if entry.is_image { "📷" } else { "📎" };
+            let date = crate::time::parse_teams_datetime(&entry.created_date_time)
+                .map(|dt| app.display_zone.format(&dt, "%b %d %H:%M"))
+                .unwrap_or_default();
+            let details = format!("  {} · {} · {}", entry.kind, entry.sender, date);
+            let name_width = inner_width
+                .saturating_sub(icon.width() + 1)
+                .saturating_sub(details.width().min(inner_width / 2));
+            let name = truncate_to_width(&entry.name, name_width);
+            let details = truncate_to_width(
+                &details,
+                inner_width.saturating_sub(icon.width() + 1 + name.width()),
+            );
+            let (name_style, details_style) = if index == selected {
+                let style = Style::default().fg(Color::Black).bg(Color::Cyan);
+                (style, style)
+            } else {
+                (
+                    Style::default().fg(Color::Magenta),
+                    Style::default().fg(Color::DarkGray),
+                )
+            };
+            Line::from(vec![
+                Span::raw(format!("{} ", icon)),
+                Span::styled(name, name_style),
+                Span::styled(details, details_style),
+            ])
+        })
+        .collect();
+
+    let title = format!(
+        "Attachments ({}) - Enter to open, ESC to close",
+        entries.len()
+    );
+    f.render_widget(Clear, area);
+    let list = Paragraph::new(lines).block(
+        Block::default()
+            .title(truncate_to_width(&title, inner_width))
+            .borders(Borders::ALL)
+            .border_style(Style::default().fg(Color::Cyan)),
+    );
+    f.render_widget(list, area);
+}
+
+/// Width and height in cells of one gallery thumbnail, including its border
+const GALLERY_CELL: (u16, u16) = (24, 10);
+
+/// Render the chat's images as a grid of thumbnails, or a list of names where the
+/// terminal can't draw graphics
+fn render_gallery(f: &mut Frame, app: &mut App) {
+    let area = f.area();
+    let popup_width = (area.width as f32 * 0.9) as u16;
+    let popup_height = (area.height as f32 * 0.9) as u16;
+    let popup_area = Rect::new(
+        (area.width - popup_width) / 2,
+        (area.height - popup_height) / 2,
+        popup_width,
+        popup_height,
+    );
+    let count = app.viewable_images.len();
+    app.selected_image_index = app.selected_image_index.min(count.saturating_sub(1));
+    let selected = app.selected_image_index;
+
+    let title = format!(
+        "Images ({}/{}) - arrows to move, Enter to open, ESC to close",
+        selected + 1,
+        count
+    );
+    let block = Block::default()
+        .title(truncate_to_width(
+            &title,
+            popup_width.saturating_sub(2) as usize,
+        ))
+        .borders(Borders::ALL)
+        .border_style(Style::default().fg(Color::Magenta));
+    let inner = block.inner(popup_area);
+    f.render_widget(Clear, popup_area);
+    f.render_widget(block, popup_area);
+
+    if !app.supports_graphics() {
+        app.gallery_columns = 1;
+        let visible = inner.height as usize;
+        let offset = (selected + 1).saturating_sub(visible);
+        let lines: Vec<Line> = app
+            .viewable_images
+            .iter()
+            .enumerate()
+            .skip(offset)
+            .take(visible)
+            .map(|(index, img)| {
+                let style = if index == selected {
+                    Style::default().fg(Color::Black).bg(Color::Magenta)
+                } else {
+                    Style::default()
+                };
+                Line::from(Span::styled(
+                    truncate_to_width(&img.name, inner.width as usize),
+                    style,
+                ))
+            })
+            .collect();
+        f.render_widget(Paragraph::new(lines), inner);
+        return;
+    }
+
+    let (cell_width, cell_height) = GALLERY_CELL;
+    let columns = (inner.width / cell_width).max(1) as usize;
+    let rows = (inner.height / cell_height).max(1) as usize;
+    app.gallery_columns = columns;
+    // Scroll by whole rows so the selection stays on screen
+    let first_row = (selected / columns + 1).saturating_sub(rows);
+
+    for (slot, index) in (first_row * columns..count)
+        .take(rows * columns)
+        .enumerate()
+    {
+        let cell = Rect::new(
+            inner.x + (slot % columns) as u16 * cell_width,
+            inner.y + (slot / columns) as u16 * cell_height,
+            cell_width.min(inner.width),
+            cell_height.min(inner.height),
+        );
+        let image = &app.viewable_images[index];
+        let url = image.url.clone();
+        let border = if index == selected {
+            Color::Green
+        } else {
+            Color::DarkGray
+        };
+        let block = Block::default()
+            .title(truncate_to_width(
+                &image.name,
+                cell.width.saturating_sub(2) as usize,
+            ))
+            .borders(Borders::ALL)
+            .border_style(Style::default().fg(border));
+        let thumb_area = block.inner(cell);
+        let placeholder = if !app.can_view_image(&url) {
+            Some("no file access")
+        } else if app.is_gallery_pending(&url) {
+            Some("Loading…")
+        } else {
+            None
+        };
+        f.render_widget(block, cell);
+
+        match app.image_protocols.get_mut(&url) {
+            Some(protocol) => {
+                f.render_stateful_widget(StatefulImage::default(), thumb_area, protocol);
+            }
+            None => {
+                let text = placeholder.unwrap_or("✗ unavailable");
+                f.render_widget(
+                    Paragraph::new(text).style(Style::default().fg(Color::DarkGray)),
+                    thumb_area,
+                );
+            }
+        }
+    }
+}
+
+/// Build the messages pane content for a text area `width` columns wide
+///
+/// This is the expensive part of a frame (HTML cleanup, wrapping, link styling), so
+/// `draw` caches the result in `App::message_cache` and only calls this again when
+/// `App::messages_dirty` is set or the pane width changes. Also returns the line each
+/// message starts at, keyed by index into `app.messages`, for `pending_scroll_to`.
+fn build_message_lines(app: &App, width: usize) -> (Vec<Line<'static>>, HashMap<usize, u16>) {
+    let mut starts = HashMap::new();
+    let lines = if app.selected_chat_unavailable() {
+        vec![Line::from(Span::styled(
+            "This chat is no longer available",
+            Style::default()
+                .fg(Color::DarkGray)
+                .add_modifier(Modifier::BOLD),
+        ))]
+    } else if let Some(ref error) = app.messages_error {
+        vec![
+            Line::from(Span::styled(
+                "Failed to load messages — press R to retry",
+                Style::default().fg(Color::Red).add_modifier(Modifier::BOLD),
+            )),
+            Line::from(Span::styled(
+                error.clone(),
+                Style::default().fg(Color::DarkGray),
+            )),
+        ]
+    } else if app.loading_messages {
+        vec![Line::from("Loading messages...")]
+    } else if app.messages.is_empty() {
+        vec![Line::from(Span::styled(
+            "No messages yet — press i to start",
+            Style::default().fg(Color::DarkGray),
+        ))]
+    } else {
+        let max_line_width = width * app.config.message_width_percent as usize / 100;
+
+        let mut lines = Vec::new();
+        let history_style = Style::default().fg(Color::DarkGray);
+        if app.loading_older {
+            lines.push(Line::from(Span::styled(
+                "⟳ Loading older messages…",
+                history_style,
+            )));
+        } else if app.next_page_link.is_none() && app.messages.len() <= app.display_limit() {
+            let marker = "— beginning of conversation —";
+            let padding = width.saturating_sub(marker.width()) / 2;
+            lines.push(Line::from(vec![
+                Span::raw(" ".repeat(padding)),
+                Span::styled(marker, history_style),
+            ]));
+        }
+        let mut last_sender: Option<String> = None;
+        let mut last_message_time: Option<chrono::DateTime<chrono::FixedOffset>> = None;
+        let mut last_day: Option<String> = None;
+
+        // Take the newest messages, up to the configured display limit
+        for (msg_index, msg) in app
+            .messages
+            .iter()
+            .enumerate()
+            .take(app.display_limit())
+            .rev()
+        {
+            if msg.is_system() && !app.config.show_system_messages {
+                continue;
+            }
+            starts.insert(msg_index, lines.len() as u16);
+
+            // Centered date separator whenever the day rolls over, and above the first message
+            let timestamp = crate::time::parse_teams_datetime(&msg.created_date_time);
+            let day = timestamp.map(|dt| app.display_zone.format(&dt, "%Y-%m-%d"));
+            let new_day = day.is_some() && day != last_day;
+            if let (true, Some(dt)) = (new_day, timestamp) {
+                if app.config.density != Density::Compact && !lines.is_empty() {
+                    lines.push(Line::from(""));
+                }
+                let separator = format!("— {} —", app.display_zone.format(&dt, "%A, %b %-d"));
+                let padding = width.saturating_sub(separator.width()) / 2;
+                lines.push(Line::from(vec![
+                    Span::raw(" ".repeat(padding)),
+                    Span::styled(separator, Style::default().fg(Color::DarkGray)),
+                ]));
+                last_day = day;
+                // The next message starts a new group right under the separator
+                last_sender = None;
+            }
+
+            // Divider above the oldest message that was unread when the chat was opened
+            let new_unread = app.first_unread_id.as_deref() == Some(msg.id.as_str());
+            if new_unread {
+                if app.config.density != Density::Compact && !lines.is_empty() && !new_day {
+                    lines.push(Line::from(""));
+                }
+                let divider = "— new messages —";
+                let padding = width.saturating_sub(divider.width()) / 2;
+                lines.push(Line::from(vec![
+                    Span::raw(" ".repeat(padding)),
+                    Span::styled(
+                        divider,
+                        Style::default().fg(Color::Red).add_modifier(Modifier::BOLD),
+                    ),
+                ]));
+                last_sender = None;
+            }
+
+            if msg.is_system() {
+                let text = msg.system_event_text().unwrap_or_else(|| {
+                    let content = msg
+                        .body
+                        .as_ref()
+                        .and_then(|b| b.content.as_deref())
+                        .unwrap_or("");
+                    crate::html::html_to_plain(content).replace('\n', " ")
+                });
+                let text = if text.is_empty() {
+                    "— system event —".to_string()
+                } else {
+                    format!("— {} —", text)
+                };
+                let padding = width.saturating_sub(text.width()) / 2;
+                lines.push(Line::from(vec![
+                    Span::raw(" ".repeat(padding)),
+                    Span::styled(text, Style::default().fg(Color::DarkGray)),
+                ]));
+
+                // The next real message starts a new group
+                last_sender = None;
+                continue;
+            }
+
+            let message_start = lines.len();
+
+            let sender_name = msg
+                .from
+                .as_ref()
+                .and_then(|f| f.user.as_ref())
+                .and_then(|u| u.display_name.as_ref())
+                .map(|s| s.as_str())
+                .unwrap_or("Unknown");
+
+            let current_time = timestamp;
+
+            let is_me = app
+                .current_user_name
+                .as_ref()
+                .is_some_and(|me| sender_name == me);
+            let same_sender = last_sender.as_deref() == Some(sender_name);
+            // Own messages are right-aligned unless the config asks for a single left column
+            let align_right = is_me && app.config.right_align_self;
+            // Someone else @-mentioning me gets a marker down the left edge
+            let mentions_me = !is_me
+                && !msg.is_system()
+                && msg.mentions_me(
+                    app.current_user_id.as_deref(),
+                    app.current_user_name.as_deref(),
+                );
+
+            // A pause longer than the configured gap starts a new group, even from the same sender
+            let significant_time_gap =
+                if let (Some(curr), Some(last)) = (current_time, last_message_time) {
+                    curr - last > app.config.header_gap()
+                } else {
+                    false
+                };
+
+            let show_header = !same_sender || significant_time_gap;
+
+            last_sender = Some(sender_name.to_string());
+            last_message_time = current_time;
+
+            // Format date: 2025-11-21T19:11:33 -> Nov-21 19:11
+            let date_str = if let Some(dt) = current_time {
+                app.display_zone.format(&dt, "%b %d %H:%M")
+            } else {
+                msg.created_date_time.clone()
+            };
+
+            let content = msg
+                .body
+                .as_ref()
+                .and_then(|b| b.content.as_ref())
+                .map(|c| c.as_str())
+                .unwrap_or("");
+
+            // Split out code blocks, which keep their layout, and strip HTML from the rest
+            let emoji_as_text = app.config.emoji_as_text;
+            let blocks: Vec<BodyBlock> = crate::html::split_body_blocks(content)
+                .into_iter()
+                .map(|block| match block {
+                    BodyBlock::Text(html) => {
+                        BodyBlock::Text(crate::html::html_to_plain_with(&html, emoji_as_text))
+                    }
+                    BodyBlock::Quote(html) => {
+                        BodyBlock::Quote(crate::html::html_to_plain_with(&html, emoji_as_text))
+                    }
+                    code => code,
+                })
+                .filter(|block| {
+                    !matches!(block, BodyBlock::Text(text) | BodyBlock::Quote(text) if text.is_empty())
+                })
+                .collect();
+
+            // Wrap text manually, preserving newlines; code lines are never wrapped
+            let mut wrapped_lines: Vec<(String, LineKind)> = Vec::new();
+
+            let has_indicators = msg
+                .attachments
+                .iter()
+                .any(|a| a.is_image(&app.config.image_extensions) || a.name.is_some());
+
+            // Deleted and policy-blocked messages get a placeholder instead of blank lines
+            let removed_notice = if msg.is_deleted() {
+                Some("(message deleted)")
+            } else if msg.is_policy_hidden() {
+                Some("(message hidden by your organisation's policy)")
+            } else {
+                None
+            };
+
+            if removed_notice.is_some() {
+                // The placeholder below stands in for the body
+            } else if blocks.is_empty() && has_indicators {
+                // Attachment-only message: the indicators below are the whole message
+            } else if blocks.is_empty() {
+                // Empty content - still show one empty line so message appears
+                wrapped_lines.push((String::new(), LineKind::Text));
+            } else {
+                for block in &blocks {
+                    match block {
+                        BodyBlock::Text(text) => {
+                            wrapped_lines.extend(
+                                wrap_text(text, max_line_width)
+                                    .into_iter()
+                                    .map(|line| (line, LineKind::Text)),
+                            );
+                        }
+                        BodyBlock::Quote(text) => {
+                            let expanded = app.expanded_quotes.contains(&msg.id);
+                            wrapped_lines.extend(quote_lines(
+                                text,
+                                max_line_width,
+                                app.config.quote_collapse_lines,
+                                expanded,
+                            ));
+                        }
+                        BodyBlock::Code(code) => {
+                            wrapped_lines.extend(code_block_lines(code, max_line_width));
+                        }
+                    }
+                }
+
+                // Ensure at least one line exists
+                if wrapped_lines.is_empty() {
+                    wrapped_lines.push((String::new(), LineKind::Text));
+                }
+            }
+
+            // Header (if different sender or significant time gap)
+            // In compact density the header shares the first body line when it fits
+            let compact = app.config.density == Density::Compact;
+            let mut inline_header: Option<Vec<Span<'static>>> = None;
+
+            if show_header {
+                // Add extra spacing before new group (unless it's the first message
+                // or it sits right under a date separator or the unread divider)
+                if !compact && !lines.is_empty() && !new_day && !new_unread {
+                    lines.push(Line::from(""));
+                }
+
+                let self_label = &app.config.self_label;
+                let header = if align_right {
+                    format!("{} {}", date_str, self_label)
+                } else if is_me {
+                    format!("{} {}", self_label, date_str)
+                } else {
+                    format!("{} {}", sender_name, date_str)
+                };
+                let color = if is_me {
+                    app.config.self_color()
+                } else {
+                    app.config.other_color()
+                };
+                let header_span = Span::styled(
+                    header,
+                    Style::default().fg(color).add_modifier(Modifier::BOLD),
+                );
+                // The badge sits on the outer edge: left of the name, or right of it
+                // when the header is right aligned
+                let header_spans = if !app.config.show_initials {
+                    vec![header_span]
+                } else if align_right {
+                    vec![
+                        header_span,
+                        Span::raw(" "),
+                        initials_badge(sender_name, Some(color)),
+                    ]
+                } else {
+                    vec![
+                        initials_badge(sender_name, is_me.then_some(color)),
+                        Span::raw(" "),
+                        header_span,
+                    ]
+                };
+                let header_width: usize = header_spans.iter().map(|s| s.width()).sum();
+                let first_line_width = wrapped_lines.first().map_or(0, |(l, _)| l.width());
+
+                if compact
+                    && !wrapped_lines.is_empty()
+                    && header_width + 1 + first_line_width <= width
+                {
+                    inline_header = Some(header_spans);
+                } else if align_right {
+                    // Right aligned header
+                    let padding = width.saturating_sub(header_width);
+                    let mut spans = vec![Span::raw(" ".repeat(padding))];
+                    spans.extend(header_spans);
+                    lines.push(Line::from(spans));
+                } else if mentions_me {
+                    // Left aligned header, continuing the mention marker
+                    let mut spans = vec![Span::styled(
+                        "▌",
+                        Style::default()
+                            .fg(MENTION_COLOR)
+                            .add_modifier(Modifier::BOLD),
+                    )];
+                    spans.extend(header_spans);
+                    lines.push(Line::from(spans));
+                } else {
+                    // Left aligned header
+                    lines.push(Line::from(header_spans));
+                }
+            }
+
+            // High and urgent messages are flagged even in the middle of a group
+            if let Some(label) = msg.importance().label() {
+                let marker = format!("! {}", label);
+                let color = if msg.importance() == Importance::Urgent {
+                    Color::Red
+                } else {
+                    Color::LightRed
+                };
+                let style = Style::default().fg(color).add_modifier(Modifier::BOLD);
+                if align_right {
+                    let padding = width.saturating_sub(marker.width());
+                    lines.push(Line::from(vec![
+                        Span::raw(" ".repeat(padding)),
+                        Span::styled(marker, style),
+                    ]));
+                } else {
+                    lines.push(Line::from(vec![Span::styled(marker, style)]));
+                }
+            }
+
+            // Channel posts may carry a subject, shown as a bold title
+            if let Some(subject) = msg.subject.as_deref().filter(|s| !s.trim().is_empty()) {
+                let title = truncate_to_width(subject.trim(), width);
+                let style = Style::default().add_modifier(Modifier::BOLD);
+                if align_right {
+                    let padding = width.saturating_sub(title.width());
+                    lines.push(Line::from(vec![
+                        Span::raw(" ".repeat(padding)),
+                        Span::styled(title, style),
+                    ]));
+                } else {
+                    lines.push(Line::from(vec![Span::styled(title, style)]));
+                }
+            }
+
+            // Highlight search hits within matching messages
+            let highlight_query = if app.search_matches.contains(&msg_index) {
+                Some(app.search_query.as_str())
+            } else {
+                None
+            };
+
+            // Message body; an edited message ends in a dim "(edited)"
+            let last_line = wrapped_lines.len().saturating_sub(1);
+            let edited_suffix = |index: usize| {
+                (msg.is_edited() && index == last_line)
+                    .then(|| Span::styled(EDITED_SUFFIX, Style::default().fg(Color::DarkGray)))
+            };
+            // Optional bubble background on the text itself, never on the alignment padding.
+            // Search hits keep their own background, and the focus shading replaces it.
+            let background = if app.focused_message_id.as_ref() == Some(&msg.id) {
+                None
+            } else if is_me {
+                app.config.self_background()
+            } else {
+                app.config.other_background()
+            };
+            let body_spans = |line, kind, index: usize| -> Vec<Span<'static>> {
+                let spans = style_line(line, kind, highlight_query)
+                    .into_iter()
+                    .chain(edited_suffix(index));
+                match background {
+                    Some(bg) => spans
+                        .map(|span| {
+                            if span.style.bg.is_none() {
+                                span.patch_style(Style::default().bg(bg))
+                            } else {
+                                span
+                            }
+                        })
+                        .collect(),
+                    None => spans.collect(),
+                }
+            };
+            if align_right {
+                // Right aligned body
+                for (index, (line, kind)) in wrapped_lines.into_iter().enumerate() {
+                    let mut spans = Vec::new();
+                    if let Some(header) = inline_header.take() {
+                        spans.extend(header);
+                        spans.push(Span::raw(" "));
+                    }
+                    spans.extend(body_spans(line, kind, index));
+                    lines.push(right_aligned(spans, width));
+                }
+            } else {
+                // Left aligned body; own messages get a gutter in their header color
+                for (index, (line, kind)) in wrapped_lines.into_iter().enumerate() {
+                    let mut spans = Vec::new();
+                    if is_me {
+                        spans.push(Span::styled(
+                            "▎",
+                            Style::default().fg(app.config.self_color()),
+                        ));
+                    } else if mentions_me {
+                        spans.push(Span::styled(
+                            "▌",
+                            Style::default()
+                                .fg(MENTION_COLOR)
+                                .add_modifier(Modifier::BOLD),
+                        ));
+                    }
+                    if let Some(header) = inline_header.take() {
+                        spans.extend(header);
+                        spans.push(Span::raw(" "));
+                    }
+                    spans.extend(body_spans(line, kind, index));
+                    lines.push(Line::from(spans));
+                }
+            }
+
+            if let Some(notice) = removed_notice {
+                let style = Style::default()
+                    .fg(Color::DarkGray)
+                    .add_modifier(Modifier::ITALIC);
+                if align_right {
+                    let padding = width.saturating_sub(notice.width());
+                    lines.push(Line::from(vec![
+                        Span::raw(" ".repeat(padding)),
+                        Span::styled(notice, style),
+                    ]));
+                } else {
+                    lines.push(Line::from(Span::styled(notice, style)));
+                }
+            }
+
+            // Show image attachment indicators
+            let image_attachments: Vec<_> = msg
+                .attachments
+                .iter()
+                .filter(|a| a.is_image(&app.config.image_extensions))
+                .collect();
+
+            if !image_attachments.is_empty() {
+                for attachment in image_attachments {
+                    let name = attachment.name.as_deref().unwrap_or("image");
+                    // Greyed out when the session lacks the scopes to download it
+                    let viewable = attachment
+                        .get_image_url()
+                        .is_none_or(|url| app.can_view_image(url));
+                    let (indicator, style) = if viewable {
+                        (
+                            format!("📷 [Image: {}]", name),
+                            Style::default().fg(Color::Magenta),
+                        )
+                    } else {
+                        (
+                            format!("📷 [Image: {} - no file access]", name),
+                            Style::default().fg(Color::DarkGray),
+                        )
+                    };
+
+                    if align_right {
+                        // Right aligned image indicator - use unicode width for proper alignment
+                        let display_width = indicator.width();
+                        let padding = width.saturating_sub(display_width);
+                        let pad_str = " ".repeat(padding);
+                        lines.push(Line::from(vec![
+                            Span::raw(pad_str),
+                            Span::styled(indicator, style),
+                        ]));
+                    } else {
+                        // Left aligned image indicator
+                        lines.push(Line::from(vec![Span::styled(indicator, style)]));
+                    }
+                }
+            }
+
+            // Show non-image attachment indicators
+            let other_attachments: Vec<_> = msg
+                .attachments
+                .iter()
+                .filter(|a| !a.is_image(&app.config.image_extensions) && a.name.is_some())
+                .collect();
+
+            for attachment in other_attachments {
+                if let Some(name) = &attachment.name {
+                    let indicator = format!("📎 [Attachment: {}]", name);
+
+                    if align_right {
+                        // Use unicode width for proper alignment
+                        let display_width = indicator.width();
+                        let padding = width.saturating_sub(display_width);
+                        let pad_str = " ".repeat(padding);
+                        lines.push(Line::from(vec![
+                            Span::raw(pad_str),
+                            Span::styled(indicator, Style::default().fg(Color::DarkGray)),
+                        ]));
+                    } else {
+                        lines.push(Line::from(vec![Span::styled(
+                            indicator,
+                            Style::default().fg(Color::DarkGray),
+                        )]));
+                    }
+                }
+            }
+
+            // Reaction counts, e.g. "👍 2  😆 1"
+            let reactions = msg.reaction_summary();
+            if !reactions.is_empty() {
+                let summary = reactions
+                    .iter()
+                    .map(|(emoji, count)| format!("{} {}", emoji, count))
+                    .collect::<Vec<_>>()
+                    .join("  ");
+                let style = Style::default().fg(Color::Yellow);
+                if align_right {
+                    let padding = width.saturating_sub(summary.width());
+                    lines.push(Line::from(vec![
+                        Span::raw(" ".repeat(padding)),
+                        Span::styled(summary, style),
+                    ]));
+                } else {
+                    lines.push(Line::from(vec![Span::styled(summary, style)]));
+                }
+            }
+
+            // Delivery marker for messages sent from this client
+            if let Some(state) = msg.send_state {
+                let (marker, style) = match state {
+                    SendState::Sending => ("sending…", Style::default().fg(Color::DarkGray)),
+                    SendState::Sent => ("✓ sent", Style::default().fg(Color::DarkGray)),
+                    SendState::Failed => (
+                        "! failed to send (S to retry)",
+                        Style::default().fg(Color::Red).add_modifier(Modifier::BOLD),
+                    ),
+                };
+
+                if align_right {
+                    let padding = width.saturating_sub(marker.width());
+                    lines.push(Line::from(vec![
+                        Span::raw(" ".repeat(padding)),
+                        Span::styled(marker, style),
+                    ]));
+                } else {
+                    lines.push(Line::from(vec![Span::styled(marker, style)]));
+                }
+            }
+
+            // Shade the message under the J/K cursor
+            if app.focused_message_id.as_ref() == Some(&msg.id) {
+                for line in &mut lines[message_start..] {
+                    if line.width() > 0 {
+                        line.style = line.style.bg(Color::Indexed(236));
+                    }
+                }
+            }
+        }
+
+        lines
+    };
+
+    (lines, starts)
+}
+
+/// Colors a sender's badge may take; my own badge uses `self_color` instead
+const BADGE_COLORS: [Color; 6] = [
+    Color::Blue,
+    Color::Magenta,
+    Color::Yellow,
+    Color::Red,
+    Color::Cyan,
+    Color::LightBlue,
+];
+
+/// Up to two initials for a display name, e.g. "BS" for "Bob Smith"
+fn initials(name: &str) -> String {
+    let mut words = name.split_whitespace();
+    let first = words.next().and_then(|w| w.chars().next());
+    let last = words.last().and_then(|w| w.chars().next());
+    match first {
+        Some(first) => first
+            .to_uppercase()
+            .chain(last.into_iter().flat_map(char::to_uppercase))
+            .collect(),
+        None => "?".to_string(),
+    }
+}
+
+/// Stable color for a sender, so the same person keeps the same badge between runs
+fn name_color(name: &str) -> Color {
+    let hash = name
+        .bytes()
+        .fold(0u32, |hash, b| hash.wrapping_mul(31).wrapping_add(b as u32));
+    BADGE_COLORS[hash as usize % BADGE_COLORS.len()]
+}
+
+/// Colored initials shown beside a sender header; `fixed` overrides the per-name color
+fn initials_badge(sender_name: &str, fixed: Option<Color>) -> Span<'static> {
+    let color = fixed.unwrap_or_else(|| name_color(sender_name));
+    Span::styled(
+        format!(" {} ", initials(sender_name)),
+        Style::default()
+            .fg(Color::Black)
+            .bg(color)
+            .add_modifier(Modifier::BOLD),
+    )
+}
+
+/// Column of the cursor after `text` in a bordered one-line prompt, relative to its
+/// inner left edge
+///
+/// Measured in terminal cells, so wide characters such as emoji count double, and
+/// kept inside the box when the text is longer than the prompt.
+fn prompt_cursor_column(text: &str, area: Rect) -> u16 {
+    let inner_width = area.width.saturating_sub(2);
+    (text.width() as u16).min(inner_width.saturating_sub(1))
+}
+
+/// Render the confirmation shown before sending to a large group chat
+fn render_send_confirmation(f: &mut Frame, recipients: usize) {
+    let area = f.area();
+    let popup_width = 48.min(area.width);
+    let popup_height = 3.min(area.height);
+    let popup_area = Rect::new(
+        (area.width - popup_width) / 2,
+        (area.height - popup_height) / 2,
+        popup_width,
+        popup_height,
+    );
+
+    f.render_widget(Clear, popup_area);
+    let confirmation = Paragraph::new(format!("Send to {} people? y/n", recipients))
+        .style(Style::default().fg(Color::Yellow))
+        .block(
+            Block::default()
+                .title("Send")
+                .borders(Borders::ALL)
+                .border_style(Style::default().fg(Color::Yellow)),
+        );
+    f.render_widget(confirmation, popup_area);
+}
+
+/// Render the unsaved-draft quit confirmation as a small centered popup
+fn render_quit_confirmation(f: &mut Frame) {
+    let area = f.area();
+    let popup_width = 48.min(area.width);
+    let popup_height = 3.min(area.height);
+    let popup_area = Rect::new(
+        (area.width - popup_width) / 2,
+        (area.height - popup_height) / 2,
+        popup_width,
+        popup_height,
+    );
+
+    f.render_widget(Clear, popup_area);
+    let confirmation = Paragraph::new("Unsaved message — quit anyway? y/n")
+        .style(Style::default().fg(Color::Yellow))
+        .block(
+            Block::default()
+                .title("Quit")
+                .borders(Borders::ALL)
+                .border_style(Style::default().fg(Color::Yellow)),
+        );
+    f.render_widget(confirmation, popup_area);
+}
+
+/// Invert the cells covered by the mouse selection in the messages pane
+fn render_selection(f: &mut Frame, app: &App, area: Rect) {
+    let Some(((start_col, start_line), (end_col, end_line))) = app.selection_range() else {
+        return;
+    };
+
+    // Stay inside the borders and the padding column next to the right border
+    let text_width = area.width.saturating_sub(3);
+    let visible_rows = area.height.saturating_sub(2);
+    let buffer = f.buffer_mut();
+
+    for row in 0..visible_rows {
+        let line = app.scroll_offset.saturating_add(row);
+        if line < start_line || line > end_line {
+            continue;
+        }
+        let from = if line == start_line { start_col } else { 0 };
+        let to = if line == end_line {
+            end_col.min(text_width.saturating_sub(1))
+        } else {
+            text_width.saturating_sub(1)
+        };
+        for col in from..=to {
+            if let Some(cell) = buffer.cell_mut((area.x + 1 + col, area.y + 1 + row)) {
+                cell.set_style(Style::default().add_modifier(Modifier::REVERSED));
+            }
+        }
+    }
+}
+
+fn code_style() -> Style {
+    Style::default().fg(Color::White).bg(Color::DarkGray)
+}
+
+/// What a laid out body line is, which decides how it is styled
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum LineKind {
+    Text,
+    Code,
+    Quote,
+}
+
+/// Style one body line according to its kind
+fn style_line(line: String, kind: LineKind, query: Option<&str>) -> Vec<Span<'static>> {
+    match kind {
+        LineKind::Text => style_body_line(line, query),
+        LineKind::Code => vec![Span::styled(line, code_style())],
+        LineKind::Quote => vec![Span::styled(line, Style::default().fg(Color::DarkGray))],
+    }
+}
+
+/// Pad spans on the left so they end at column `width`
+///
+/// Padding is measured in terminal cells, so emoji (two cells, often several
+/// codepoints) line up with plain text.
+fn right_aligned(spans: Vec<Span<'static>>, width: usize) -> Line<'static> {
+    let content_width: usize = spans.iter().map(|s| s.width()).sum();
+    let mut padded = vec![Span::raw(" ".repeat(width.saturating_sub(content_width)))];
+    padded.extend(spans);
+    Line::from(padded)
+}
+
+/// Word-wrap plain text to `max_width` cells per line, keeping its line breaks
+fn wrap_text(text: &str, max_width: usize) -> Vec<String> {
+    let mut wrapped = Vec::new();
+    for line in text.lines() {
+        let mut current_line = String::new();
+
+        for word in line.split_whitespace() {
+            if !current_line.is_empty() && current_line.width() + word.width() + 1 > max_width {
+                wrapped.push(current_line);
+                current_line = String::from(word);
+            } else {
+                if !current_line.is_empty() {
+                    current_line.push(' ');
+                }
+                current_line.push_str(word);
+            }
+        }
+        if !current_line.is_empty() {
+            wrapped.push(current_line);
+        }
+    }
+    wrapped
+}
+
+/// Lay out a quote behind a "│" bar, cut to `collapse_after` lines unless `expanded`
+///
+/// A cut quote ends in a "[+ N more quoted lines]" marker; Enter on the message
+/// expands it. A `collapse_after` of 0 never cuts.
+fn quote_lines(
+    text: &str,
+    max_width: usize,
+    collapse_after: usize,
+    expanded: bool,
+) -> Vec<(String, LineKind)> {
+    let mut lines: Vec<(String, LineKind)> = wrap_text(text, max_width.saturating_sub(2).max(1))
+        .into_iter()
+        .map(|line| (format!("│ {}", line), LineKind::Quote))
+        .collect();
+    if !expanded && collapse_after > 0 && lines.len() > collapse_after {
+        let hidden = lines.len() - collapse_after;
+        lines.truncate(collapse_after);
+        let noun = if hidden == 1 { "line" } else { "lines" };
+        lines.push((
+            format!("│ [+ {} more quoted {}]", hidden, noun),
+            LineKind::Quote,
+        ));
+    }
+    lines
+}
+
+/// Lay out a code block as a box as wide as its longest line (up to `max_width`)
+///
+/// Lines are cut rather than wrapped so indentation stays readable; a cut line
+/// ends in "…".
+fn code_block_lines(code: &str, max_width: usize) -> Vec<(String, LineKind)> {
+    let inner_width = max_width.saturating_sub(2).max(1);
+    let box_width = code
+        .lines()
+        .map(|line| line.width())
+        .max()
+        .unwrap_or(0)
+        .min(inner_width);
+    code.lines()
+        .map(|line| {
+            let shown = truncate_to_width(line, box_width);
+            let fill = box_width.saturating_sub(shown.width());
+            (format!(" {}{} ", shown, " ".repeat(fill)), LineKind::Code)
+        })
+        .collect()
+}
+
+/// Style a message body line: links underlined in blue, search hits reversed elsewhere
+fn style_body_line(line: String, query: Option<&str>) -> Vec<Span<'static>> {
+    let urls = crate::html::find_urls(&line);
+    if urls.is_empty() {
+        return highlight_matches(line, query);
+    }
+
+    let link_style = Style::default()
+        .fg(Color::Blue)
+        .add_modifier(Modifier::UNDERLINED);
+    let mut spans = Vec::new();
+    let mut last = 0;
+    for url in urls {
+        if url.start > last {
+            spans.extend(highlight_matches(line[last..url.start].to_string(), query));
+        }
+        spans.push(Span::styled(line[url.clone()].to_string(), link_style));
+        last = url.end;
+    }
+    if last < line.len() {
+        spans.extend(highlight_matches(line[last..].to_string(), query));
+    }
+    spans
+}
+
+/// Split a line into spans, rendering case-insensitive matches of `query` inverted
+fn highlight_matches(line: String, query: Option<&str>) -> Vec<Span<'static>> {
+    let query = match query {
+        Some(q) if !q.is_empty() => q.to_lowercase(),
+        _ => return vec![Span::raw(line)],
+    };
+
+    // Lowercasing can change byte lengths for some scripts; skip highlighting then
+    let lower = line.to_lowercase();
+    if lower.len() != line.len() {
+        return vec![Span::raw(line)];
+    }
+
+    let mut spans = Vec::new();
+    let mut last = 0;
+    for (start, _) in lower.match_indices(&query) {
+        let end = start + query.len();
+        if !line.is_char_boundary(start) || !line.is_char_boundary(end) {
+            continue;
+        }
+        if start > last {
+            spans.push(Span::raw(line[last..start].to_string()));
+        }
+        spans.push(Span::styled(
+            line[start..end].to_string(),
+            Style::default().add_modifier(Modifier::REVERSED),
+        ));
+        last = end;
+    }
+    if last < line.len() || spans.is_empty() {
+        spans.push(Span::raw(line[last..].to_string()));
+    }
+    spans
+}
+
+/// Render image viewer as a centered popup overlay
+fn render_image_viewer(f: &mut Frame, app: &mut App) {
+    let area = f.area();
+    // Create a centered popup that takes 80% of the screen
+    let popup_width = (area.width as f32 * 0.8) as u16;
+    let popup_height = (area.height as f32 * 0.8) as u16;
+    let popup_x = (area.width - popup_width) / 2;
+    let popup_y = (area.height - popup_height) / 2;
+
+    let popup_area = Rect::new(popup_x, popup_y, popup_width, popup_height);
+
+    // Clear the popup area first
+    f.render_widget(Clear, popup_area);
+
+    // Get image name for title
+    let title = if let Some(ref img) = app.viewing_image {
+        let nav_hint = if app.viewable_images.len() > 1 {
+            format!(
+                " ({}/{}) - ←/→ to navigate, ESC to close, 'o' to open externally, 'c' to copy link",
+                app.selected_image_index + 1,
+                app.viewable_images.len()
+            )
+        } else {
+            " - ESC to close, 'o' to open externally, 'c' to copy link".to_string()
+        };
+        // Protocol and resolution explain why e.g. a halfblock render looks blocky
+        let mut details = String::new();
+        if let Some(picker) = app.image_picker.as_ref() {
+            details.push_str(&format!(" — {}", picker.protocol_name()));
+        }
+        if let Some((w, h)) = img.dimensions {
+            details.push_str(&format!(" — {}×{}", w, h));
+        }
+        if img.full_url.is_some() {
+            details.push_str(if img.full_resolution {
+                " — full size"
+            } else {
+                " — preview, f for full size"
+            });
+        }
+        if !app.zoom_fit {
+            details.push_str(" — actual size (arrows pan, z to fit)");
+        }
+        format!("Image: {}{}{}", img.name, details, nav_hint)
+    } else {
+        "Image Viewer - ESC to close, 'o' to open externally".to_string()
+    };
 
     // Create the block for the popup
     let block = Block::default()
@@ -589,6 +1898,7 @@ fn render_image_viewer(f: &mut Frame, app: &mut App) {
 
     // Get the inner area for the image
     let inner_area = block.inner(popup_area);
+    app.set_image_viewport(inner_area);
 
     // Render the block
     f.render_widget(block, popup_area);
@@ -604,7 +1914,13 @@ fn render_image_viewer(f: &mut Frame, app: &mut App) {
         // Show protocol info if not graphics
         if let Some(picker) = app.image_picker.as_ref() {
             if !picker.supports_graphics() {
-                let msg = Paragraph::new("⚠ Image display is limited: your terminal does not support graphics protocols. Showing Unicode fallback.")
+                let dimensions = app
+                    .viewing_image
+                    .as_ref()
+                    .and_then(|img| img.dimensions)
+                    .map(|(w, h)| format!(" of a {}×{} image", w, h))
+                    .unwrap_or_default();
+                let msg = Paragraph::new(format!("⚠ Image display is limited: your terminal does not support graphics protocols. Showing Unicode fallback{}.", dimensions))
                     .style(Style::default().fg(Color::Yellow));
                 // Render message at bottom of popup
                 let msg_area = Rect {
@@ -626,3 +1942,118 @@ fn render_image_viewer(f: &mut Frame, app: &mut App) {
         f.render_widget(msg, inner_area);
     }
 }
+
+/// Short "time since" label for the chat list: "now", "5m", "3h", "Tue", "12 Mar"
+fn relative_time(
+    then: chrono::DateTime<chrono::Utc>,
+    now: chrono::DateTime<chrono::Utc>,
+    zone: DisplayZone,
+) -> String {
+    let elapsed = now.signed_duration_since(then);
+    if elapsed.num_minutes() < 1 {
+        "now".to_string()
+    } else if elapsed.num_hours() < 1 {
+        format!("{}m", elapsed.num_minutes())
+    } else if elapsed.num_days() < 1 {
+        format!("{}h", elapsed.num_hours())
+    } else if elapsed.num_days() < 7 {
+        zone.format(&then, "%a")
+    } else {
+        zone.format(&then, "%-d %b")
+    }
+}
+
+/// Cut `text` to at most `max_width` display columns, ending with "…" if shortened
+///
+/// Cuts fall between grapheme clusters, so accented letters and emoji sequences
+/// are never split apart.
+fn truncate_to_width(text: &str, max_width: usize) -> String {
+    if text.width() <= max_width {
+        return text.to_string();
+    }
+    let mut truncated = String::new();
+    let mut width = 0;
+    for grapheme in text.graphemes(true) {
+        let grapheme_width = grapheme.width();
+        if width + grapheme_width + 1 > max_width {
+            break;
+        }
+        truncated.push_str(grapheme);
+        width += grapheme_width;
+    }
+    if max_width > 0 {
+        truncated.push('…');
+    }
+    truncated
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_prompt_cursor_column_counts_cells() {
+        let area = Rect::new(0, 0, 40, 3);
+        assert_eq!(prompt_cursor_column("hi", area), 2);
+        // "é" is two bytes but one cell; "👍" is four bytes but two cells
+        assert_eq!(prompt_cursor_column("café", area), 4);
+        assert_eq!(prompt_cursor_column("ok 👍", area), 5);
+        assert_eq!(prompt_cursor_column("👨‍👩‍👧", area), 2);
+        // Never past the right border
+        assert_eq!(prompt_cursor_column(&"👍".repeat(50), area), 37);
+    }
+
+    #[test]
+    fn test_quote_lines_collapse_until_expanded() {
+        let text = "one\ntwo\nthree\nfour";
+        let collapsed = quote_lines(text, 40, 2, false);
+        assert_eq!(
+            collapsed
+                .iter()
+                .map(|(l, _)| l.as_str())
+                .collect::<Vec<_>>(),
+            vec!["│ one", "│ two", "│ [+ 2 more quoted lines]"]
+        );
+        assert_eq!(quote_lines(text, 40, 2, true).len(), 4);
+        assert_eq!(quote_lines(text, 40, 0, false).len(), 4);
+        assert!(collapsed.iter().all(|(_, kind)| *kind == LineKind::Quote));
+    }
+
+    #[test]
+    fn test_emoji_lines_align_right() {
+        let text = "ship it 👍🏽 🎉🎉 👨‍👩‍👧 ❤️ done";
+        let lines = wrap_text(text, 10);
+        assert!(lines.iter().all(|line| line.width() <= 10), "{:?}", lines);
+        // Two cells per emoji however many codepoints, not their byte length
+        assert_eq!(lines, vec!["ship it 👍🏽", "🎉🎉 👨‍👩‍👧 ❤️", "done"]);
+        for line in lines {
+            let aligned = right_aligned(vec![Span::raw(line)], 30);
+            assert_eq!(aligned.width(), 30);
+        }
+    }
+
+    #[test]
+    fn test_truncate_to_width_multibyte_names() {
+        assert_eq!(truncate_to_width("Zoë Brontë", 20), "Zoë Brontë");
+        assert_eq!(truncate_to_width("Zoë Brontë", 5), "Zoë …");
+        // Combining accents stay with their letter
+        assert_eq!(
+            truncate_to_width("Zoe\u{308} Bronte\u{308}", 4),
+            "Zoe\u{308}…"
+        );
+        // Wide characters count two columns and are never half-cut
+        assert_eq!(truncate_to_width("山田太郎", 6), "山田…");
+        assert_eq!(truncate_to_width("山田太郎", 4), "山…");
+        assert_eq!(truncate_to_width("👩‍💻 Team", 4), "👩‍💻 …");
+        assert!(truncate_to_width("山田太郎さん", 7).width() <= 7);
+    }
+
+    #[test]
+    fn test_initials_and_badge_color() {
+        assert_eq!(initials("Bob Smith"), "BS");
+        assert_eq!(initials("mary ann de la cruz"), "MC");
+        assert_eq!(initials("Cher"), "C");
+        assert_eq!(initials("  "), "?");
+        assert_eq!(name_color("Bob Smith"), name_color("Bob Smith"));
+    }
+}